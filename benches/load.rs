@@ -0,0 +1,55 @@
+//! Times adding a large resource to a Locale, from the source text to the
+//! first entity localized, and parsing it on its own for comparison.
+//!
+//! Run with `cargo bench --bench load`.
+
+#![allow(deprecated)]
+
+extern crate l20n;
+
+use std::time::{Duration, Instant};
+
+const ENTRIES: usize = 16_800;
+const PASSES: usize = 5;
+
+/// A resource of about 7.5 MB, with the entities, hashes, attributes, macros
+/// and comments a real one has.
+fn resource() -> String {
+    let mut source = String::from("<brand 'Rust' name: 'Rust Lang'>\n<plural($n) { $n == 1 ? 'one' : 'other' }>\n");
+    for i in 0..ENTRIES {
+        source.push_str(&format!("\
+/* Shown on page {i}, where the user can read their messages and
+   the ones sent to the groups they are in. */
+<greeting{i} \"Hello, {{{{ $user }}}}! Welcome back to {{{{ brand::name }}}}, page {i}.\">
+<messages{i}[plural($n)] {{
+  one: \"You have one new message in {{{{ brand }}}}, waiting on page {i}.\",
+ *other: \"You have {{{{ $n }}}} new messages in {{{{ brand }}}}, waiting on page {i}.\"
+}} title: \"Messages on page {i}\">
+<double{i}($n) {{ $n * 2 + {i} }}>
+"));
+    }
+    source
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn main() {
+    let source = resource();
+    println!("resource: {:.1} MB, {} entries", source.len() as f64 / 1e6, ENTRIES * 3 + 2);
+
+    for pass in 0..PASSES {
+        let parse = time(|| {
+            l20n::parse(&source).unwrap();
+        });
+        let mut locale = l20n::Locale::new();
+        let add = time(|| locale.add_resource(&source).unwrap());
+        let localize = time(|| {
+            locale.localize_entity("brand", ()).unwrap();
+        });
+        println!("pass {}: parse {:?}, add_resource {:?}, first localize {:?}", pass + 1, parse, add, localize);
+    }
+}
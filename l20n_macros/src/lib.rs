@@ -0,0 +1,381 @@
+#![allow(deprecated)]
+//! Compile-time checked message lookups for l20n.
+//!
+//! `l20n!(locale, "path/to/resource.l20n", "id", var = value, ...)` reads
+//! the resource while compiling, and fails the build if it has no entity
+//! `id`, or if the variables given are not exactly the `$vars` the entity
+//! uses. The path is relative to the crate's `Cargo.toml`.
+//!
+//! At runtime it expands to `locale.localize_entity("id", args)`, so it
+//! returns an `l20n::LocalizeResult<String>`. The resource is not embedded:
+//! `locale` still needs it added, the macro only checks against it.
+//!
+//! Each value is passed as the `l20n::Arg` the variable is used as, so it
+//! has to be an `i64` for a number, a `bool` for a condition, and a `&str`
+//! otherwise.
+//!
+//! # Example
+//!
+//! ```rust
+//! # #![allow(deprecated)]
+//! #[macro_use]
+//! extern crate l20n_macros;
+//! extern crate l20n;
+//!
+//! fn main() {
+//!     let mut locale = l20n::Locale::new();
+//!     locale.add_resource(include_str!("../locales/example.l20n")).unwrap();
+//!
+//!     let s = l20n!(locale, "locales/example.l20n", "factorial", number = 3).unwrap();
+//!     assert_eq!(s, "Factorial of 3 is 6.");
+//!     let s = l20n!(locale, "locales/example.l20n", "brand").unwrap();
+//!     assert_eq!(s, "Firefox");
+//!
+//!     // a comma between generic arguments does not end the value
+//!     let s = l20n!(locale, "locales/example.l20n", "factorial",
+//!                   number = ::std::collections::HashMap::<&str, i64>::new().len() as i64 + 3).unwrap();
+//!     assert_eq!(s, "Factorial of 3 is 6.");
+//! }
+//! ```
+//!
+//! An unknown id does not compile:
+//!
+//! ```rust,compile_fail
+//! # #![allow(deprecated)]
+//! # #[macro_use] extern crate l20n_macros;
+//! # extern crate l20n;
+//! # fn main() {
+//! # let locale = l20n::Locale::new();
+//! l20n!(locale, "locales/example.l20n", "factorail", number = 3);
+//! # }
+//! ```
+//!
+//! Nor does a missing variable:
+//!
+//! ```rust,compile_fail
+//! # #![allow(deprecated)]
+//! # #[macro_use] extern crate l20n_macros;
+//! # extern crate l20n;
+//! # fn main() {
+//! # let locale = l20n::Locale::new();
+//! l20n!(locale, "locales/example.l20n", "greeting");
+//! # }
+//! ```
+
+#![deny(missing_docs)]
+#![cfg_attr(test, deny(warnings))]
+
+extern crate l20n;
+extern crate proc_macro;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use l20n::build::{self, ArgType, BuildError, Signature};
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Looks up an entity, checking the id and variables against a resource
+/// at compile time.
+#[proc_macro]
+pub fn l20n(input: TokenStream) -> TokenStream {
+    match expand(input) {
+        Ok(tokens) => tokens,
+        Err((span, msg)) => compile_error(span, &msg),
+    }
+}
+
+type Result<T> = ::std::result::Result<T, (Span, String)>;
+
+fn expand(input: TokenStream) -> Result<TokenStream> {
+    let mut args = split(input).into_iter();
+    let locale = match args.next() {
+        Some(locale) => locale,
+        None => return Err((Span::call_site(), String::from("expected a locale, a resource path and an id"))),
+    };
+    let (path, path_span) = string(args.next(), "a resource path")?;
+    let (id, id_span) = string(args.next(), "an entity id")?;
+
+    let mut vars = vec![];
+    for arg in args {
+        let mut tokens = arg.into_iter();
+        let (name, span) = match (tokens.next(), tokens.next()) {
+            (Some(TokenTree::Ident(name)), Some(TokenTree::Punct(ref eq))) if eq.as_char() == '=' => {
+                let s = name.to_string();
+                (s.trim_start_matches("r#").to_string(), name.span())
+            }
+            (tt, _) => {
+                let span = tt.map_or(Span::call_site(), |tt| tt.span());
+                return Err((span, String::from("expected `name = value`")));
+            }
+        };
+        let value: TokenStream = tokens.collect();
+        if value.is_empty() {
+            return Err((span, format!("expected a value for `{}`", name)));
+        }
+        vars.push((name, span, value));
+    }
+
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| String::from("."));
+    let full = PathBuf::from(dir).join(&path);
+    let source = fs::read_to_string(&full)
+        .map_err(|e| (path_span, format!("could not read `{}`: {}", full.display(), e)))?;
+    let signatures = build::signatures(&[(&full, source)]).map_err(|e| match e {
+        BuildError::Parse(_, e) => {
+            (path_span, format!("`{}` is not valid L20n: {:?} at line {}, column {}", path, e.kind, e.line + 1, e.col))
+        }
+        BuildError::Io(_, e) => (path_span, e.to_string()),
+        BuildError::Lint(lints) => {
+            let lints: Vec<String> = lints.iter().map(ToString::to_string).collect();
+            (path_span, format!("`{}` has errors: {}", path, lints.join("; ")))
+        }
+    })?;
+    let sig = match signatures.iter().find(|sig| sig.id == id) {
+        Some(sig) => sig,
+        None => return Err((id_span, format!("no entity `{}` in `{}`", id, path))),
+    };
+    let names: Vec<(&str, Span)> = vars.iter().map(|&(ref name, span, _)| (&name[..], span)).collect();
+    check(sig, &names).map_err(|(span, msg)| (span.unwrap_or(id_span), msg))?;
+
+    // [("name", ::l20n::Arg::Num(value)), ...]
+    let mut pairs = TokenStream::new();
+    for (name, _, value) in vars {
+        let ty = sig.params.iter().find(|param| param.0 == name).unwrap().1;
+        let variant = match ty {
+            ArgType::Str => "Str",
+            ArgType::Num => "Num",
+            ArgType::Bool => "Bool",
+        };
+        let mut pair = TokenStream::new();
+        pair.extend(vec![
+            TokenTree::Literal(Literal::string(&name)),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        ]);
+        pair.extend(tokens(&format!("::l20n::Arg::{}", variant)));
+        pair.extend(Some(TokenTree::Group(Group::new(Delimiter::Parenthesis, value))));
+        pairs.extend(vec![
+            TokenTree::Group(Group::new(Delimiter::Parenthesis, pair)),
+            TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        ]);
+    }
+
+    // {
+    //     const _: &[u8] = include_bytes!("/full/path");
+    //     (locale).localize_entity("id", [...].iter().cloned().collect::<HashMap<_, _>>())
+    // }
+    let mut block = tokens("const _: &[u8] = include_bytes!");
+    block.extend(Some(TokenTree::Group(Group::new(
+        Delimiter::Parenthesis,
+        Some(TokenTree::Literal(Literal::string(&full.to_string_lossy()))).into_iter().collect(),
+    ))));
+    block.extend(tokens(";"));
+    block.extend(Some(TokenTree::Group(Group::new(Delimiter::Parenthesis, locale.into_iter().collect()))));
+    block.extend(tokens(".localize_entity"));
+    let mut call = TokenStream::new();
+    call.extend(vec![
+        TokenTree::Literal(Literal::string(&id)),
+        TokenTree::Punct(Punct::new(',', Spacing::Alone)),
+        TokenTree::Group(Group::new(Delimiter::Bracket, pairs)),
+    ]);
+    call.extend(tokens(".iter().cloned().collect::<::std::collections::HashMap<&str, ::l20n::Arg>>()"));
+    block.extend(Some(TokenTree::Group(Group::new(Delimiter::Parenthesis, call))));
+    Ok(Some(TokenTree::Group(Group::new(Delimiter::Brace, block))).into_iter().collect())
+}
+
+/// Checks that the variables given are exactly the ones the entity uses,
+/// returning the span to report an error at, if it is not the id's.
+fn check<S: Copy>(sig: &Signature, names: &[(&str, S)]) -> ::std::result::Result<(), (Option<S>, String)> {
+    for (i, &(name, span)) in names.iter().enumerate() {
+        if names[..i].iter().any(|&(prev, _)| prev == name) {
+            return Err((Some(span), format!("`{}` is given more than once", name)));
+        }
+        if !sig.params.iter().any(|param| param.0 == name) {
+            return Err((Some(span), format!("`{}` does not use `${}`", sig.id, name)));
+        }
+    }
+    let missing: Vec<String> = sig.params.iter()
+        .filter(|param| !names.iter().any(|&(name, _)| name == param.0))
+        .map(|param| format!("`{}`", param.0))
+        .collect();
+    if !missing.is_empty() {
+        return Err((None, format!("`{}` needs a value for {}", sig.id, missing.join(", "))));
+    }
+    Ok(())
+}
+
+/// Splits the input at its top level commas, dropping a trailing one.
+///
+/// A comma between generic arguments, as in `HashMap::<K, V>::new()`, is
+/// not at the top level. A `<` opens generic arguments where Rust reads it
+/// that way: after `::`, in a type after `as`, `:` or `->`, and at the start
+/// of a value, for `<T as Trait>::f()`. Anywhere else it is a comparison.
+fn split(input: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut args = vec![vec![]];
+    let mut depth = 0;
+    // whether a `<` here would open generic arguments
+    let mut start = true;
+    let mut in_type = false;
+    let mut after_path_sep = false;
+    // the previous token, if it is a punct joined to this one
+    let mut joint = None;
+    for tt in input {
+        let generic = start || in_type || after_path_sep;
+        start = false;
+        after_path_sep = false;
+        let mut next_joint = None;
+        if let TokenTree::Punct(ref p) = tt {
+            match p.as_char() {
+                ',' if depth == 0 => {
+                    args.push(vec![]);
+                    start = true;
+                    in_type = false;
+                    joint = None;
+                    continue;
+                }
+                '<' if depth > 0 || generic => depth += 1,
+                '>' if joint == Some('-') => in_type = true,
+                '>' if depth > 0 && joint != Some('=') => depth -= 1,
+                ':' if joint == Some(':') => {
+                    after_path_sep = true;
+                    in_type = generic;
+                }
+                ':' if p.spacing() == Spacing::Alone => in_type = true,
+                ':' => in_type = generic,
+                '=' if p.spacing() == Spacing::Alone && joint.is_none() => start = true,
+                _ => in_type = false,
+            }
+            if p.spacing() == Spacing::Joint {
+                next_joint = Some(p.as_char());
+            }
+        } else if let TokenTree::Ident(ref ident) = tt {
+            if ident.to_string() == "as" {
+                in_type = true;
+            }
+        } else {
+            in_type = false;
+        }
+        joint = next_joint;
+        args.last_mut().unwrap().push(tt);
+    }
+    if args.last().is_some_and(|arg| arg.is_empty()) {
+        args.pop();
+    }
+    args
+}
+
+fn string(arg: Option<Vec<TokenTree>>, what: &str) -> Result<(String, Span)> {
+    let arg = arg.unwrap_or_default();
+    let span = arg.first().map_or(Span::call_site(), |tt| tt.span());
+    match arg.first() {
+        Some(TokenTree::Literal(lit)) if arg.len() == 1 => {
+            match unquote(&lit.to_string()) {
+                Some(s) => Ok((s, span)),
+                None => Err((span, format!("expected {} as a string literal", what))),
+            }
+        }
+        _ => Err((span, format!("expected {} as a string literal", what))),
+    }
+}
+
+/// The contents of a string literal, as written in the source.
+fn unquote(lit: &str) -> Option<String> {
+    if let Some(raw) = lit.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let inner = &raw[hashes..raw.len() - hashes];
+        return inner.strip_prefix('"').and_then(|s| s.strip_suffix('"')).map(String::from);
+    }
+    let inner = lit.strip_prefix('"').and_then(|s| s.strip_suffix('"'))?;
+    let mut s = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => s.push('\n'),
+            'r' => s.push('\r'),
+            't' => s.push('\t'),
+            '0' => s.push('\0'),
+            c @ ('\\' | '"' | '\'') => s.push(c),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if hex.len() == 2 && b < 0x80 => s.push(b as char),
+                    _ => return None,
+                }
+            }
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let end = rest.find('}')?;
+                let hex = rest[..end].replace('_', "");
+                if hex.is_empty() || hex.len() > 6 {
+                    return None;
+                }
+                s.push(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)?);
+                chars = rest[end + 1..].chars();
+            }
+            // a line continuation, which skips the whitespace after it
+            '\n' => chars = chars.as_str().trim_start().chars(),
+            _ => return None,
+        }
+    }
+    Some(s)
+}
+
+fn tokens(s: &str) -> TokenStream {
+    s.parse().unwrap()
+}
+
+fn compile_error(span: Span, msg: &str) -> TokenStream {
+    let mut lit = Literal::string(msg);
+    lit.set_span(span);
+    let mut args = Group::new(Delimiter::Parenthesis, Some(TokenTree::Literal(lit)).into_iter().collect());
+    args.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    vec![
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(args),
+    ].into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use l20n::build::{ArgType, Signature};
+
+    use super::{check, unquote};
+
+    #[test]
+    fn test_check() {
+        let sig = Signature {
+            id: String::from("hi"),
+            note: None,
+            params: vec![
+                (String::from("admin"), ArgType::Bool),
+                (String::from("name"), ArgType::Str),
+            ],
+        };
+        assert!(check(&sig, &[("name", 1), ("admin", 2)]).is_ok());
+        assert_eq!(check(&sig, &[("name", 1), ("admin", 2), ("name", 3)]),
+                   Err((Some(3), String::from("`name` is given more than once"))));
+        assert_eq!(check(&sig, &[("name", 1), ("other", 2)]),
+                   Err((Some(2), String::from("`hi` does not use `$other`"))));
+        assert_eq!(check::<i32>(&sig, &[]),
+                   Err((None, String::from("`hi` needs a value for `admin`, `name`"))));
+    }
+
+    #[test]
+    fn test_unquote() {
+        assert_eq!(unquote(r#""a\"b\\c\n""#), Some(String::from("a\"b\\c\n")));
+        assert_eq!(unquote(r###"r#"a"b"#"###), Some(String::from("a\"b")));
+        assert_eq!(unquote(r#""\x41\u{e9}\u{1F_600}""#), Some(String::from("A\u{e9}\u{1f600}")));
+        assert_eq!(unquote("\"a\\\n    b\""), Some(String::from("ab")));
+        assert_eq!(unquote(r#""\x80""#), None);
+        assert_eq!(unquote(r#""\u{d800}""#), None);
+        assert_eq!(unquote(r#""\u{}""#), None);
+        assert_eq!(unquote("b\"bytes\""), None);
+        assert_eq!(unquote("42"), None);
+    }
+}
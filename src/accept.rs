@@ -0,0 +1,219 @@
+use std::cmp::Reverse;
+use std::ptr;
+use std::slice;
+
+use context::Locale;
+use langtag::{negotiate, LanguageTag, NegotiationStrategy};
+
+/// A language range of an `Accept-Language` header.
+#[derive(Debug, PartialEq, Clone)]
+pub enum LanguageRange {
+    /// A language, along with its more specific forms: `en` also accepts
+    /// `en-US`.
+    Tag(LanguageTag),
+    /// `*`, any language not named otherwise.
+    Any,
+}
+
+/// Parses an `Accept-Language` header value into the ranges it accepts,
+/// the most preferred first.
+///
+/// Ranges are ordered by their `q` weight, and keep their order in the
+/// header when weights are equal. Ranges with `q=0`, which are not
+/// acceptable, and malformed ones are left out, so that a broken header
+/// gives as much as can be made of it rather than an error.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::{parse_accept_language, LanguageRange};
+///
+/// let ranges = parse_accept_language("fr;q=0.5, de-CH, *;q=0.1, en;q=0.8");
+/// assert_eq!(ranges, vec![
+///     LanguageRange::Tag("de-CH".parse().unwrap()),
+///     LanguageRange::Tag("en".parse().unwrap()),
+///     LanguageRange::Tag("fr".parse().unwrap()),
+///     LanguageRange::Any,
+/// ]);
+/// ```
+pub fn parse_accept_language(header: &str) -> Vec<LanguageRange> {
+    weighted(header).into_iter()
+        .filter(|&(_, q)| q > 0)
+        .map(|(range, _)| range)
+        .collect()
+}
+
+/// Picks the Locales to try for a request, in order, from its
+/// `Accept-Language` header.
+///
+/// Each range is negotiated against the languages of `available` with
+/// `NegotiationStrategy::Filtering`, and `*` adds the Locales not picked
+/// yet, except those ruled out with `q=0`. Locales without a language are
+/// never picked. `default`, if given, comes last.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::{locales_for_request, Locale};
+///
+/// let available: Vec<Locale> = ["en-US", "de", "fr"].iter()
+///     .map(|tag| Locale::with_language(tag.parse().unwrap()))
+///     .collect();
+/// let chain = locales_for_request("de-AT, en;q=0.5", &available, Some(&available[0]));
+/// let tags: Vec<String> = chain.iter().map(|l| l.language().unwrap().to_string()).collect();
+/// assert_eq!(tags, vec!["de", "en-US"]);
+/// ```
+pub fn locales_for_request<'a>(accept_language: &str,
+                               available: &'a [Locale],
+                               default: Option<&'a Locale>) -> Vec<&'a Locale> {
+    let ranges = weighted(accept_language);
+    let excluded: Vec<&LanguageTag> = ranges.iter().filter_map(|&(ref range, q)| match *range {
+        LanguageRange::Tag(ref tag) if q == 0 => Some(tag),
+        _ => None,
+    }).collect();
+
+    let mut chosen: Vec<&'a Locale> = vec![];
+    for (range, q) in &ranges {
+        if *q == 0 {
+            continue;
+        }
+        let remaining: Vec<&'a Locale> = available.iter()
+            .filter(|l| l.language().is_some() && !chosen.iter().any(|c| same(c, l)))
+            .collect();
+        match *range {
+            LanguageRange::Tag(ref tag) => {
+                let tags: Vec<LanguageTag> = remaining.iter().map(|l| l.language().unwrap().clone()).collect();
+                let picked = negotiate(slice::from_ref(tag), &tags, None, NegotiationStrategy::Filtering);
+                for tag in picked {
+                    let i = tags.iter().position(|t| *t == tag).unwrap();
+                    chosen.push(remaining[i]);
+                }
+            }
+            LanguageRange::Any => {
+                chosen.extend(remaining.into_iter().filter(|l| {
+                    let language = l.language().unwrap();
+                    !excluded.iter().any(|ex| matches!(ex.distance(language), Some(0) | Some(2)))
+                }));
+            }
+        }
+    }
+    if let Some(default) = default {
+        if !chosen.iter().any(|c| same(c, default)) {
+            chosen.push(default);
+        }
+    }
+    chosen
+}
+
+fn same(a: &Locale, b: &Locale) -> bool {
+    ptr::eq(a, b)
+}
+
+/// The well formed ranges with their weights in thousandths, sorted by
+/// weight, keeping only the first of each range.
+fn weighted(header: &str) -> Vec<(LanguageRange, u16)> {
+    let mut ranges: Vec<(LanguageRange, u16)> = vec![];
+    for item in header.split(',') {
+        let mut parts = item.split(';');
+        let range = match parts.next().map(str::trim) {
+            Some("") | None => continue,
+            Some("*") => LanguageRange::Any,
+            Some(tag) => match LanguageTag::parse(tag) {
+                Ok(tag) => LanguageRange::Tag(tag),
+                Err(_) => continue,
+            },
+        };
+        let mut q = Some(1000);
+        for param in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let mut kv = param.splitn(2, '=');
+            let key = kv.next().unwrap().trim();
+            if key.eq_ignore_ascii_case("q") {
+                q = kv.next().and_then(|v| qvalue(v.trim()));
+            }
+        }
+        let q = match q {
+            Some(q) => q,
+            None => continue,
+        };
+        if !ranges.iter().any(|(r, _)| *r == range) {
+            ranges.push((range, q));
+        }
+    }
+    // stable, so equal weights stay in the order they were given
+    ranges.sort_by_key(|&(_, q)| Reverse(q));
+    ranges
+}
+
+/// Parses a weight, `0` to `1` with at most three decimals, into
+/// thousandths.
+fn qvalue(s: &str) -> Option<u16> {
+    let (int, frac) = match s.find('.') {
+        Some(dot) => (&s[..dot], &s[dot + 1..]),
+        None => (s, ""),
+    };
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let thousandths = format!("{:0<3}", frac).parse::<u16>().ok()?;
+    match int {
+        "0" => Some(thousandths),
+        "1" if thousandths == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use context::Locale;
+    use super::{locales_for_request, parse_accept_language, qvalue, LanguageRange};
+
+    fn ranges(header: &str) -> Vec<String> {
+        parse_accept_language(header).into_iter().map(|r| match r {
+            LanguageRange::Tag(tag) => tag.to_string(),
+            LanguageRange::Any => String::from("*"),
+        }).collect()
+    }
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(ranges("da, en-gb;q=0.8, en;q=0.7"), vec!["da", "en-GB", "en"]);
+        assert_eq!(ranges("en;q=0.5,fr;q=0.5,de"), vec!["de", "en", "fr"]);
+        assert_eq!(ranges(" en ; Q = 0.2 ,  fr ;q=1.000"), vec!["fr", "en"]);
+        assert_eq!(ranges("*;q=0.1, de;q=0"), vec!["*"]);
+        assert_eq!(ranges("en, en;q=0.1, EN-us"), vec!["en", "en-US"]);
+        assert_eq!(ranges(""), Vec::<String>::new());
+
+        // malformed ranges are skipped, the rest is kept
+        assert_eq!(ranges(",,en-US;;, 12-34, fr;q=2, de;q=0.1234, it;q=, es;q=abc, nl;level=1"),
+                   vec!["en-US", "nl"]);
+        assert_eq!(ranges("\u{fffd}\u{0}, pt-BR"), vec!["pt-BR"]);
+    }
+
+    #[test]
+    fn test_qvalue() {
+        assert_eq!(qvalue("1"), Some(1000));
+        assert_eq!(qvalue("0.5"), Some(500));
+        assert_eq!(qvalue("0.125"), Some(125));
+        assert_eq!(qvalue("0."), Some(0));
+        assert_eq!(qvalue("1.001"), None);
+        assert_eq!(qvalue(".5"), None);
+        assert_eq!(qvalue("-0"), None);
+    }
+
+    #[test]
+    fn test_locales_for_request() {
+        let mut available: Vec<Locale> = ["en-US", "en-GB", "fr-CA", "fr", "de"].iter()
+            .map(|tag| Locale::with_language(tag.parse().unwrap()))
+            .collect();
+        available.push(Locale::new());
+        let chain = |header: &str, default: Option<&Locale>| -> Vec<String> {
+            locales_for_request(header, &available, default).iter()
+                .map(|l| l.language().map_or(String::from("?"), |t| t.to_string()))
+                .collect()
+        };
+
+        assert_eq!(chain("fr-FR, en-GB;q=0.5", None), vec!["fr", "fr-CA", "en-GB", "en-US"]);
+        assert_eq!(chain("de, *;q=0.5, fr;q=0", None), vec!["de", "en-US", "en-GB"]);
+        assert_eq!(chain("it", Some(&available[0])), vec!["en-US"]);
+        assert_eq!(chain("it", Some(&available[5])), vec!["?"]);
+        assert_eq!(chain("garbage;;;", None), Vec::<String>::new());
+    }
+}
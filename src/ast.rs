@@ -0,0 +1,218 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The syntax tree of an L20n resource, as returned by `parse`.
+//!
+//! Names and text in the tree borrow from the source where they can, so
+//! parsing does not copy the resource. A string is only owned when it had to
+//! be changed, such as a `\` being dropped. `into_owned` converts a tree into
+//! one that no longer borrows from the source, to keep it around after the
+//! source is gone.
+//!
+//! # Stability
+//!
+//! These types follow the crate's version number like the rest of the public
+//! API. Changing or removing a variant or field is a breaking change. New
+//! variants may be added as the grammar grows; while the crate is before
+//! 1.0, that only happens in a new minor version (0.x), so tools that match
+//! exhaustively will not break on a patch update. Matching with a wildcard
+//! arm keeps a tool compiling across those updates as well.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+pub use self::Entry::*;
+pub use self::Value::*;
+pub use self::AccessType::*;
+pub use self::Expr::*;
+pub use self::BinOp::*;
+pub use self::UnOp::*;
+
+/// A top-level item in an L20n resource.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Entry<'a> {
+    /// `<id[indices] value attrs>`: a translatable string, with its id, its
+    /// value, the index used to pick from a Hash value, and its attributes.
+    Entity(Cow<'a, str>, Value<'a>, Vec<Expr<'a>>, Vec<Attr<'a>>),
+    /// `<id($args) { body }>`: a macro, with its id, its arguments (always
+    /// `VarExpr`s), and its body.
+    Macro(Cow<'a, str>, Vec<Expr<'a>>, Expr<'a>),
+    /// `/* text */`: a comment.
+    Comment(Cow<'a, str>),
+    /// `import('path')`: another resource to add before this one, by its
+    /// path relative to this one.
+    Import(Cow<'a, str>),
+}
+
+/// The value of an entity or attribute.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value<'a> {
+    /// A string without any `{{ }}` placeables.
+    Str(Cow<'a, str>),
+    /// A string with placeables, split into its pieces. Literal pieces are
+    /// `ValExpr(Str(..))`.
+    ComplexStr(Vec<Expr<'a>>),
+    /// `{ key: value, ... }`: values keyed by name, with the key marked with
+    /// `*` as the default, and the index expression from the entity (filled
+    /// in when the resource is added to a `Locale`).
+    Hash(HashMap<Cow<'a, str>, Value<'a>>, Option<Cow<'a, str>>, Option<Box<Expr<'a>>>)
+}
+
+/// How a property or attribute is accessed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AccessType {
+    /// `foo[expr]` or `foo::[expr]`, where the name is computed.
+    Computed,
+    /// `foo.bar` or `foo::bar`, where the name is written out.
+    Static,
+}
+
+/// An expression, used in placeables, indices and macro bodies.
+#[derive(Debug, PartialEq, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum Expr<'a> {
+    /// `cond ? consequent : alternate`
+    CondExpr(Box<Expr<'a>>, Box<Expr<'a>>, Box<Expr<'a>>),
+    /// `left op right`
+    BinExpr(Box<Expr<'a>>, BinOp, Box<Expr<'a>>),
+    /// `op expr`
+    UnExpr(UnOp, Box<Expr<'a>>),
+    /// `$name`, a variable from the localization data or a macro argument.
+    VarExpr(Cow<'a, str>),
+    /// A literal string or Hash.
+    ValExpr(Value<'a>),
+    /// `parent.prop` or `parent[prop]`
+    PropExpr(Box<Expr<'a>>, Box<Expr<'a>>, AccessType),
+    /// `parent::attr` or `parent::[attr]`
+    AttrExpr(Box<Expr<'a>>, Box<Expr<'a>>, AccessType),
+    /// `callee(args)`, calling a macro.
+    CallExpr(Box<Expr<'a>>, Vec<Expr<'a>>),
+    /// `name`, a reference to another entity or macro.
+    IdentExpr(Cow<'a, str>),
+    /// A literal integer.
+    NumExpr(i64),
+    /// `(expr)`
+    ParenExpr(Box<Expr<'a>>),
+    /// `@name`, a global.
+    GlobalExpr(Cow<'a, str>),
+    /// `~`, the current entity.
+    ThisExpr,
+}
+
+/// `name[indices]: value`, an attribute of an entity.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Attr<'a>(pub Cow<'a, str>, pub Value<'a>, pub Vec<Expr<'a>>);
+
+fn owned(s: Cow<str>) -> Cow<'static, str> {
+    Cow::Owned(s.into_owned())
+}
+
+fn owned_exprs(exprs: Vec<Expr>) -> Vec<Expr<'static>> {
+    exprs.into_iter().map(Expr::into_owned).collect()
+}
+
+impl<'a> Entry<'a> {
+    /// Copies anything borrowed from the source, so the entry can outlive it.
+    pub fn into_owned(self) -> Entry<'static> {
+        match self {
+            Entity(id, value, indices, attrs) => {
+                Entity(owned(id), value.into_owned(), owned_exprs(indices),
+                       attrs.into_iter().map(Attr::into_owned).collect())
+            }
+            Macro(id, args, body) => Macro(owned(id), owned_exprs(args), body.into_owned()),
+            Comment(text) => Comment(owned(text)),
+            Import(path) => Import(owned(path)),
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    /// Copies anything borrowed from the source, so the value can outlive it.
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Str(s) => Str(owned(s)),
+            ComplexStr(exprs) => ComplexStr(owned_exprs(exprs)),
+            Hash(map, def_key, def_index) => {
+                Hash(map.into_iter().map(|(k, v)| (owned(k), v.into_owned())).collect(),
+                     def_key.map(owned),
+                     def_index.map(|index| Box::new(index.into_owned())))
+            }
+        }
+    }
+}
+
+impl<'a> Expr<'a> {
+    /// Copies anything borrowed from the source, so the expression can
+    /// outlive it.
+    pub fn into_owned(self) -> Expr<'static> {
+        let boxed = |expr: Box<Expr>| Box::new(expr.into_owned());
+        match self {
+            CondExpr(cond, consequent, alt) => CondExpr(boxed(cond), boxed(consequent), boxed(alt)),
+            BinExpr(left, op, right) => BinExpr(boxed(left), op, boxed(right)),
+            UnExpr(op, expr) => UnExpr(op, boxed(expr)),
+            VarExpr(name) => VarExpr(owned(name)),
+            ValExpr(value) => ValExpr(value.into_owned()),
+            PropExpr(parent, prop, access) => PropExpr(boxed(parent), boxed(prop), access),
+            AttrExpr(parent, prop, access) => AttrExpr(boxed(parent), boxed(prop), access),
+            CallExpr(callee, args) => CallExpr(boxed(callee), owned_exprs(args)),
+            IdentExpr(name) => IdentExpr(owned(name)),
+            NumExpr(n) => NumExpr(n),
+            ParenExpr(expr) => ParenExpr(boxed(expr)),
+            GlobalExpr(name) => GlobalExpr(owned(name)),
+            ThisExpr => ThisExpr,
+        }
+    }
+}
+
+impl<'a> Attr<'a> {
+    /// Copies anything borrowed from the source, so the attribute can
+    /// outlive it.
+    pub fn into_owned(self) -> Attr<'static> {
+        Attr(owned(self.0), self.1.into_owned(), owned_exprs(self.2))
+    }
+}
+
+/// A binary operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]
+pub enum BinOp {
+    /// `+`
+    BiAdd,
+    /// `-`
+    BiSub,
+    /// `*`
+    BiMul,
+    /// `/`
+    BiDiv,
+    /// `%`
+    BiRem,
+    /// `&&`
+    BiAnd,
+    /// `||`
+    BiOr,
+    /// `==`
+    BiEq,
+    /// `!=`
+    BiNe,
+    /// `<`
+    BiLt,
+    /// `<=`
+    BiLe,
+    /// `>`
+    BiGt,
+    /// `>=`
+    BiGe
+}
+
+/// A unary operator.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]
+pub enum UnOp {
+    /// `+`
+    UnAdd,
+    /// `-`
+    UnSub,
+    /// `!`
+    UnNot
+}
@@ -0,0 +1,75 @@
+//! Checks L20n resources for problems that would otherwise only be found
+//! when localizing them.
+//!
+//! Usage: `l20n-lint [--var NAME]... FILE...`
+//!
+//! All files are checked together, as if added to the same `Locale`. Passing
+//! `--var` declares the `$vars` the resources may use.
+#![allow(deprecated)]
+
+extern crate l20n;
+
+use std::env;
+use std::fs;
+use std::process;
+
+fn usage() -> ! {
+    eprintln!("usage: l20n-lint [--var NAME]... FILE...");
+    process::exit(2);
+}
+
+fn main() {
+    let mut vars: Option<Vec<String>> = None;
+    let mut paths = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--var" {
+            match args.next() {
+                Some(var) => vars.get_or_insert_with(Vec::new).push(var),
+                None => usage(),
+            }
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.is_empty() {
+        usage();
+    }
+
+    let mut sources = vec![];
+    let mut failed = false;
+    for path in &paths {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(2);
+            }
+        };
+        // report parse errors against the file they occurred in
+        if let Err(e) = l20n::parse(&source) {
+            println!("{}:{}:{}: {:?}", path, e.line + 1, e.col, e.kind);
+            failed = true;
+        }
+        sources.push(source);
+    }
+    if failed {
+        process::exit(1);
+    }
+
+    let sources: Vec<&str> = sources.iter().map(|s| s.as_str()).collect();
+    let vars: Option<Vec<&str>> = vars.as_ref().map(|v| v.iter().map(|s| s.as_str()).collect());
+    let lints = match l20n::lint(&sources, vars.as_deref()) {
+        Ok(lints) => lints,
+        Err(e) => {
+            eprintln!("l20n-lint: {}:{}: {:?}", e.line + 1, e.col, e.kind);
+            process::exit(1);
+        }
+    };
+    for lint in &lints {
+        println!("{}", lint);
+    }
+    if !lints.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,670 @@
+//! A precompiled form of a resource, so a `Locale` can load it without
+//! parsing.
+//!
+//! The data starts with a header: the magic bytes `l20nbin\0`, the format
+//! version as a little-endian `u16`, then the length and CRC-32 of the rest
+//! as little-endian `u32`s. The rest is a table of every string used, followed
+//! by the resource's comments and definitions, which refer to strings by
+//! their index in the table. Numbers are LEB128 varints.
+//!
+//! Loading checks everything before using it: the header, the checksum,
+//! every length against the data that is left, every string index, and how
+//! deeply expressions nest, so that corrupt or hostile data is rejected with
+//! a `BinaryError` rather than a panic.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str;
+
+use compiler::{self, Definition, Resource};
+use context::Duplicate;
+use parser::{self, ParseError, Span};
+
+const MAGIC: &[u8] = b"l20nbin\0";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 18;
+// deep enough for any real resource, shallow enough not to overflow the stack
+const MAX_DEPTH: usize = 256;
+
+const BIN_OPS: [parser::BinOp; 13] = [
+    parser::BiAdd, parser::BiSub, parser::BiMul, parser::BiDiv, parser::BiRem, parser::BiAnd,
+    parser::BiOr, parser::BiEq, parser::BiNe, parser::BiLt, parser::BiLe, parser::BiGt, parser::BiGe,
+];
+const UN_OPS: [parser::UnOp; 3] = [parser::UnAdd, parser::UnSub, parser::UnNot];
+
+/// An error loading a precompiled resource.
+#[derive(Debug)]
+pub enum BinaryError {
+    /// The data does not start with the magic bytes of the format.
+    NotBinary,
+    /// The data is in a version of the format this crate cannot read.
+    UnsupportedVersion(u16),
+    /// The data does not match its checksum, so it was corrupted.
+    ChecksumMismatch,
+    /// The data ends before the resource does.
+    Truncated,
+    /// The data is not a valid resource, at the given byte offset.
+    Malformed(usize),
+    /// The resource defines an id that was already defined, and the Locale
+    /// rejects duplicates. It is boxed to keep the error, which decoding
+    /// passes back up through every level of nesting, small.
+    Duplicate(Box<Duplicate>),
+}
+
+type Result<T> = ::std::result::Result<T, BinaryError>;
+
+/// Compiles an L20n resource into the precompiled format, to be loaded with
+/// `Locale::add_binary`.
+///
+/// This is meant to run at build time, such as from a build script, so that
+/// parsing is not paid for at startup. A precompiled resource is loaded on
+/// its own, so one that imports others is rejected.
+///
+/// # Panics
+///
+/// If the compiled resource is 4 GiB or more, which the header cannot
+/// give the length of.
+///
+/// ```rust
+/// let bytes = l20n::compile_binary("<hi 'Hello'>").unwrap();
+///
+/// let mut locale = l20n::Locale::new();
+/// locale.add_binary(&bytes).unwrap();
+/// assert_eq!(locale.localize_entity("hi", ()).unwrap(), "Hello");
+/// ```
+pub fn compile_binary(source: &str) -> ::std::result::Result<Vec<u8>, ParseError> {
+    Ok(encode(&compiler::compile_standalone(source)?))
+}
+
+fn encode(resource: &Resource) -> Vec<u8> {
+    let mut w = Writer { strings: vec![], index: HashMap::new(), out: vec![] };
+    w.len(resource.comments.len());
+    for comment in &resource.comments {
+        w.string(comment);
+    }
+    w.len(resource.definitions.len());
+    for def in &resource.definitions {
+        for &n in &[def.span.line, def.span.col, def.span.end_line, def.span.end_col] {
+            w.len(n);
+        }
+        match def.note {
+            Some(ref note) => {
+                w.byte(1);
+                w.string(note);
+            }
+            None => w.byte(0),
+        }
+        w.entry(&def.entry);
+    }
+
+    let mut table = Writer { strings: vec![], index: HashMap::new(), out: vec![] };
+    table.len(w.strings.len());
+    for s in &w.strings {
+        table.len(s.len());
+        table.out.extend_from_slice(s.as_bytes());
+    }
+    let mut payload = table.out;
+    payload.extend_from_slice(&w.out);
+
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    let len = u32::try_from(payload.len()).expect("a compiled resource under 4 GiB");
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&crc32(&payload).to_le_bytes());
+    out.extend_from_slice(&payload);
+    out
+}
+
+/// Checks and reads precompiled data back into a compiled resource.
+pub fn decode(bytes: &[u8]) -> Result<Resource<'static>> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(BinaryError::NotBinary);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(BinaryError::Truncated);
+    }
+    let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+    if version != VERSION {
+        return Err(BinaryError::UnsupportedVersion(version));
+    }
+    let len = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]) as usize;
+    let checksum = u32::from_le_bytes([bytes[14], bytes[15], bytes[16], bytes[17]]);
+    let payload = &bytes[HEADER_LEN..];
+    if payload.len() < len {
+        return Err(BinaryError::Truncated);
+    }
+    if payload.len() > len {
+        return Err(BinaryError::Malformed(HEADER_LEN + len));
+    }
+    if crc32(payload) != checksum {
+        return Err(BinaryError::ChecksumMismatch);
+    }
+
+    let mut r = Reader { bytes: payload, pos: 0, strings: vec![], depth: 0 };
+    let count = r.len()?;
+    let mut strings = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = r.len()?;
+        let start = r.pos;
+        r.pos += len;
+        match str::from_utf8(&payload[start..r.pos]) {
+            Ok(s) => strings.push(s),
+            Err(_) => return Err(r.malformed_at(start)),
+        }
+    }
+    r.strings = strings;
+
+    let count = r.len()?;
+    let mut comments = Vec::with_capacity(count);
+    for _ in 0..count {
+        comments.push(r.string()?.into_owned());
+    }
+
+    let count = r.len()?;
+    let mut definitions = Vec::with_capacity(count);
+    for _ in 0..count {
+        let span = Span { line: r.varint()?, col: r.varint()?, end_line: r.varint()?, end_col: r.varint()? };
+        let note = match r.byte()? {
+            0 => None,
+            1 => Some(r.string()?.into_owned()),
+            _ => return Err(r.malformed()),
+        };
+        let entry = r.entry()?;
+        let id = match entry {
+            parser::Entity(ref id, ..) | parser::Macro(ref id, ..) => id.to_string(),
+            parser::Comment(..) | parser::Import(..) => unreachable!(),
+        };
+        definitions.push(Definition { id, entry, span, note });
+    }
+
+    if r.pos != payload.len() {
+        return Err(r.malformed());
+    }
+    Ok(Resource { definitions, comments, imports: vec![] })
+}
+
+/// The CRC-32 used by zip and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+struct Writer<'r> {
+    // every string used, in the order they were first seen
+    strings: Vec<&'r str>,
+    index: HashMap<&'r str, usize>,
+    out: Vec<u8>,
+}
+
+impl<'r> Writer<'r> {
+    fn byte(&mut self, b: u8) {
+        self.out.push(b);
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.out.push(n as u8);
+    }
+
+    fn len(&mut self, n: usize) {
+        self.varint(n as u64);
+    }
+
+    fn string(&mut self, s: &'r str) {
+        let next = self.strings.len();
+        let i = *self.index.entry(s).or_insert(next);
+        if i == next {
+            self.strings.push(s);
+        }
+        self.len(i);
+    }
+
+    fn opt_string(&mut self, s: &'r Option<Cow<str>>) {
+        match *s {
+            Some(ref s) => {
+                self.byte(1);
+                self.string(s);
+            }
+            None => self.byte(0),
+        }
+    }
+
+    fn exprs(&mut self, exprs: &'r [parser::Expr]) {
+        self.len(exprs.len());
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn entry(&mut self, entry: &'r parser::Entry) {
+        match *entry {
+            parser::Entity(ref id, ref value, ref indices, ref attrs) => {
+                self.byte(0);
+                self.string(id);
+                self.value(value);
+                self.exprs(indices);
+                self.len(attrs.len());
+                for attr in attrs {
+                    self.string(&attr.0);
+                    self.value(&attr.1);
+                    self.exprs(&attr.2);
+                }
+            }
+            parser::Macro(ref id, ref args, ref body) => {
+                self.byte(1);
+                self.string(id);
+                self.len(args.len());
+                for arg in args {
+                    match *arg {
+                        parser::VarExpr(ref name) => self.string(name),
+                        // not a VarExpr would be the parser going nuts
+                        _ => unreachable!()
+                    }
+                }
+                self.expr(body);
+            }
+            parser::Comment(..) | parser::Import(..) => {
+                unreachable!("comments and imports are not compiled into definitions")
+            }
+        }
+    }
+
+    fn value(&mut self, value: &'r parser::Value) {
+        match *value {
+            parser::Str(ref s) => {
+                self.byte(0);
+                self.string(s);
+            }
+            parser::ComplexStr(ref exprs) => {
+                self.byte(1);
+                self.exprs(exprs);
+            }
+            parser::Hash(ref map, ref def_key, ref def_index) => {
+                self.byte(2);
+                self.len(map.len());
+                for (key, value) in map {
+                    self.string(key);
+                    self.value(value);
+                }
+                self.opt_string(def_key);
+                match *def_index {
+                    Some(ref index) => {
+                        self.byte(1);
+                        self.expr(index);
+                    }
+                    None => self.byte(0),
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &'r parser::Expr) {
+        match *expr {
+            parser::CondExpr(ref cond, ref consequent, ref alt) => {
+                self.byte(0);
+                self.expr(cond);
+                self.expr(consequent);
+                self.expr(alt);
+            }
+            parser::BinExpr(ref left, op, ref right) => {
+                self.byte(1);
+                self.byte(op as u8);
+                self.expr(left);
+                self.expr(right);
+            }
+            parser::UnExpr(op, ref expr) => {
+                self.byte(2);
+                self.byte(op as u8);
+                self.expr(expr);
+            }
+            parser::VarExpr(ref name) => {
+                self.byte(3);
+                self.string(name);
+            }
+            parser::ValExpr(ref value) => {
+                self.byte(4);
+                self.value(value);
+            }
+            parser::PropExpr(ref parent, ref prop, access) |
+            parser::AttrExpr(ref parent, ref prop, access) => {
+                self.byte(if let parser::PropExpr(..) = *expr { 5 } else { 6 });
+                self.byte(access as u8);
+                self.expr(parent);
+                self.expr(prop);
+            }
+            parser::CallExpr(ref callee, ref args) => {
+                self.byte(7);
+                self.expr(callee);
+                self.exprs(args);
+            }
+            parser::IdentExpr(ref name) => {
+                self.byte(8);
+                self.string(name);
+            }
+            parser::NumExpr(n) => {
+                self.byte(9);
+                // zigzag, so small negative numbers stay small
+                self.varint(((n << 1) ^ (n >> 63)) as u64);
+            }
+            parser::ParenExpr(ref expr) => {
+                self.byte(10);
+                self.expr(expr);
+            }
+            parser::GlobalExpr(ref name) => {
+                self.byte(11);
+                self.string(name);
+            }
+            parser::ThisExpr => self.byte(12),
+        }
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+    strings: Vec<&'b str>,
+    // how many values and expressions are being read inside each other
+    depth: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn malformed(&self) -> BinaryError {
+        self.malformed_at(self.pos)
+    }
+
+    fn malformed_at(&self, pos: usize) -> BinaryError {
+        BinaryError::Malformed(HEADER_LEN + pos)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        match self.bytes.get(self.pos) {
+            Some(&b) => {
+                self.pos += 1;
+                Ok(b)
+            }
+            None => Err(BinaryError::Truncated),
+        }
+    }
+
+    fn varint(&mut self) -> Result<usize> {
+        let start = self.pos;
+        let mut n = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift == 63 && b > 1 || shift > 63 {
+                return Err(self.malformed_at(start));
+            }
+            n |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        if n > usize::MAX as u64 {
+            return Err(self.malformed_at(start));
+        }
+        Ok(n as usize)
+    }
+
+    /// A count of items or bytes that follow, each taking at least a byte,
+    /// so it cannot be more than what is left. This keeps a bad length from
+    /// allocating more than the data could hold.
+    fn len(&mut self) -> Result<usize> {
+        let n = self.varint()?;
+        if n > self.bytes.len() - self.pos {
+            return Err(BinaryError::Truncated);
+        }
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<Cow<'static, str>> {
+        let start = self.pos;
+        let i = self.varint()?;
+        match self.strings.get(i) {
+            Some(s) => Ok(Cow::Owned(s.to_string())),
+            None => Err(self.malformed_at(start)),
+        }
+    }
+
+    fn opt_string(&mut self) -> Result<Option<Cow<'static, str>>> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.string()?)),
+            _ => Err(self.malformed_at(self.pos - 1)),
+        }
+    }
+
+    fn nested<T, F>(&mut self, read: F) -> Result<T>
+    where F: FnOnce(&mut Reader<'b>) -> Result<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.malformed());
+        }
+        self.depth += 1;
+        let result = read(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn exprs(&mut self) -> Result<Vec<parser::Expr<'static>>> {
+        let count = self.len()?;
+        let mut exprs = Vec::with_capacity(count);
+        for _ in 0..count {
+            exprs.push(self.expr()?);
+        }
+        Ok(exprs)
+    }
+
+    fn entry(&mut self) -> Result<parser::Entry<'static>> {
+        match self.byte()? {
+            0 => {
+                let id = self.string()?;
+                let value = self.value()?;
+                let indices = self.exprs()?;
+                let count = self.len()?;
+                let mut attrs = Vec::with_capacity(count);
+                for _ in 0..count {
+                    attrs.push(parser::Attr(self.string()?, self.value()?, self.exprs()?));
+                }
+                Ok(parser::Entity(id, value, indices, attrs))
+            }
+            1 => {
+                let id = self.string()?;
+                let count = self.len()?;
+                let mut args = Vec::with_capacity(count);
+                for _ in 0..count {
+                    args.push(parser::VarExpr(self.string()?));
+                }
+                Ok(parser::Macro(id, args, self.expr()?))
+            }
+            _ => Err(self.malformed_at(self.pos - 1)),
+        }
+    }
+
+    fn value(&mut self) -> Result<parser::Value<'static>> {
+        self.nested(|r| match r.byte()? {
+            0 => Ok(parser::Str(r.string()?)),
+            1 => Ok(parser::ComplexStr(r.exprs()?)),
+            2 => {
+                let count = r.len()?;
+                let mut map = HashMap::with_capacity(count);
+                for _ in 0..count {
+                    let key = r.string()?;
+                    map.insert(key, r.value()?);
+                }
+                let def_key = r.opt_string()?;
+                let def_index = match r.byte()? {
+                    0 => None,
+                    1 => Some(Box::new(r.expr()?)),
+                    _ => return Err(r.malformed_at(r.pos - 1)),
+                };
+                Ok(parser::Hash(map, def_key, def_index))
+            }
+            _ => Err(r.malformed_at(r.pos - 1)),
+        })
+    }
+
+    fn op<T: Copy>(&mut self, ops: &[T]) -> Result<T> {
+        let b = self.byte()?;
+        match ops.get(b as usize) {
+            Some(&op) => Ok(op),
+            None => Err(self.malformed_at(self.pos - 1)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<parser::Expr<'static>> {
+        let boxed = |r: &mut Reader| r.expr().map(Box::new);
+        self.nested(|r| Ok(match r.byte()? {
+            0 => parser::CondExpr(boxed(r)?, boxed(r)?, boxed(r)?),
+            1 => {
+                let op = r.op(&BIN_OPS)?;
+                parser::BinExpr(boxed(r)?, op, boxed(r)?)
+            }
+            2 => parser::UnExpr(r.op(&UN_OPS)?, boxed(r)?),
+            3 => parser::VarExpr(r.string()?),
+            4 => parser::ValExpr(r.value()?),
+            tag @ 5 | tag @ 6 => {
+                let access = r.op(&[parser::Computed, parser::Static])?;
+                let (parent, prop) = (boxed(r)?, boxed(r)?);
+                if tag == 5 {
+                    parser::PropExpr(parent, prop, access)
+                } else {
+                    parser::AttrExpr(parent, prop, access)
+                }
+            }
+            7 => parser::CallExpr(boxed(r)?, r.exprs()?),
+            8 => parser::IdentExpr(r.string()?),
+            9 => {
+                let n = r.varint()? as u64;
+                parser::NumExpr((n >> 1) as i64 ^ -((n & 1) as i64))
+            }
+            10 => parser::ParenExpr(boxed(r)?),
+            11 => parser::GlobalExpr(r.string()?),
+            12 => parser::ThisExpr,
+            _ => return Err(r.malformed_at(r.pos - 1)),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile_binary, decode, crc32, BinaryError, HEADER_LEN};
+    use compiler::compile;
+    use compiler::ResolveError::{DivideByZero, Overflow};
+    use context::{Locale, LocalizeError};
+
+    const SOURCE: &str = r#"
+    /* Greetings */
+
+    /* Shown on the home page. */
+    <brand { *short: 'Rust', long: 'Rust Lang' } title: 'The {{ brand.long }}'>
+    <hi 'Hello, {{ brand::title }}!'>
+    <fac($n) { $n == 0 ? 1 : $n * fac($n - 1) }>
+    <neg '{{ -42 + fac(3) }}'>
+    <pick['b'] { a: 'A', b: '{{ $x }}' }>
+    "#;
+
+    fn set_checksum(bytes: &mut [u8]) {
+        let checksum = crc32(&bytes[HEADER_LEN..]).to_le_bytes();
+        bytes[14..HEADER_LEN].copy_from_slice(&checksum);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let compiled = compile(SOURCE).unwrap();
+        let decoded = decode(&compile_binary(SOURCE).unwrap()).unwrap();
+
+        assert_eq!(decoded.comments, compiled.comments);
+        assert_eq!(decoded.definitions.len(), compiled.definitions.len());
+        for (d, c) in decoded.definitions.iter().zip(&compiled.definitions) {
+            assert_eq!(d.id, c.id);
+            assert_eq!(d.entry, c.entry);
+            assert_eq!(d.span, c.span);
+            assert_eq!(d.note, c.note);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        // constants are folded when the resource is lowered, which must
+        // not panic on these
+        let bytes = compile_binary("<div '{{ 1 / 0 }}'>\n<add '{{ 9223372036854775807 + 1 }}'>").unwrap();
+        let mut locale = Locale::new();
+        locale.add_binary(&bytes).unwrap();
+        assert!(matches!(locale.localize_entity("div", ()), Err(LocalizeError::ResolveError(DivideByZero))));
+        assert!(matches!(locale.localize_entity("add", ()), Err(LocalizeError::ResolveError(Overflow))));
+    }
+
+    #[test]
+    fn test_rejects_bad_header() {
+        let bytes = compile_binary(SOURCE).unwrap();
+        assert!(matches!(decode(b"<hi 'Hello'>"), Err(BinaryError::NotBinary)));
+        assert!(matches!(decode(&bytes[..12]), Err(BinaryError::Truncated)));
+
+        let mut future = bytes.clone();
+        future[8] = 2;
+        assert!(matches!(decode(&future), Err(BinaryError::UnsupportedVersion(2))));
+
+        assert!(matches!(decode(&bytes[..bytes.len() - 1]), Err(BinaryError::Truncated)));
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(matches!(decode(&longer), Err(BinaryError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let bytes = compile_binary(SOURCE).unwrap();
+        for i in HEADER_LEN..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[i] ^= 0x40;
+            assert!(matches!(decode(&corrupt), Err(BinaryError::ChecksumMismatch)));
+        }
+    }
+
+    #[test]
+    fn test_rejects_malformed() {
+        // a valid checksum over garbage must still not panic
+        let bytes = compile_binary(SOURCE).unwrap();
+        for i in HEADER_LEN..bytes.len() {
+            for &b in &[0x00, 0x01, 0x0d, 0x7f, 0x80, 0xff] {
+                let mut bad = bytes.clone();
+                bad[i] = b;
+                set_checksum(&mut bad);
+                let _ = decode(&bad);
+            }
+        }
+
+        // no strings, no comments, one entity whose id is string 5
+        let mut bad = compile_binary("").unwrap();
+        bad.truncate(HEADER_LEN);
+        bad.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0, 0, 0, 5]);
+        bad[10] = 10;
+        set_checksum(&mut bad);
+        assert!(matches!(decode(&bad), Err(BinaryError::Malformed(27))));
+    }
+
+    #[test]
+    fn test_rejects_deep_nesting() {
+        // a macro whose body is a million parentheses deep
+        let mut payload = vec![1, 1, b'm', 0, 1, 0, 0, 0, 0, 0, 1, 0, 0];
+        payload.extend(vec![10; 1_000_000]);
+        payload.push(12);
+        let mut bad = compile_binary("").unwrap();
+        bad.truncate(HEADER_LEN);
+        bad.extend_from_slice(&payload);
+        bad[10..14].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        set_checksum(&mut bad);
+        assert!(matches!(decode(&bad), Err(BinaryError::Malformed(_))));
+    }
+}
@@ -0,0 +1,509 @@
+//! Generates typed accessors for L20n resources from a build script.
+//!
+//! `generate` reads every `.l20n` file in a directory, checks that it
+//! compiles, and writes Rust code with a `Strings` type that embeds the
+//! resources and has one method per entity. A method takes one argument per
+//! `$var` the entity uses, including through the entities and macros it
+//! references, so a misspelled id or a missing variable is a compile error
+//! rather than a `MissingIdent` or `MissingVar` at runtime. A reference
+//! inside a resource that would fail the same way, such as to an entity
+//! that does not exist, fails the build instead.
+//!
+//! Each argument's type is inferred from how the variable is used: `i64` if
+//! it takes part in arithmetic or is compared to a number, `bool` if it is
+//! used as a condition, and `&str` otherwise. `signatures` gives the same
+//! information without generating code.
+//!
+//! # Example
+//!
+//! In `build.rs`:
+//!
+//! ```rust,no_run
+//! # #![allow(deprecated)]
+//! # extern crate l20n;
+//! use std::env;
+//! use std::path::Path;
+//!
+//! fn main() {
+//!     let out = Path::new(&env::var("OUT_DIR").unwrap()).join("strings.rs");
+//!     l20n::build::generate("locales/en-US", out).unwrap();
+//! }
+//! ```
+//!
+//! And in the crate, for a resource with
+//! `<factorial "Factorial of {{ $number }} is {{ fac($number) }}.">`:
+//!
+//! ```rust,ignore
+//! mod strings {
+//!     include!(concat!(env!("OUT_DIR"), "/strings.rs"));
+//! }
+//!
+//! let strings = strings::Strings::default();
+//! assert_eq!(strings.factorial(3), "Factorial of 3 is 6.");
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use binary;
+use compiler::{self, Definition};
+use lint::{self, Lint, LintKind};
+use parser::{self, ParseError};
+use visit::{self, Visit};
+
+/// An error generating accessors.
+#[derive(Debug)]
+pub enum BuildError {
+    /// Reading the resources or writing the code failed.
+    Io(PathBuf, io::Error),
+    /// A resource is not valid L20n.
+    Parse(PathBuf, ParseError),
+    /// The resources reference an entity or macro that does not exist, or
+    /// call one with the wrong arguments, which would panic when the
+    /// accessor is called. Holds every such problem, sorted by id.
+    Lint(Vec<Lint>),
+}
+
+/// Generates accessors for the `.l20n` files in `dir`, and writes them to
+/// `out`, to be `include!`d by the crate.
+///
+/// The files are added to one `Locale` in the order of their names. This
+/// also tells Cargo to run the build script again when they change.
+pub fn generate<P: AsRef<Path>, Q: AsRef<Path>>(dir: P, out: Q) -> Result<(), BuildError> {
+    let dir = dir.as_ref();
+    let io_err = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e| BuildError::Io(path, e)
+    };
+
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).map_err(io_err(dir))? {
+        let path = entry.map_err(io_err(dir))?.path();
+        if path.extension().is_some_and(|ext| ext == "l20n") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    println!("cargo:rerun-if-changed={}", dir.display());
+    let mut sources = vec![];
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let source = fs::read_to_string(&path).map_err(io_err(&path))?;
+        sources.push((path, source));
+    }
+
+    let code = generate_code(&sources)?;
+    let out = out.as_ref();
+    fs::write(out, code).map_err(io_err(out))
+}
+
+/// Generates the code for resources already read into memory, each with
+/// the path it is reported under if it does not compile.
+pub fn generate_code<P: AsRef<Path>>(sources: &[(P, String)]) -> Result<String, BuildError> {
+    let definitions = merge(sources)?;
+    let env = env(&definitions);
+    let mut lints: Vec<Lint> = lint::lint_env(&env, None).into_iter().filter(|lint| matches!(lint.kind,
+        LintKind::UnknownIdent(..) | LintKind::NotAMacro(..) | LintKind::WrongNumberOfArgs(..)
+    )).collect();
+    if !lints.is_empty() {
+        lints.sort();
+        return Err(BuildError::Lint(lints));
+    }
+    let signatures = signatures_of(&definitions, &env);
+    let mut resources = vec![];
+    for (path, source) in sources {
+        resources.push(binary::compile_binary(source)
+            .map_err(|e| BuildError::Parse(path.as_ref().to_path_buf(), e))?);
+    }
+
+    let mut code = String::new();
+    code.push_str("// Generated by l20n::build. Do not edit.\n\n");
+    code.push_str("static RESOURCES: &[&[u8]] = &[\n");
+    for resource in &resources {
+        writeln!(code, "    {},", byte_str(resource)).unwrap();
+    }
+    code.push_str("];\n\n");
+    code.push_str("/// The entities of the L20n resources, with one method each.\n");
+    code.push_str("pub struct Strings {\n    locale: ::l20n::Locale,\n}\n\n");
+    code.push_str("impl Default for Strings {\n");
+    code.push_str("    /// Loads the resources embedded at build time.\n");
+    code.push_str("    fn default() -> Strings {\n");
+    code.push_str("        let mut locale = ::l20n::Locale::new();\n");
+    code.push_str("        for resource in RESOURCES {\n");
+    code.push_str("            locale.add_binary(resource).expect(\"resources were checked at build time\");\n");
+    code.push_str("        }\n");
+    code.push_str("        Strings { locale }\n");
+    code.push_str("    }\n}\n\n");
+    code.push_str("impl AsRef<::l20n::Locale> for Strings {\n");
+    code.push_str("    fn as_ref(&self) -> &::l20n::Locale {\n        &self.locale\n    }\n}\n\n");
+    code.push_str("#[allow(non_snake_case)]\nimpl Strings {\n");
+
+    for (i, sig) in signatures.iter().enumerate() {
+        if i > 0 {
+            code.push('\n');
+        }
+        if let Some(ref note) = sig.note {
+            for line in note.lines() {
+                writeln!(code, "    /// {}", line.trim()).unwrap();
+            }
+        } else {
+            writeln!(code, "    /// The `{}` entity.", sig.id).unwrap();
+        }
+
+        let params: Vec<String> = sig.params.iter()
+            .map(|(var, ty)| format!(", {}: {}", ident(var), ty.rust_type()))
+            .collect();
+        writeln!(code, "    pub fn {}(&self{}) -> String {{", ident(&sig.id), params.concat()).unwrap();
+        code.push_str("        #[allow(unused_mut)]\n");
+        code.push_str("        let mut args = ::std::collections::HashMap::<&str, ::l20n::Arg>::new();\n");
+        for (var, ty) in &sig.params {
+            writeln!(code, "        args.insert({:?}, ::l20n::Arg::{}({}));", var, ty.variant(), ident(var)).unwrap();
+        }
+        writeln!(code, "        self.locale.localize_entity({:?}, args)", sig.id).unwrap();
+        writeln!(code, "            .unwrap_or_else(|e| panic!(\"could not localize `{}`: {{:?}}\", e))", sig.id).unwrap();
+        code.push_str("    }\n");
+    }
+    code.push_str("}\n");
+    Ok(code)
+}
+
+/// The accessor for an entity.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature {
+    /// The id of the entity.
+    pub id: String,
+    /// The comment directly before the entity, if any.
+    pub note: Option<String>,
+    /// The `$vars` the entity needs, sorted by name.
+    pub params: Vec<(String, ArgType)>,
+}
+
+/// Finds the accessor of every entity in the resources, sorted by id.
+///
+/// Later resources override earlier ones, as they would when added to a
+/// `Locale` in the same order.
+pub fn signatures<P: AsRef<Path>>(sources: &[(P, String)]) -> Result<Vec<Signature>, BuildError> {
+    let definitions = merge(sources)?;
+    Ok(signatures_of(&definitions, &env(&definitions)))
+}
+
+/// The definition kept for each id, the later resources overriding the
+/// earlier ones.
+fn merge<P: AsRef<Path>>(sources: &[(P, String)]) -> Result<HashMap<String, Definition<'_>>, BuildError> {
+    let mut definitions = HashMap::new();
+    for (path, source) in sources {
+        let compiled = compiler::compile_standalone(source)
+            .map_err(|e| BuildError::Parse(path.as_ref().to_path_buf(), e))?;
+        for def in compiled.definitions {
+            definitions.insert(def.id.clone(), def);
+        }
+    }
+    Ok(definitions)
+}
+
+fn env<'a>(definitions: &HashMap<String, Definition<'a>>) -> compiler::Env<'a> {
+    definitions.iter()
+        .map(|(id, def)| (id.clone(), def.entry.clone()))
+        .collect()
+}
+
+fn signatures_of(definitions: &HashMap<String, Definition>, env: &compiler::Env) -> Vec<Signature> {
+    let mut signatures: Vec<Signature> = infer_vars(env).into_iter()
+        .map(|(id, vars)| Signature {
+            note: definitions[&id].note.clone(),
+            id,
+            params: vars.into_iter().collect(),
+        })
+        .collect();
+    signatures.sort_by(|a, b| a.id.cmp(&b.id));
+    signatures
+}
+
+/// The type of a `$var`, as far as can be told from how it is used.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ArgType {
+    /// Passed as `Arg::Str`, and used as text.
+    Str,
+    /// Passed as `Arg::Num`, and used in arithmetic or compared to a number.
+    Num,
+    /// Passed as `Arg::Bool`, and used as a condition.
+    Bool,
+}
+
+impl ArgType {
+    fn rust_type(self) -> &'static str {
+        match self {
+            ArgType::Str => "&str",
+            ArgType::Num => "i64",
+            ArgType::Bool => "bool",
+        }
+    }
+
+    fn variant(self) -> &'static str {
+        match self {
+            ArgType::Str => "Str",
+            ArgType::Num => "Num",
+            ArgType::Bool => "Bool",
+        }
+    }
+}
+
+/// Marks `var` as `ty`. A string is only a guess, so any other use wins.
+fn mark(vars: &mut BTreeMap<String, ArgType>, var: &str, ty: ArgType) {
+    let found = vars.entry(var.to_string()).or_insert(ty);
+    if *found == ArgType::Str {
+        *found = ty;
+    }
+}
+
+/// What an entry uses: its `$vars` with their types, the entries it
+/// references, and which of its `$vars` are passed to which macro argument.
+#[derive(Default)]
+struct Usage {
+    vars: BTreeMap<String, ArgType>,
+    refs: BTreeSet<String>,
+    calls: Vec<(String, usize, String)>,
+}
+
+impl<'a> Visit<'a> for Usage {
+    fn visit_expr(&mut self, expr: &'a parser::Expr) {
+        match *expr {
+            parser::BinExpr(ref left, op, ref right) => {
+                let ty = match op {
+                    parser::BiAnd | parser::BiOr => Some(ArgType::Bool),
+                    parser::BiEq | parser::BiNe => {
+                        match (unparen(left), unparen(right)) {
+                            (&parser::NumExpr(..), _) | (_, &parser::NumExpr(..)) => Some(ArgType::Num),
+                            _ => None,
+                        }
+                    }
+                    _ => Some(ArgType::Num),
+                };
+                if let Some(ty) = ty {
+                    self.mark(left, ty);
+                    self.mark(right, ty);
+                }
+            }
+            parser::UnExpr(op, ref expr) => {
+                self.mark(expr, if op == parser::UnNot { ArgType::Bool } else { ArgType::Num });
+            }
+            parser::CondExpr(ref cond, ..) => self.mark(cond, ArgType::Bool),
+            parser::CallExpr(ref callee, ref args) => {
+                if let parser::IdentExpr(ref name) = **callee {
+                    for (i, arg) in args.iter().enumerate() {
+                        if let parser::VarExpr(ref var) = *unparen(arg) {
+                            self.calls.push((name.to_string(), i, var.to_string()));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_var(&mut self, name: &'a str) {
+        mark(&mut self.vars, name, ArgType::Str);
+    }
+
+    fn visit_ident(&mut self, name: &'a str) {
+        self.refs.insert(name.to_string());
+    }
+}
+
+impl Usage {
+    fn mark(&mut self, expr: &parser::Expr, ty: ArgType) {
+        if let parser::VarExpr(ref var) = *unparen(expr) {
+            mark(&mut self.vars, var, ty);
+        }
+    }
+}
+
+fn unparen<'r, 'a>(mut expr: &'r parser::Expr<'a>) -> &'r parser::Expr<'a> {
+    while let parser::ParenExpr(ref inner) = *expr {
+        expr = inner;
+    }
+    expr
+}
+
+/// Finds the `$vars` each entity needs from the localization data, directly
+/// or through what it references, along with their types.
+fn infer_vars(env: &compiler::Env) -> HashMap<String, BTreeMap<String, ArgType>> {
+    let mut usages = HashMap::new();
+    let mut params = HashMap::new();
+    for (id, entry) in env {
+        let mut usage = Usage::default();
+        usage.visit_entry(entry);
+        if let parser::Macro(_, ref args, _) = *entry {
+            let names: Vec<String> = args.iter().filter_map(|arg| match *arg {
+                parser::VarExpr(ref name) => Some(name.to_string()),
+                _ => None,
+            }).collect();
+            params.insert(id.as_str(), names);
+        }
+        usages.insert(id.as_str(), usage);
+    }
+
+    // a $var passed to a macro takes the type of the macro's argument, which
+    // may itself have come from another macro, so repeat until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut found = vec![];
+        for (id, usage) in &usages {
+            for (callee, i, var) in &usage.calls {
+                let param = params.get(callee.as_str()).and_then(|p| p.get(*i));
+                let ty = param.and_then(|p| usages[callee.as_str()].vars.get(p));
+                if let Some(&ty) = ty {
+                    if ty != ArgType::Str && usage.vars.get(var) == Some(&ArgType::Str) {
+                        found.push((*id, var.clone(), ty));
+                    }
+                }
+            }
+        }
+        for (id, var, ty) in found {
+            mark(&mut usages.get_mut(id).unwrap().vars, &var, ty);
+            changed = true;
+        }
+    }
+
+    let mut vars = HashMap::new();
+    for (id, entry) in env {
+        if let parser::Macro(..) = *entry {
+            continue;
+        }
+        let mut needed = BTreeMap::new();
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![id.as_str()];
+        while let Some(current) = stack.pop() {
+            if !seen.insert(current) {
+                continue;
+            }
+            let usage = match usages.get(current) {
+                Some(usage) => usage,
+                None => continue,
+            };
+            let own = params.get(current);
+            for (var, &ty) in &usage.vars {
+                if !own.is_some_and(|own| own.contains(var)) {
+                    mark(&mut needed, var, ty);
+                }
+            }
+            stack.extend(usage.refs.iter().map(|r| r.as_str()));
+        }
+        vars.insert(id.clone(), needed);
+    }
+    vars
+}
+
+/// An id or `$var` name as a Rust identifier.
+fn ident(name: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+        "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "macro",
+        "override", "priv", "typeof", "unsized", "virtual", "yield",
+    ];
+    match name {
+        // these cannot be raw identifiers
+        "self" | "Self" | "super" | "crate" | "_" => format!("{}_", name),
+        _ if KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+/// A byte string literal, keeping printable ASCII readable.
+fn byte_str(bytes: &[u8]) -> String {
+    let mut s = String::from("b\"");
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => {
+                s.push('\\');
+                s.push(b as char);
+            }
+            b' '..=b'~' => s.push(b as char),
+            _ => write!(s, "\\x{:02x}", b).unwrap(),
+        }
+    }
+    s.push('"');
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{generate_code, infer_vars, BuildError, ArgType};
+    use lint::{Lint, LintKind};
+    use compiler::{compile, Env};
+
+    fn vars(source: &str, id: &str) -> Vec<(String, ArgType)> {
+        let env: Env = compile(source).unwrap().definitions
+            .into_iter().map(|def| (def.id, def.entry)).collect();
+        let vars: BTreeMap<String, ArgType> = infer_vars(&env).remove(id).unwrap();
+        vars.into_iter().collect()
+    }
+
+    #[test]
+    fn test_infer_vars() {
+        let src = r#"
+        <fac($n) { $n == 0 ? 1 : $n * fac($n - 1) }>
+        <factorial "Factorial of {{ $number }} is {{ fac($number) }}.">
+        <greet($who) { 'Hi, {{ $who }} from {{ $place }}' }>
+        <hi '{{ greet($user) }}'>
+        <both '{{ hi }} {{ $admin ? "(admin)" : "" }}'>
+        "#;
+        assert_eq!(vars(src, "factorial"), vec![(String::from("number"), ArgType::Num)]);
+        assert_eq!(vars(src, "hi"), vec![
+            (String::from("place"), ArgType::Str),
+            (String::from("user"), ArgType::Str),
+        ]);
+        assert_eq!(vars(src, "both"), vec![
+            (String::from("admin"), ArgType::Bool),
+            (String::from("place"), ArgType::Str),
+            (String::from("user"), ArgType::Str),
+        ]);
+    }
+
+    #[test]
+    fn test_generate_code() {
+        let src = String::from(r#"
+        /* The result of fac. */
+        <factorial "Factorial of {{ $number }} is {{ fac($number) }}.">
+        <fac($n) { $n == 0 ? 1 : $n * fac($n - 1) }>
+        <type 'Type: {{ $self }}'>
+        "#);
+        let code = generate_code(&[("en.l20n", src)]).unwrap();
+        assert!(code.contains("    /// The result of fac.\n    pub fn factorial(&self, number: i64) -> String {\n"));
+        assert!(code.contains("args.insert(\"number\", ::l20n::Arg::Num(number));"));
+        assert!(code.contains("pub fn r#type(&self, self_: &str) -> String {"));
+        assert!(!code.contains("fn fac("));
+
+        match generate_code(&[("bad.l20n", String::from("<hi"))]) {
+            Err(BuildError::Parse(ref path, _)) => assert_eq!(path.to_str(), Some("bad.l20n")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let src = String::from(r#"
+        <x "{{ brnd }}">
+        <hi "{{ x(1) }}">
+        <twice($n) { $n * 2 }>
+        <four "{{ twice(2, 2) }}">
+        <plain "{{ brnd }}" title: "Title">
+        "#);
+        match generate_code(&[("en.l20n", src)]) {
+            Err(BuildError::Lint(ref lints)) => assert_eq!(*lints, vec![
+                Lint { id: String::from("four"), kind: LintKind::WrongNumberOfArgs(String::from("twice"), 1, 2) },
+                Lint { id: String::from("hi"), kind: LintKind::NotAMacro(String::from("x")) },
+                Lint { id: String::from("plain"), kind: LintKind::UnknownIdent(String::from("brnd")) },
+                Lint { id: String::from("x"), kind: LintKind::UnknownIdent(String::from("brnd")) },
+            ]),
+            other => panic!("expected lints, got {:?}", other),
+        }
+    }
+}
@@ -1,117 +1,136 @@
 
 use std::collections::HashMap;
 
-use data;
-use parser::{ParseError, Parser};
+use parser::{ParseError, Parser, Span};
 use parser;
 
-use self::ResolveError::*;
-use self::ResolveTarget::*;
 
+/// A compiled L20n resource, which borrows from its source until
+/// `into_owned` is called.
+#[derive(Clone)]
+pub struct Resource<'a> {
+    /// Every entity and macro, in the order they were defined.
+    pub definitions: Vec<Definition<'a>>,
+    /// Comments that are not attached to an entry, such as section headers.
+    pub comments: Vec<String>,
+    /// The paths of the resources this one imports, as written.
+    pub imports: Vec<(String, Span)>,
+}
 
-pub fn compile(source: &str) -> Result<HashMap<String, parser::Entry>, ParseError> {
-    let p = Parser::new(source.chars());
-    let entries = try!(p.parse());
+/// An entity or macro, along with where it was defined so duplicate ids can
+/// be found.
+#[derive(Clone)]
+pub struct Definition<'a> {
+    pub id: String,
+    pub entry: parser::Entry<'a>,
+    pub span: Span,
+    /// The comment directly preceding the entry, if any.
+    pub note: Option<String>,
+}
 
-    let mut map = HashMap::new();
+impl<'a> Resource<'a> {
+    /// Copies whatever the resource still borrows from its source.
+    pub fn into_owned(self) -> Resource<'static> {
+        Resource {
+            definitions: self.definitions.into_iter().map(Definition::into_owned).collect(),
+            comments: self.comments,
+            imports: self.imports,
+        }
+    }
+}
 
-    for mut entry in entries {
-        let id = match entry {
-            parser::Comment(..) => continue,
-            parser::Macro(ref id, _, _) => id.clone(),
-            parser::Entity(ref id, ref mut value, ref indices, ref mut attrs)    => {
-                // while we're here, fix up and Hash values with default indices
-                match *value {
-                    parser::Hash(..) => {
-                        if indices.len() > 0 {
-                            add_default_indices(value, indices.iter());
-                        }
-                    },
-                    _ => {}
-                };
-                for &mut parser::Attr(_, ref mut value, ref indices) in attrs.iter_mut() {
-                    match *value {
-                        parser::Hash(..) => {
-                            if indices.len() > 0 {
-                                add_default_indices(value, indices.iter());
-                            }
-                        },
-                        _ => {}
-                    };
-                }
+impl<'a> Definition<'a> {
+    /// Copies whatever the entry still borrows from its source.
+    pub fn into_owned(self) -> Definition<'static> {
+        Definition {
+            id: self.id,
+            entry: self.entry.into_owned(),
+            span: self.span,
+            note: self.note,
+        }
+    }
+}
 
-                id.clone()
+pub fn compile<'a>(source: &'a str) -> Result<Resource<'a>, ParseError> {
+    let p = Parser::new(source);
+    let mut resource = Resource { definitions: vec![], comments: vec![], imports: vec![] };
+
+    // a comment is a note if the next entry starts on the line right after it
+    let mut pending: Option<(String, usize)> = None;
+    for (entry, span) in p.parse_spanned()? {
+        let mut note = None;
+        if let Some((text, end_line)) = pending.take() {
+            match entry {
+                parser::Comment(..) | parser::Import(..) => resource.comments.push(text),
+                _ if span.line <= end_line + 1 => note = Some(text),
+                _ => resource.comments.push(text),
             }
-        };
-        map.insert(id, entry);
+        }
+
+        if let parser::Comment(ref text) = entry {
+            pending = Some((text.trim().to_string(), span.end_line));
+        } else if let parser::Import(ref path) = entry {
+            resource.imports.push((path.to_string(), span));
+        } else if let Some((id, entry)) = compile_entry(entry) {
+            resource.definitions.push(Definition { id, entry, span, note });
+        }
+    }
+    if let Some((text, _)) = pending {
+        resource.comments.push(text);
     }
 
-    Ok(map)
+    Ok(resource)
 }
 
-
-fn add_default_indices<'r, I: Iterator<Item=&'r parser::Expr> + Clone>(value: &mut parser::Value, mut indices: I) {
-    match *value {
-        parser::Hash(ref mut map, _, ref mut def_index) => {
-            match indices.next() {
-                Some(idx) => {
-                    for (_k, v) in map.iter_mut() {
-                        add_default_indices(v, indices.clone());
-                    }
-                    *def_index = Some(Box::new(idx.clone()));
-                },
-                None => {}
-            }
-        },
-        _ => {}
+/// Compiles a resource that is used on its own, where an import has
+/// nothing to load it with.
+pub fn compile_standalone<'a>(source: &'a str) -> Result<Resource<'a>, ParseError> {
+    let resource = compile(source)?;
+    if let Some(&(_, span)) = resource.imports.first() {
+        return Err(ParseError {
+            kind: parser::UnresolvedImportError,
+            line: span.line,
+            col: span.col,
+        });
     }
+    Ok(resource)
 }
 
-pub type Env = HashMap<String, parser::Entry>;
-
-pub struct ResolveContext<'a> {
-    data: &'a data::Data,
-    env: &'a Env,
-    locals: Option<&'a data::Data>,
-    index: Option<String>,
-}
+fn compile_entry(mut entry: parser::Entry) -> Option<(String, parser::Entry)> {
+    let id = match entry {
+        parser::Comment(..) | parser::Import(..) => return None,
+        parser::Macro(ref id, _, _) => id.to_string(),
+        parser::Entity(ref id, ref mut value, ref indices, ref mut attrs)    => {
+            // while we're here, fix up and Hash values with default indices
+            if let parser::Hash(..) = *value {
+                add_default_indices(value, indices.iter());
+            }
+            for &mut parser::Attr(_, ref mut value, ref indices) in attrs.iter_mut() {
+                if let parser::Hash(..) = *value {
+                    add_default_indices(value, indices.iter());
+                }
+            }
 
-impl<'a> ResolveContext<'a> {
-    pub fn new(env: &'a Env, data: &'a data::Data) -> ResolveContext<'a> {
-        ResolveContext {
-            env: env,
-            data: data,
-            locals: None,
-            index: None,
+            id.to_string()
         }
-    }
+    };
+    Some((id, entry))
+}
 
-    fn with_locals(&'a self, locals: &'a data::Data) -> ResolveContext<'a> {
-        ResolveContext {
-            env: self.env,
-            data: self.data,
-            locals: Some(locals),
-            index: None,
-        }
-    }
 
-    fn with_index(&'a self, index: Option<String>) -> ResolveContext<'a> {
-        ResolveContext {
-            env: self.env,
-            data: self.data,
-            locals: self.locals,
-            index: index,
+fn add_default_indices<'r, 'a: 'r, I>(value: &mut parser::Value<'a>, mut indices: I)
+    where I: Iterator<Item=&'r parser::Expr<'a>> + Clone {
+    if let parser::Hash(ref mut map, _, ref mut def_index) = *value {
+        if let Some(idx) = indices.next() {
+            for (_k, v) in map.iter_mut() {
+                add_default_indices(v, indices.clone());
+            }
+            *def_index = Some(Box::new(idx.clone()));
         }
     }
 }
 
-pub type ResolveResult = Result<ResolveTarget, ResolveError>;
-
-pub enum ResolveTarget {
-    Entry(parser::Entry),
-    Value(parser::Value),
-    Data(data::Data)
-}
+pub type Env<'a> = HashMap<String, parser::Entry<'a>>;
 
 /// Errors that can occur when resolving a set of l20n resources into strings.
 /// These errors are cause by problems in the l20n file, or incorrect Data
@@ -131,277 +150,47 @@ pub enum ResolveError {
     /// A string tried to use another string in the l20n resource that did not
     /// exist.
     MissingIdent(String),
-}
-
-/// Resolve an L20n resource into Data.
-pub trait Resolve {
-
-    /// Resolves this value a step. It could resolve to another Value, or
-    /// resolve completely to a Data.
-    fn resolve(&self, ctx: &ResolveContext) -> ResolveResult;
-
-    /// Keeps resolving until a Data value is returned.
-    fn resolve_data(&self, ctx: &ResolveContext) -> Result<data::Data, ResolveError> {
-        match self.resolve(ctx) {
-            Ok(Data(d)) => Ok(d),
-            Ok(other) => other.resolve_data(ctx),
-            Err(e) => Err(e)
-        }
-    }
-}
-
-impl Resolve for ResolveTarget {
-    fn resolve(&self, ctx: &ResolveContext) -> ResolveResult {
-        match *self {
-            Data(ref d) => Ok(Data(d.clone())),
-            Entry(ref e) => e.resolve(ctx),
-            Value(ref v) => v.resolve(ctx)
-        }
-    }
-}
-
-impl Resolve for parser::Entry {
-    fn resolve(&self, _ctx: &ResolveContext) -> ResolveResult {
-        match *self {
-            parser::Entity(_, ref value, _, _) => {
-                Ok(Value(value.clone()))
-            }
-            _ => Ok(Data(data::Null))
-        }
-    }
-}
-
-impl Resolve for parser::Value {
-    fn resolve(&self, ctx: &ResolveContext) -> ResolveResult {
-        match *self {
-            parser::Str(ref s) => Ok(Data(data::Str(s.clone()))),
-            parser::ComplexStr(ref exprs) => {
-                let mut vec = Vec::with_capacity(exprs.len());
-                for expr in exprs.iter() {
-                    vec.push(match expr.resolve_data(ctx) {
-                        Ok(data::Str(s)) => s,
-                        Ok(data::Num(n)) => format!("{}", n),
-                        Ok(_) => return Err(WrongType),
-                        Err(e) => return Err(e)
-                    });
-                }
-                Ok(Data(data::Str(vec.concat())))
-            }
-            parser::Hash(ref map, ref def_key, ref def_index) => {
-                match ctx.index {
-                    Some(ref s) => match map.get(s) {
-                            Some(v) => return Ok(Value(v.clone())),
-                            None => {}
-                    },
-                    None => {}
-                };
-                match *def_key {
-                    Some(ref s) => match map.get(s) {
-                            Some(v) => return Ok(Value(v.clone())),
-                            None => {}
-                    },
-                    None => {}
-                };
-                match *def_index {
-                    Some(ref e) => match e.resolve_data(ctx) {
-                            Ok(data::Str(ref s)) => match map.get(s) {
-                                Some(v) => return Ok(Value(v.clone())),
-                                None => {}
-                            },
-                            Ok(_) => return Err(WrongType),
-                            Err(e) => return Err(e)
-                    },
-                    None => {}
-                };
-                Err(MissingIndex)
-            }
-        }
-    }
-}
-
-impl Resolve for parser::Expr {
-    fn resolve(&self, ctx: &ResolveContext) -> ResolveResult {
-        match *self {
-            parser::ValExpr(ref val) => Ok(Value(val.clone())),
-            parser::NumExpr(ref n) => Ok(Data(data::Num(*n))),
-            parser::BinExpr(ref left, ref op, ref right) => {
-                let left = try!(left.resolve_data(ctx));
-                let right = try!(right.resolve_data(ctx));
-                match (*op, left, right) {
-                    // math ops
-                    (parser::BiAdd, data::Num(l), data::Num(r)) => Ok(Data(data::Num(l + r))),
-                    (parser::BiSub, data::Num(l), data::Num(r)) => Ok(Data(data::Num(l - r))),
-                    (parser::BiMul, data::Num(l), data::Num(r)) => Ok(Data(data::Num(l * r))),
-                    (parser::BiDiv, data::Num(l), data::Num(r)) => Ok(Data(data::Num(l / r))),
-                    (parser::BiRem, data::Num(l), data::Num(r)) => Ok(Data(data::Num(l % r))),
-
-                    (parser::BiLt, data::Num(l), data::Num(r)) => Ok(Data(data::Bool(l < r))),
-                    (parser::BiLe, data::Num(l), data::Num(r)) => Ok(Data(data::Bool(l <= r))),
-                    (parser::BiGt, data::Num(l), data::Num(r)) => Ok(Data(data::Bool(l > r))),
-                    (parser::BiGe, data::Num(l), data::Num(r)) => Ok(Data(data::Bool(l >= r))),
-
-                    // logical ops
-                    (parser::BiAnd, data::Bool(l), data::Bool(r)) => Ok(Data(data::Bool(l && r))),
-                    (parser::BiOr, data::Bool(l), data::Bool(r)) => Ok(Data(data::Bool(l || r))),
-
-                    // equality ops. can be Num, Bool, or Str
-                    (parser::BiEq, data::Bool(l), data::Bool(r)) => Ok(Data(data::Bool(l == r))),
-                    (parser::BiEq, data::Str(l), data::Str(r)) => Ok(Data(data::Bool(l == r))),
-                    (parser::BiEq, data::Num(l), data::Num(r)) => Ok(Data(data::Bool(l == r))),
-                    (parser::BiNe, data::Bool(l), data::Bool(r)) => Ok(Data(data::Bool(l == r))),
-                    (parser::BiNe, data::Str(l), data::Str(r)) => Ok(Data(data::Bool(l == r))),
-                    (parser::BiNe, data::Num(l), data::Num(r)) => Ok(Data(data::Bool(l != r))),
-
-                    (_, _, _) => Err(WrongType)
-                }
-            }
-            parser::UnExpr(ref op, ref expr) => {
-                let expr = try!(expr.resolve_data(ctx));
-                match (*op, expr) {
-                    (parser::UnAdd, data::Num(n)) => Ok(Data(data::Num(n))),
-                    (parser::UnSub, data::Num(n)) => Ok(Data(data::Num(-n))),
-                    (parser::UnNot, data::Bool(b)) => Ok(Data(data::Bool(!b))),
-                    _ => Err(WrongType)
-                }
-            }
-            parser::VarExpr(ref name) => {
-                match ctx.locals.and_then(|locals| locals.get(name)) {
-                    Some(val) => return Ok(Data(val.clone())),
-                    _ => {}
-                };
-                match ctx.data.get(name) {
-                    Some(d) => Ok(Data(d.clone())),
-                    None => Err(MissingVar(name.clone()))
-                }
-            }
-            parser::IdentExpr(ref ident) => {
-                match ctx.env.get(ident) {
-                    Some(e) => Ok(Entry(e.clone())),
-                    None => Err(MissingIdent(ident.clone()))
-                }
-            }
-            parser::CondExpr(ref cond, ref consequent, ref alt) => {
-                match try!(cond.resolve_data(ctx)) {
-                    data::Bool(b) => {
-                        if b {
-                            consequent.resolve(ctx)
-                        } else {
-                            alt.resolve(ctx)
-                        }
-                    },
-                    _ => Err(WrongType)
-                }
-            }
-            parser::CallExpr(ref ident, ref args) => {
-                match **ident {
-                    parser::IdentExpr(ref ident) => {
-                        match ctx.env.get(ident) {
-                            Some(&parser::Macro(_, ref arg_names, ref body)) => {
-                                if args.len() == arg_names.len() {
-                                    let mut map = HashMap::new();
-                                    for (k, v) in arg_names.iter().zip(args.iter()) {
-                                        let name = match k {
-                                            &parser::VarExpr(ref name) => name.clone(),
-                                            // not a VarExpr would be the parser going nuts
-                                            _ => unreachable!()
-                                        };
-                                        let arg = match v.resolve_data(ctx) {
-                                            Ok(val) => val,
-                                            Err(e) => return Err(e)
-                                        };
-                                        map.insert(name, arg);
-                                    }
-                                    let locals = data::Map(map);
-                                    body.resolve(&ctx.with_locals(&locals))
-                                } else {
-                                    Err(WrongNumberOfArgs)
-                                }
-                            }
-                            Some(_) => Err(WrongType),
-                            None => Err(MissingIdent(ident.clone()))
-                        }
-                    },
-                    _ => panic!("CallExpr may only have IdentExpr in first spot")
-                }
-            }
-            parser::PropExpr(ref parent, ref prop, ref access) => {
-                let prop = match *access {
-                    parser::Computed => match prop.resolve_data(ctx) {
-                        Ok(data::Str(s)) => s,
-                        Ok(_) => return Err(WrongType),
-                        Err(e) => return Err(e)
-                    },
-                    parser::Static => match **prop {
-                        parser::IdentExpr(ref s) => s.clone(),
-                        _ => return Err(WrongType)
-                    }
-                };
-
-                match parent.resolve(ctx) {
-                    Ok(Data(data::Map(ref m))) => {
-                        match m.get(&prop) {
-                            Some(d) => Ok(Data(d.clone())),
-                            None => Err(MissingIndex)
-                        }
-                    },
-                    Ok(Entry(ref e)) => {
-                        match e.resolve(ctx) {
-                            Ok(Value(ref v)) => v.resolve(&ctx.with_index(Some(prop))),
-                            Ok(_) => Err(WrongType),
-                            Err(e) => Err(e)
-                        }
-                    },
-                    Ok(Value(ref v)) => {
-                        v.resolve(&ctx.with_index(Some(prop)))
-                    },
-                    Ok(_) => Err(WrongType),
-                    Err(e) => Err(e)
-                }
-            }
-            parser::Expr::AttrExpr(ref parent, ref prop, ref access) => {
-                let prop = match *access {
-                    parser::AccessType::Computed => match prop.resolve_data(ctx) {
-                        Ok(data::Str(s)) => s,
-                        Ok(_) => return Err(WrongType),
-                        Err(e) => return Err(e)
-                    },
-                    parser::AccessType::Static => match **prop {
-                        parser::Expr::IdentExpr(ref s) => s.clone(),
-                        _ => return Err(WrongType)
-                    }
-                };
-
-                match parent.resolve(ctx) {
-                    Ok(Entry(parser::Entry::Entity(_, _, _, ref attrs))) => {
-                        for &parser::Attr(ref id, ref value, _) in attrs.iter() {
-                            if *id == prop {
-                                return value.resolve(ctx)
-                            }
-                        }
-                        Err(MissingAttr)
-                    },
-                    Ok(_) => Err(WrongType),
-                    Err(e) => Err(e)
-                }
-            }
-            ref e => panic!("{:?} not yet implemented", e)
-        }
-    }
+    /// Divided, or took the remainder, by zero.
+    DivideByZero,
+    /// The result of arithmetic does not fit in an i64.
+    Overflow,
+    /// Used an expression that parses but cannot be resolved yet, such as
+    /// a global like `@os`.
+    Unsupported,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{compile, Resolve, ResolveContext};
-    use data::{Str, Null};
+    use super::compile;
+    use parser;
 
     #[test]
     fn test_compile() {
-        let map = compile("<hi 'hello world'>").unwrap();
-        let entity = &map["hi"];
-        let data = Null;
-        let ctx = ResolveContext::new(&map, &data);
-
-        assert_eq!(entity.resolve_data(&ctx).unwrap(), Str(String::from("hello world")));
+        let res = compile("<hi['a'] { a: 'hello', b: 'world' }>").unwrap();
+        assert_eq!(res.definitions.len(), 1);
+        assert_eq!(res.definitions[0].id, "hi");
+        // the entity's index is copied onto its Hash
+        match res.definitions[0].entry {
+            parser::Entity(_, parser::Hash(_, _, Some(ref index)), _, _) => {
+                assert_eq!(**index, parser::ValExpr(parser::Str("a".into())));
+            }
+            _ => panic!("hi should be an entity with a Hash"),
+        }
+    }
 
+    #[test]
+    fn test_compile_notes() {
+        let res = compile(r#"
+        /* Greetings */
+
+        /* Shown on the home page. */
+        <hi 'Hello'>
+        <bye 'Bye'>
+        /* trailing */
+        "#).unwrap();
+
+        let notes: Vec<_> = res.definitions.iter().map(|def| def.note.clone()).collect();
+        assert_eq!(notes, vec![Some(String::from("Shown on the home page.")), None]);
+        assert_eq!(res.comments, vec![String::from("Greetings"), String::from("trailing")]);
     }
 }
@@ -1,11 +1,19 @@
 use std::collections::HashMap;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
 
 use serde;
 
-use compiler::{Resolve, ResolveContext};
+use binary::{self, BinaryError};
 use compiler;
 use data;
+use import::{self, ResourceLoader};
+use ir;
+use langtag::LanguageTag;
+use loader::LoadError;
 use parser;
+use parser::Span;
 
 use self::LocalizeError::*;
 
@@ -38,7 +46,7 @@ impl Context {
 
     pub fn add_locale_resource(&mut self, name: String, res: String) -> Result<(), parser::ParseError> {
         let mut locale = self.locales.find_or_insert_with(name, |_| Locale::new());
-        let entities = try!(compiler::compile(res.as_slice()));
+        let entities = compiler::compile(res.as_slice())?;
         locale.resources.extend(entities.move_iter());
         Ok(())
     }
@@ -56,8 +64,84 @@ impl Context {
 */
 
 /// A Locale contains all the resources for a specific language.
+#[derive(Clone)]
 pub struct Locale {
-    resources: HashMap<String, parser::Entry>
+    resources: Vec<Stored>,
+    /// The resources lowered for resolving, built on first use so that
+    /// adding many resources in a row does not lower them each time.
+    program: OnceLock<ir::Program>,
+    /// The definition that is kept for each id: the resource it is in, and
+    /// where in it.
+    origins: HashMap<String, (usize, Span)>,
+    notes: HashMap<String, String>,
+    comments: Vec<String>,
+    duplicate_policy: DuplicatePolicy,
+    duplicates: Vec<Duplicate>,
+    language: Option<LanguageTag>,
+}
+
+/// A resource, as a Locale keeps it.
+#[derive(Clone)]
+enum Stored {
+    /// The source, compiled again whenever its entries are needed, so that
+    /// adding it does not copy every entry out of it.
+    Source(Arc<str>),
+    /// Entries with no source to go back to, such as those of a binary
+    /// resource, or ones that were rewritten.
+    Compiled(Vec<compiler::Definition<'static>>),
+}
+
+/// What a Locale does when a resource defines an id that was already
+/// defined, earlier in the same resource or in a previous one.
+#[derive(Clone)]
+pub enum DuplicatePolicy {
+    /// Reject the resource, leaving the Locale as it was. Only
+    /// `try_add_resource` can report that, with a `ResourceError::Duplicate`.
+    Error,
+    /// Keep the later definition, and report the Duplicate to the callback.
+    Warn(Arc<dyn Fn(&Duplicate) + Send + Sync>),
+    /// Keep the later definition. This is the default.
+    LastWins,
+    /// Keep the earlier definition.
+    FirstWins,
+}
+
+/// An id that was defined more than once.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Duplicate {
+    /// The id that was defined more than once.
+    pub id: String,
+    /// Where the id was defined before.
+    pub first: Span,
+    /// The resource the earlier definition is in, counting from 0 in the
+    /// order resources were added.
+    pub first_resource: usize,
+    /// Where the id was defined again.
+    pub second: Span,
+    /// The resource the later definition is in.
+    pub second_resource: usize,
+}
+
+/// Why a resource could not be added to a Locale with `try_add_resource`.
+#[derive(Debug)]
+pub enum ResourceError {
+    /// The resource is not valid L20n.
+    Parse(parser::ParseError),
+    /// The resource defines an id that was already defined, under
+    /// `DuplicatePolicy::Error`. The first one found is reported.
+    Duplicate(Duplicate),
+}
+
+impl From<parser::ParseError> for ResourceError {
+    fn from(e: parser::ParseError) -> ResourceError {
+        ResourceError::Parse(e)
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Locale {
+        Locale::new()
+    }
 }
 
 /// An enum of the various errors that can occur during localization.
@@ -79,17 +163,219 @@ impl Locale {
     /// Creates a new empty Locale.
     pub fn new() -> Locale {
         Locale {
-            resources: HashMap::new()
+            resources: vec![],
+            program: OnceLock::new(),
+            origins: HashMap::new(),
+            notes: HashMap::new(),
+            comments: vec![],
+            duplicate_policy: DuplicatePolicy::LastWins,
+            duplicates: vec![],
+            language: None,
+        }
+    }
+
+    /// Creates a new empty Locale for a language.
+    pub fn with_language(language: LanguageTag) -> Locale {
+        let mut locale = Locale::new();
+        locale.language = Some(language);
+        locale
+    }
+
+    /// The language the resources are in, if it was given.
+    pub fn language(&self) -> Option<&LanguageTag> {
+        self.language.as_ref()
+    }
+
+    /// Sets the language the resources are in.
+    pub fn set_language(&mut self, language: LanguageTag) {
+        self.language = Some(language);
+    }
+
+    /// Sets what happens when a resource defines an id that is already
+    /// defined.
+    pub fn set_duplicate_policy(&mut self, policy: DuplicatePolicy) {
+        self.duplicate_policy = policy;
+    }
+
+    /// All the ids that have been defined more than once, in the order they
+    /// were found, whichever policy was in use.
+    pub fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
+    }
+
+    /// The comment written directly above an entry, meant as a note for
+    /// translators.
+    pub fn note(&self, id: &str) -> Option<&str> {
+        self.notes.get(id).map(|s| s.as_str())
+    }
+
+    /// Whether an entity or macro is defined.
+    pub(crate) fn defines(&self, id: &str) -> bool {
+        self.origins.contains_key(id)
+    }
+
+    /// Calls `f` with every entity and macro that is kept, in the order they
+    /// were defined. Resources kept as source are compiled again for it.
+    pub(crate) fn with_definitions<R, F>(&self, f: F) -> R
+        where F: FnOnce(&[(&str, &parser::Entry)]) -> R
+    {
+        let recompiled: Vec<_> = self.resources.iter().map(|stored| match *stored {
+            Stored::Source(ref source) => recompile(source).definitions,
+            Stored::Compiled(..) => vec![],
+        }).collect();
+
+        let mut definitions = vec![];
+        for (i, stored) in self.resources.iter().enumerate() {
+            let compiled = match *stored {
+                Stored::Source(..) => &recompiled[i][..],
+                Stored::Compiled(ref compiled) => &compiled[..],
+            };
+            for def in compiled {
+                if self.origins.get(&def.id) == Some(&(i, def.span)) {
+                    definitions.push((&def.id[..], &def.entry));
+                }
+            }
         }
+        f(&definitions)
+    }
+
+    /// Comments not attached to any entry, such as section headers, from
+    /// every resource in the order they were added.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
     }
 
-    /// Add a L20n string resource, and it will be parsed.
+    /// Add a L20n string resource, and it will be parsed. A resource that
+    /// imports others is rejected, use `add_resource_from` for those.
+    ///
+    /// # Panics
+    ///
+    /// Under `DuplicatePolicy::Error`, if the resource defines an id that is
+    /// already defined. Use `try_add_resource` with that policy.
     pub fn add_resource(&mut self, res: &str) -> Result<(), parser::ParseError> {
-        let entities = try!(compiler::compile(res));
-        self.resources.extend(entities.into_iter());
+        match self.try_add_resource(res) {
+            Ok(()) => Ok(()),
+            Err(ResourceError::Parse(e)) => Err(e),
+            Err(ResourceError::Duplicate(dup)) => {
+                panic!("`{}` is defined more than once, use try_add_resource under DuplicatePolicy::Error", dup.id)
+            }
+        }
+    }
+
+    /// Add a L20n string resource, as `add_resource` does, and report an id
+    /// that is already defined under `DuplicatePolicy::Error`.
+    pub fn try_add_resource(&mut self, res: &str) -> Result<(), ResourceError> {
+        self.define(&compiler::compile_standalone(res)?).map_err(ResourceError::Duplicate)?;
+        self.resources.push(Stored::Source(Arc::from(res)));
+        Ok(())
+    }
+
+    /// Add the resource named `name`, read with `loader`, after the resources
+    /// it imports, and the ones they import in turn. Each is added once, even
+    /// if more than one resource imports it.
+    pub fn add_resource_from<L: ResourceLoader + ?Sized>(&mut self, loader: &L, name: &str) -> Result<(), LoadError> {
+        self.add_resources_from(loader, &[name.to_string()])
+    }
+
+    pub(crate) fn add_resources_from<L: ResourceLoader + ?Sized>(&mut self, loader: &L, names: &[String])
+                                                                 -> Result<(), LoadError> {
+        for (name, source) in import::resolve(loader, names)? {
+            let compiled = recompile(&source);
+            self.define(&compiled).map_err(|dup| LoadError::Duplicate(PathBuf::from(name), Box::new(dup)))?;
+            self.resources.push(Stored::Source(Arc::from(source)));
+        }
+        Ok(())
+    }
+
+    /// Add a resource precompiled with `compile_binary`, without parsing it.
+    /// The data is checked before it is used, so it may come from anywhere.
+    pub fn add_binary(&mut self, bytes: &[u8]) -> Result<(), BinaryError> {
+        self.add_compiled(binary::decode(bytes)?).map_err(|dup| BinaryError::Duplicate(Box::new(dup)))
+    }
+
+    pub(crate) fn add_compiled(&mut self, compiled: compiler::Resource<'static>) -> Result<(), Duplicate> {
+        self.define(&compiled)?;
+        self.resources.push(Stored::Compiled(compiled.definitions));
+        Ok(())
+    }
+
+    /// Records the definitions of the resource about to be added: which one
+    /// is kept for each id, and the duplicates, unless the policy rejects
+    /// the resource.
+    fn define(&mut self, compiled: &compiler::Resource) -> Result<(), Duplicate> {
+        let resource = self.resources.len();
+
+        let first_wins = matches!(self.duplicate_policy, DuplicatePolicy::FirstWins);
+        let mut found = vec![];
+        let mut defined = HashMap::with_capacity(compiled.definitions.len());
+        for def in &compiled.definitions {
+            let previous = defined.get(&def.id[..]).or_else(|| self.origins.get(&def.id));
+            if let Some(&(first_resource, first)) = previous {
+                found.push(Duplicate {
+                    id: def.id.clone(),
+                    first,
+                    first_resource,
+                    second: def.span,
+                    second_resource: resource,
+                });
+            }
+            // under FirstWins a later definition is dropped, so it is not
+            // the one a third would duplicate
+            if previous.is_none() || !first_wins {
+                defined.insert(&def.id[..], (resource, def.span));
+            }
+        }
+
+        match self.duplicate_policy {
+            DuplicatePolicy::Error => if let Some(dup) = found.first() {
+                return Err(dup.clone());
+            },
+            DuplicatePolicy::Warn(ref warn) => for dup in &found {
+                warn(dup);
+            },
+            DuplicatePolicy::LastWins | DuplicatePolicy::FirstWins => {}
+        }
+
+        self.origins.reserve(compiled.definitions.len());
+        for def in &compiled.definitions {
+            if first_wins && self.origins.contains_key(&def.id) {
+                continue;
+            }
+            match def.note {
+                Some(ref note) => self.notes.insert(def.id.clone(), note.clone()),
+                None => self.notes.remove(&def.id),
+            };
+            self.origins.insert(def.id.clone(), (resource, def.span));
+        }
+        self.program = OnceLock::new();
+        self.comments.extend(compiled.comments.iter().cloned());
+        self.duplicates.extend(found);
         Ok(())
     }
 
+    /// Rewrites every entry with `f`, as if the resources had been added
+    /// that way.
+    pub(crate) fn rewrite<F: FnMut(&mut parser::Entry<'static>)>(&mut self, mut f: F) {
+        let origins = &self.origins;
+        for (i, stored) in self.resources.iter_mut().enumerate() {
+            let mut compiled = match *stored {
+                Stored::Source(ref source) => recompile(source).into_owned().definitions,
+                Stored::Compiled(ref mut compiled) => mem::take(compiled),
+            };
+            // the entries that are not kept will never be needed again
+            compiled.retain(|def| origins.get(&def.id) == Some(&(i, def.span)));
+            for def in &mut compiled {
+                f(&mut def.entry);
+            }
+            *stored = Stored::Compiled(compiled);
+        }
+        self.program = OnceLock::new();
+    }
+
+    fn program(&self) -> &ir::Program {
+        self.program.get_or_init(|| self.with_definitions(ir::Program::new))
+    }
+
     /// Resolves all the resouces into Strings, and returns a Deserialize
     /// object of your choosing.
     pub fn localize<T: serde::Deserialize>(&self) -> LocalizeResult<T> {
@@ -103,26 +389,42 @@ impl Locale {
         D: serde::Serialize
         >(&self, data: D) -> LocalizeResult<T> {
         let mut enc = data::Encoder::new();
-        match data.serialize(&mut enc) {
-            Err(e) => return Err(EncodeError(e)),
-            _ => {}
+        if let Err(e) = data.serialize(&mut enc) {
+            return Err(EncodeError(e));
         }
         self.localize_data_raw(enc.data().unwrap())
     }
 
+    /// Resolves a single entity into a String, using the environment Data
+    /// you provide.
+    pub fn localize_entity<D: serde::Serialize>(&self, id: &str, data: D) -> LocalizeResult<String> {
+        let mut enc = data::Encoder::new();
+        if let Err(e) = data.serialize(&mut enc) {
+            return Err(EncodeError(e));
+        }
+        let data = enc.data().unwrap();
+
+        let entry = match self.program().lookup(id) {
+            Some(entry) => entry,
+            None => return Err(ResolveError(compiler::ResolveError::MissingIdent(id.to_string())))
+        };
+        match self.program().resolve(entry, &data) {
+            Ok(data::Str(s)) => Ok(s),
+            Ok(data::Num(n)) => Ok(n.to_string()),
+            Ok(_) => Err(ResolveError(compiler::ResolveError::WrongType)),
+            Err(e) => Err(ResolveError(e))
+        }
+    }
+
     fn localize_data_raw<T: serde::Deserialize>(&self, data: data::Data) -> LocalizeResult<T> {
         let mut map = HashMap::new();
-        let ctx = ResolveContext::new(&self.resources, &data);
-        for (id, entry) in &self.resources {
-            match entry {
-                &parser::Entity(..) => {
-                    map.insert(id.clone(), match entry.resolve_data(&ctx) {
-                        Ok(d) => d,
-                        Err(e) => return Err(ResolveError(e))
-                    });
-                }
-                _ => () // dont localize comments or macros
-            }
+        // dont localize macros
+        let program = self.program();
+        for (id, entry) in program.entities() {
+            map.insert(id.to_string(), match program.resolve(entry, &data) {
+                Ok(d) => d,
+                Err(e) => return Err(ResolveError(e))
+            });
         }
 
         let mut dec = data::Decoder::new(data::Data::Map(map));
@@ -133,12 +435,22 @@ impl Locale {
     }
 }
 
+/// Compiles a resource that is known to compile, because it was compiled
+/// before.
+fn recompile<'a>(source: &'a str) -> compiler::Resource<'a> {
+    compiler::compile(source).expect("a resource that compiled once compiles again")
+}
+
 #[cfg(test)]
 mod tests {
 
     use std::collections::HashMap;
 
-    use super::Locale;
+    use super::{Locale, LocalizeError, DuplicatePolicy, Duplicate, ResourceError};
+    use compiler::ResolveError::{DivideByZero, Overflow};
+    use binary::{compile_binary, BinaryError};
+    use parser::Span;
+    use std::sync::{Arc, Mutex};
 
     /* custom serde impls are hard
     use serde;
@@ -176,4 +488,162 @@ mod tests {
         assert_eq!(t["mail"], "Email in your inbox: too many.");
     }
 
+    #[test]
+    fn test_ne() {
+        let mut locale = Locale::new();
+        locale.add_resource(r#"
+        <brand 'Rust'>
+        <str '{{ brand != "Rust" ? "other" : "same" }}'>
+        <big($n) { $n > 2 }>
+        <bool '{{ big($num) != big(1) ? "other" : "same" }}'>
+        "#).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("num", 3);
+
+        let t: HashMap<String, String> = locale.localize_data(data).unwrap();
+
+        assert_eq!(t["str"], "same");
+        assert_eq!(t["bool"], "other");
+    }
+
+    fn hi(locale: &Locale) -> String {
+        let t: HashMap<String, String> = locale.localize().unwrap();
+        t["hi"].clone()
+    }
+
+    #[test]
+    fn test_duplicates_last_wins() {
+        let mut locale = Locale::new();
+        locale.add_resource("<hi 'Hello'>\n<hi 'Hey'>").unwrap();
+        locale.add_resource("<bye 'Bye'>\n<hi 'Hi'>").unwrap();
+        assert_eq!(hi(&locale), "Hi");
+        assert_eq!(locale.duplicates(), &[
+            Duplicate {
+                id: String::from("hi"),
+                first: Span { line: 0, col: 1, end_line: 0, end_col: 12 },
+                first_resource: 0,
+                second: Span { line: 1, col: 2, end_line: 1, end_col: 11 },
+                second_resource: 0,
+            },
+            Duplicate {
+                id: String::from("hi"),
+                first: Span { line: 1, col: 2, end_line: 1, end_col: 11 },
+                first_resource: 0,
+                second: Span { line: 1, col: 2, end_line: 1, end_col: 10 },
+                second_resource: 1,
+            },
+        ][..]);
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        let mut locale = Locale::new();
+        locale.add_resource("<x '{{ 1 / 0 }}'>\n<y '{{ 9223372036854775807 + 1 }}'>\n<z 'z'>").unwrap();
+        assert!(matches!(locale.localize_entity("x", ()), Err(LocalizeError::ResolveError(DivideByZero))));
+        assert!(matches!(locale.localize_entity("y", ()), Err(LocalizeError::ResolveError(Overflow))));
+        assert_eq!(locale.localize_entity("z", ()).unwrap(), "z");
+    }
+
+    #[test]
+    fn test_redefinition_after_localize() {
+        let mut locale = Locale::new();
+        locale.add_resource("<brand 'Rust'>\n<hi 'Hello, {{ brand }}'>").unwrap();
+        // resources are only lowered when they are first localized
+        assert!(locale.program.get().is_none());
+        assert_eq!(hi(&locale), "Hello, Rust");
+        assert!(locale.program.get().is_some());
+        locale.add_resource("<brand 'Rust Lang'>").unwrap();
+        assert!(locale.program.get().is_none());
+        assert_eq!(hi(&locale), "Hello, Rust Lang");
+    }
+
+    #[test]
+    fn test_add_binary() {
+        let bytes = compile_binary("/* The greeting. */\n<hi 'Hello'>").unwrap();
+        let mut locale = Locale::new();
+        locale.add_binary(&bytes).unwrap();
+        assert_eq!(hi(&locale), "Hello");
+        assert_eq!(locale.note("hi"), Some("The greeting."));
+
+        locale.set_duplicate_policy(DuplicatePolicy::Error);
+        match locale.add_binary(&bytes) {
+            Err(BinaryError::Duplicate(dup)) => assert_eq!((dup.first_resource, dup.second_resource), (0, 1)),
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_duplicates_first_wins() {
+        let mut locale = Locale::new();
+        locale.set_duplicate_policy(DuplicatePolicy::FirstWins);
+        locale.add_resource("<hi 'Hello'>").unwrap();
+        locale.add_resource("<hi 'Hi'>\n<hi 'Hey'>").unwrap();
+        assert_eq!(hi(&locale), "Hello");
+        // both are reported against the one that was kept
+        let firsts: Vec<_> = locale.duplicates().iter().map(|dup| (dup.first_resource, dup.first)).collect();
+        assert_eq!(firsts, vec![(0, Span { line: 0, col: 1, end_line: 0, end_col: 12 }); 2]);
+    }
+
+    #[test]
+    fn test_duplicates_error() {
+        let mut locale = Locale::new();
+        locale.set_duplicate_policy(DuplicatePolicy::Error);
+        locale.add_resource("<hi 'Hello'>").unwrap();
+        match locale.try_add_resource("<bye 'Bye'>\n<hi 'Hi'>") {
+            Err(ResourceError::Duplicate(dup)) => assert_eq!(dup, Duplicate {
+                id: String::from("hi"),
+                first: Span { line: 0, col: 1, end_line: 0, end_col: 12 },
+                first_resource: 0,
+                second: Span { line: 1, col: 2, end_line: 1, end_col: 10 },
+                second_resource: 1,
+            }),
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+        // the rejected resource was not added at all
+        let t: HashMap<String, String> = locale.localize().unwrap();
+        assert_eq!(t.len(), 1);
+        assert_eq!(t["hi"], "Hello");
+    }
+
+    #[test]
+    #[should_panic(expected = "try_add_resource")]
+    fn test_duplicates_error_add_resource() {
+        let mut locale = Locale::new();
+        locale.set_duplicate_policy(DuplicatePolicy::Error);
+        let _ = locale.add_resource("<hi 'Hello'>\n<hi 'Hi'>");
+    }
+
+    #[test]
+    fn test_duplicates_warn() {
+        let warned = Arc::new(Mutex::new(vec![]));
+        let sink = warned.clone();
+        let mut locale = Locale::new();
+        locale.set_duplicate_policy(DuplicatePolicy::Warn(Arc::new(move |dup| {
+            sink.lock().unwrap().push(dup.id.clone());
+        })));
+        locale.add_resource("<hi 'Hello'>\n<hi 'Hi'>").unwrap();
+        assert_eq!(hi(&locale), "Hi");
+        assert_eq!(*warned.lock().unwrap(), vec![String::from("hi")]);
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut locale = Locale::new();
+        locale.add_resource(r#"
+        /* Navigation */
+
+        /* The greeting on the home page. */
+        <hi 'Hello'>
+        <bye 'Bye'>
+        "#).unwrap();
+        assert_eq!(locale.note("hi"), Some("The greeting on the home page."));
+        assert_eq!(locale.note("bye"), None);
+        assert_eq!(locale.comments(), &[String::from("Navigation")][..]);
+
+        // a redefinition without a note drops the old one
+        locale.add_resource("<hi 'Hi'>").unwrap();
+        assert_eq!(locale.note("hi"), None);
+    }
+
 }
@@ -0,0 +1,167 @@
+//! Android `strings.xml` resources, from L20n.
+//!
+//! | L20n                                         | Android                              |
+//! |----------------------------------------------|--------------------------------------|
+//! | `<hello "Hi">`                               | `<string name="hello">Hi</string>`   |
+//! | `<hi "Hi, {{ $name }}">`                     | `<string name="hi">Hi, %1$s</string>` |
+//! | `<hi "Hi from {{ brand }}">`                 | `brand`'s value, in place            |
+//! | `title: "Hi"` (an attribute of `hello`)      | `<string name="hello.title">Hi</string>` |
+//! | `<emails[plural($n)] { one: ..., *other: ... }>` | `<plurals name="emails"><item quantity="one">...</item>...</plurals>` |
+//! | `/* note */`                                 | `<!-- note -->`                      |
+//!
+//! Each entity is resolved as far as it can be without data: references to
+//! other entities are replaced by their values, and variables become
+//! positional format arguments, numbered in the order they first appear.
+//! The count of a plural is the first argument, and a number (`%1$d`); the
+//! others are strings (`%2$s`). A plural always has an `other` item, which
+//! is the default of the Hash if it has no `other` key.
+//!
+//! # Issues
+//!
+//! - `Macro`, `Import`: left out.
+//! - `Expression`: an expression other than a variable, a reference or a
+//!   literal, such as a global or a macro call, or a Hash index that depends
+//!   on one. The entity, or attribute, is not exportable and is left out.
+//! - `Hash`: a Hash with an index other than `plural($n)`. Only its default
+//!   value is exported.
+
+use ast::Entry;
+use convert::Issue;
+use convert::native::{natives, Body, Item, Native};
+
+/// Converts L20n entries into an Android `strings.xml` resource.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::android;
+///
+/// let entries = l20n::parse("
+/// <brand \"Mail\">
+/// <emails[plural($n)] {
+///   one: \"One email in {{ brand }}\",
+///  *other: \"{{ $n }} emails in {{ brand }}\"
+/// }>
+/// ").unwrap();
+/// let (xml, issues) = android::from_l20n(&entries);
+/// assert!(issues.is_empty());
+/// assert_eq!(xml, r#"<?xml version="1.0" encoding="utf-8"?>
+/// <resources>
+///     <string name="brand">Mail</string>
+///     <plurals name="emails">
+///         <item quantity="one">One email in Mail</item>
+///         <item quantity="other">%1$d emails in Mail</item>
+///     </plurals>
+/// </resources>
+/// "#);
+/// ```
+pub fn from_l20n(entries: &[Entry]) -> (String, Vec<Issue>) {
+    let (items, issues) = natives(entries);
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<resources>\n");
+    for item in &items {
+        match *item {
+            Item::Comment(ref text) => {
+                out.push_str(&format!("    <!-- {} -->\n", text.replace("--", "- -")));
+            }
+            Item::Native(ref native) => write_native(&mut out, native),
+        }
+    }
+    out.push_str("</resources>\n");
+    (out, issues)
+}
+
+fn write_native(out: &mut String, native: &Native) {
+    match native.body {
+        Body::Single(ref parts) => {
+            let text = escape(&native.format(parts, 's'));
+            // strings without arguments are not formatted
+            let formatted = if native.args.is_empty() && text.contains('%') { " formatted=\"false\"" } else { "" };
+            out.push_str(&format!("    <string name=\"{}\"{}>{}</string>\n", native.name, formatted, text));
+        }
+        Body::Plural(ref variants) => {
+            out.push_str(&format!("    <plurals name=\"{}\">\n", native.name));
+            for (category, parts) in variants {
+                out.push_str(&format!("        <item quantity=\"{}\">{}</item>\n",
+                                      category, escape(&native.format(parts, 's'))));
+            }
+            out.push_str("    </plurals>\n");
+        }
+    }
+}
+
+/// Escapes text the way `aapt` reads it: XML, then quotes, backslashes and
+/// a leading `@` or `?`, and space that would otherwise be collapsed.
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    let mut previous = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '@' | '?' if previous.is_none() => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' ' if previous.is_none() || previous == Some(' ') || chars.peek().is_none() => out.push_str("\\u0020"),
+            c => out.push(c),
+        }
+        previous = Some(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use convert::{Issue, IssueKind};
+    use parser::parse;
+    use super::from_l20n;
+
+    #[test]
+    fn test_from_l20n() {
+        let entries = parse("\
+/* Greetings & such */
+<brand \"Mail\" title: \"{{ brand }} app\">
+<hi \"Hi {{ $name }}, it's 100% {{ brand::title }}!\"
+  tip: \"@home  ok \">
+<plural($n) { $n == 1 ? \"one\" : \"other\" }>
+<emails[plural($n)] { one: \"{{ $name }}: one\", *many: \"{{ $name }}: {{ $n }}\" }>
+<os \"{{ @os }}\">
+<call \"{{ plural(1) }}\">
+<gender[$g] { *he: \"He\", she: \"She\" }>
+<hashed[@os] { *mac: \"Mac\" }>
+<loop \"{{ loop }}\">
+<percent \"50%\">
+").unwrap();
+        let (xml, issues) = from_l20n(&entries);
+        assert_eq!(issues, vec![
+            Issue { id: "plural".into(), kind: IssueKind::Macro },
+            Issue { id: "os".into(), kind: IssueKind::Expression("@os".into()) },
+            Issue { id: "call".into(), kind: IssueKind::Expression("plural(1)".into()) },
+            Issue { id: "gender".into(), kind: IssueKind::Hash },
+            Issue { id: "hashed".into(), kind: IssueKind::Expression("@os".into()) },
+            Issue { id: "loop".into(), kind: IssueKind::Expression("loop".into()) },
+        ]);
+        assert_eq!(xml, r#"<?xml version="1.0" encoding="utf-8"?>
+<resources>
+    <!-- Greetings & such -->
+    <string name="brand">Mail</string>
+    <string name="brand.title">Mail app</string>
+    <string name="hi">Hi %1$s, it\'s 100%% Mail app!</string>
+    <string name="hi.tip">\@home \u0020ok\u0020</string>
+    <plurals name="emails">
+        <item quantity="one">%2$s: one</item>
+        <item quantity="many">%2$s: %1$d</item>
+        <item quantity="other">%2$s: %1$d</item>
+    </plurals>
+    <string name="gender">He</string>
+    <string name="percent" formatted="false">50%</string>
+</resources>
+"#);
+    }
+}
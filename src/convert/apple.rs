@@ -0,0 +1,181 @@
+//! Apple `.strings` and `.stringsdict` files, from L20n.
+//!
+//! | L20n                                         | Apple                                |
+//! |----------------------------------------------|--------------------------------------|
+//! | `<hello "Hi">`                               | `"hello" = "Hi";`                    |
+//! | `<hi "Hi, {{ $name }}">`                     | `"hi" = "Hi, %1$@";`                 |
+//! | `<hi "Hi from {{ brand }}">`                 | `brand`'s value, in place            |
+//! | `title: "Hi"` (an attribute of `hello`)      | `"hello.title" = "Hi";`              |
+//! | `<emails[plural($n)] { one: ..., *other: ... }>` | an `NSStringPluralRuleType` entry in the `.stringsdict` |
+//! | `/* note */`                                 | `/* note */`                         |
+//!
+//! Entities are resolved as for Android (see `convert::android`), with
+//! string arguments as objects (`%2$@`). Plurals go in the `.stringsdict`,
+//! with a format of `%#@n@` for a count of `$n`, and everything else in the
+//! `.strings`. A comment goes with the entity after it.
+//!
+//! # Issues
+//!
+//! - `Macro`, `Import`: left out.
+//! - `Expression`: an expression other than a variable, a reference or a
+//!   literal, such as a global or a macro call, or a Hash index that depends
+//!   on one. The entity, or attribute, is not exportable and is left out.
+//! - `Hash`: a Hash with an index other than `plural($n)`. Only its default
+//!   value is exported.
+
+use ast::Entry;
+use convert::Issue;
+use convert::native::{natives, Body, Item};
+
+/// The files L20n entries are exported to.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Files {
+    /// The `.strings` file, of everything but plurals.
+    pub strings: String,
+    /// The `.stringsdict` file, of plurals.
+    pub stringsdict: String,
+}
+
+/// Converts L20n entries into `.strings` and `.stringsdict` files.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::apple;
+///
+/// let entries = l20n::parse("
+/// <hi \"Hi, {{ $name }}!\">
+/// <emails[plural($n)] { one: \"One email\", *other: \"{{ $n }} emails\" }>
+/// ").unwrap();
+/// let (files, issues) = apple::from_l20n(&entries);
+/// assert!(issues.is_empty());
+/// assert_eq!(files.strings, "\"hi\" = \"Hi, %1$@!\";\n");
+/// assert!(files.stringsdict.contains("<string>%#@n@</string>"));
+/// assert!(files.stringsdict.contains("<key>other</key>\n            <string>%1$d emails</string>"));
+/// ```
+pub fn from_l20n(entries: &[Entry]) -> (Files, Vec<Issue>) {
+    let (items, issues) = natives(entries);
+    let mut strings = String::new();
+    let mut dict = String::new();
+    let mut comments: Vec<&str> = vec![];
+    for item in &items {
+        let native = match *item {
+            Item::Comment(ref text) => {
+                comments.push(text);
+                continue;
+            }
+            Item::Native(ref native) => native,
+        };
+        match native.body {
+            Body::Single(ref parts) => {
+                for comment in comments.drain(..) {
+                    strings.push_str(&format!("/* {} */\n", comment.replace("*/", "* /")));
+                }
+                strings.push_str(&format!("\"{}\" = \"{}\";\n",
+                                          escape(&native.name), escape(&native.format(parts, '@'))));
+            }
+            Body::Plural(ref variants) => {
+                for comment in comments.drain(..) {
+                    dict.push_str(&format!("    <!-- {} -->\n", comment.replace("--", "- -")));
+                }
+                let count = &native.args[0];
+                dict.push_str(&format!("    <key>{}</key>\n    <dict>\n", xml(&native.name)));
+                dict.push_str("        <key>NSStringLocalizedFormatKey</key>\n");
+                dict.push_str(&format!("        <string>%#@{}@</string>\n", xml(count)));
+                dict.push_str(&format!("        <key>{}</key>\n        <dict>\n", xml(count)));
+                dict.push_str("            <key>NSStringFormatSpecTypeKey</key>\n");
+                dict.push_str("            <string>NSStringPluralRuleType</string>\n");
+                dict.push_str("            <key>NSStringFormatValueTypeKey</key>\n");
+                dict.push_str("            <string>d</string>\n");
+                for (category, parts) in variants {
+                    dict.push_str(&format!("            <key>{}</key>\n            <string>{}</string>\n",
+                                           category, xml(&native.format(parts, '@'))));
+                }
+                dict.push_str("        </dict>\n    </dict>\n");
+            }
+        }
+    }
+    for comment in comments {
+        strings.push_str(&format!("/* {} */\n", comment.replace("*/", "* /")));
+    }
+    let stringsdict = format!("\
+<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\">
+<dict>
+{}</dict>
+</plist>
+", dict);
+    (Files { strings, stringsdict }, issues)
+}
+
+/// Escapes text inside the quotes of a `.strings` file.
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use convert::{Issue, IssueKind};
+    use parser::parse;
+    use super::from_l20n;
+
+    #[test]
+    fn test_from_l20n() {
+        let entries = parse("\
+/* Greetings */
+<brand \"Mail\">
+<hi \"Hi {{ $name }}, \\\"{{ brand }}\\\" is 100% yours\" tip: \"a\\\\b\">
+/* Counted */
+<emails[plural($count)] { one: \"{{ $name }} & one\", *other: \"{{ $count }} < {{ $name }}\" }>
+<os \"{{ @os }}\">
+/* trailing */
+").unwrap();
+        let (files, issues) = from_l20n(&entries);
+        assert_eq!(issues, vec![Issue { id: "os".into(), kind: IssueKind::Expression("@os".into()) }]);
+        assert_eq!(files.strings, "\
+/* Greetings */
+\"brand\" = \"Mail\";
+\"hi\" = \"Hi %1$@, \\\"Mail\\\" is 100%% yours\";
+\"hi.tip\" = \"a\\\\b\";
+/* trailing */
+");
+        assert_eq!(files.stringsdict, r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <!-- Counted -->
+    <key>emails</key>
+    <dict>
+        <key>NSStringLocalizedFormatKey</key>
+        <string>%#@count@</string>
+        <key>count</key>
+        <dict>
+            <key>NSStringFormatSpecTypeKey</key>
+            <string>NSStringPluralRuleType</string>
+            <key>NSStringFormatValueTypeKey</key>
+            <string>d</string>
+            <key>one</key>
+            <string>%2$@ &amp; one</string>
+            <key>other</key>
+            <string>%1$d &lt; %2$@</string>
+        </dict>
+    </dict>
+</dict>
+</plist>
+"#);
+    }
+}
@@ -0,0 +1,1118 @@
+//! Fluent (`.ftl`) resources.
+//!
+//! | Fluent                          | L20n                                 |
+//! |---------------------------------|--------------------------------------|
+//! | `hello = Hi`                    | `<hello "Hi">`                       |
+//! | `-brand = Firefox` (a term)     | `<_brand "Firefox">`                 |
+//! | `.title = Hi` (an attribute)    | `title: "Hi"`                        |
+//! | `{ $name }`                     | `{{ $name }}`                        |
+//! | `{ hello }`, `{ -brand }`       | `{{ hello }}`, `{{ _brand }}`        |
+//! | `{ hello.title }`               | `{{ hello::title }}`                 |
+//! | `{ $n -> [one] ... *[other] }`  | `<id[plural($n)] { one: ..., *other: ... }>` |
+//! | `{ $g -> [he] ... *[they] }`    | `<id[$g] { he: ..., *they: ... }>`   |
+//! | `# note`                        | `/* note */`                         |
+//!
+//! A select expression becomes a Hash when it is the only one in its
+//! pattern; the text around it is repeated in every value. Selecting on a
+//! variable with plural category keys becomes `plural($n)`, as described in
+//! `convert`.
+//!
+//! `-` in names becomes `_`, since L20n names cannot have one.
+//!
+//! # Issues
+//!
+//! From Fluent:
+//!
+//! - `Select`: a select expression next to another one, inside a variant,
+//!   or with keys that are not L20n names, such as `[0]`. Its default
+//!   variant is kept.
+//! - `Function`: a function call, replaced by its first argument, or a term
+//!   called with arguments, whose arguments are dropped.
+//! - `Renamed`: a message, term, attribute or variable with `-` in its name.
+//! - `Syntax`: an entry that could not be parsed, which is left out.
+//!
+//! To Fluent:
+//!
+//! - `Macro`, `Import`: left out.
+//! - `Expression`: an expression other than a variable, a reference or a
+//!   literal, or a Hash index other than a variable, `plural($n)` or a term's
+//!   attribute. The entity is left out.
+//! - `Hash`: a Hash without an index, or with a key that is not a Fluent
+//!   name. Only its default value is kept.
+//! - `Renamed`: a name that is not a valid Fluent identifier.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use ast::*;
+use convert::{Issue, IssueKind, Pieces, PLURAL, PLURAL_CATEGORIES};
+use serializer::serialize_expr;
+
+/// Converts a Fluent resource into L20n entries.
+///
+/// Entries that cannot be parsed are left out and reported, and the rest
+/// of the resource is still converted.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::ftl;
+///
+/// let (entries, issues) = ftl::to_l20n("
+/// -brand = Firefox
+/// ## Shown on the start page.
+/// welcome = Welcome to { -brand }, { $name }!
+///     .title = Welcome
+/// ");
+/// assert!(issues.is_empty());
+/// assert_eq!(l20n::serialize(&entries), "\
+/// <_brand \"Firefox\">
+/// /* Shown on the start page. */
+/// <welcome \"Welcome to {{ _brand }}, {{ $name }}!\" title: \"Welcome\">
+/// ");
+/// ```
+pub fn to_l20n(source: &str) -> (Vec<Entry<'static>>, Vec<Issue>) {
+    let source = source.replace("\r\n", "\n");
+    let mut parser = Parser { chars: source.chars().collect(), pos: 0 };
+    let mut importer = Importer { issues: vec![] };
+    let mut entries = vec![];
+
+    // consecutive comment lines of the same level make one comment
+    let mut comment: Option<(usize, String)> = None;
+    while let Some(c) = parser.peek() {
+        if c == '#' {
+            if let Some((level, text)) = parser.comment_line() {
+                match comment {
+                    Some((l, ref mut t)) if l == level => {
+                        t.push('\n');
+                        t.push_str(&text);
+                        continue;
+                    }
+                    _ => {}
+                }
+                if let Some((_, text)) = comment.replace((level, text)) {
+                    entries.push(comment_entry(text));
+                }
+                continue;
+            }
+        }
+        if let Some((_, text)) = comment.take() {
+            entries.push(comment_entry(text));
+        }
+
+        let start = parser.pos;
+        if parser.blank_line() {
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '-' {
+            if let Ok(message) = parser.message() {
+                entries.push(importer.entry(message));
+                continue;
+            }
+            parser.pos = start;
+        }
+        importer.issues.push(Issue::new("", IssueKind::Syntax(parser.line())));
+        parser.skip_junk();
+    }
+    if let Some((_, text)) = comment {
+        entries.push(comment_entry(text));
+    }
+    (entries, importer.issues)
+}
+
+fn comment_entry(text: String) -> Entry<'static> {
+    Comment(Cow::Owned(format!(" {} ", text)))
+}
+
+/// Converts L20n entries into a Fluent resource.
+///
+/// Entries that cannot be converted are left out and reported.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::{ftl, IssueKind};
+///
+/// let entries = l20n::parse(r#"
+/// <plural($n) { $n == 1 ? "one" : "other" }>
+/// <emails[plural($n)] { one: "One email", *other: "{{ $n }} emails" }>
+/// "#).unwrap();
+/// let (ftl, issues) = ftl::from_l20n(&entries);
+/// assert_eq!(ftl, "\
+/// emails =
+///     { $n ->
+///         [one] One email
+///        *[other] { $n } emails
+///     }
+/// ");
+/// assert_eq!(issues[0].id, "plural");
+/// assert_eq!(issues[0].kind, IssueKind::Macro);
+/// ```
+pub fn from_l20n(entries: &[Entry]) -> (String, Vec<Issue>) {
+    let mut exporter = Exporter { issues: vec![] };
+    let mut out = String::new();
+    for entry in entries {
+        match *entry {
+            Entity(ref id, ref value, ref indices, ref attrs) => {
+                match exporter.entity(id, value, indices, attrs) {
+                    Ok(message) => {
+                        out.push_str(&message);
+                        out.push('\n');
+                    }
+                    Err(kind) => exporter.issues.push(Issue::new(&id[..], kind)),
+                }
+            }
+            Comment(ref text) => {
+                for line in text.trim().lines().map(str::trim) {
+                    out.push('#');
+                    if !line.is_empty() {
+                        out.push(' ');
+                        out.push_str(line);
+                    }
+                    out.push('\n');
+                }
+            }
+            Macro(ref id, ..) => exporter.issues.push(Issue::new(&id[..], IssueKind::Macro)),
+            Import(ref path) => exporter.issues.push(Issue::new(&path[..], IssueKind::Import)),
+        }
+    }
+    (out, exporter.issues)
+}
+
+/// A message or term, as parsed.
+struct Message {
+    id: String,
+    term: bool,
+    value: Option<Vec<Element>>,
+    attrs: Vec<(String, Vec<Element>)>,
+}
+
+#[derive(Clone)]
+enum Element {
+    Text(String),
+    Placeable(Expression),
+}
+
+#[derive(Clone)]
+enum Expression {
+    Str(String),
+    Num(String),
+    Var(String),
+    Message(String, Option<String>),
+    /// A term reference, with its attribute and whether it has arguments.
+    Term(String, Option<String>, bool),
+    /// A function call, with its positional arguments and whether it has
+    /// named ones.
+    Function(String, Vec<Expression>, bool),
+    Select(Box<Expression>, Vec<Variant>),
+}
+
+#[derive(Clone)]
+struct Variant {
+    key: String,
+    default: bool,
+    value: Vec<Element>,
+}
+
+/// A pattern element before its indentation is known.
+enum Raw {
+    Text(String),
+    /// The start of a continuation line: how many lines it is from the
+    /// previous one, and how far it is indented.
+    Line(usize, usize),
+    Placeable(Expression),
+}
+
+/// Parses the subset of Fluent syntax that has an L20n equivalent, along
+/// with function calls and named arguments so they can be reported.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.get(self.pos + n).cloned()
+    }
+
+    fn bump(&mut self) {
+        self.pos += 1;
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.bump();
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ()> {
+        if self.eat(c) { Ok(()) } else { Err(()) }
+    }
+
+    fn skip_inline(&mut self) {
+        while self.eat(' ') {}
+    }
+
+    fn skip_blank(&mut self) {
+        while self.eat(' ') || self.eat('\n') {}
+    }
+
+    fn line(&self) -> usize {
+        self.chars[..self.pos].iter().filter(|&&c| c == '\n').count()
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.peek() {
+            self.bump();
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// Skips a line with nothing but spaces on it.
+    fn blank_line(&mut self) -> bool {
+        let start = self.pos;
+        self.skip_inline();
+        match self.peek() {
+            None => true,
+            Some('\n') => {
+                self.bump();
+                true
+            }
+            Some(_) => {
+                self.pos = start;
+                false
+            }
+        }
+    }
+
+    /// Skips to the next line that starts an entry or a comment.
+    fn skip_junk(&mut self) {
+        self.skip_line();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphabetic() || c == '-' || c == '#' {
+                break;
+            }
+            self.skip_line();
+        }
+    }
+
+    /// A comment line, with its level: 1 for `#`, 2 for `##` and 3 for
+    /// `###`.
+    fn comment_line(&mut self) -> Option<(usize, String)> {
+        let start = self.pos;
+        let mut level = 0;
+        while self.eat('#') {
+            level += 1;
+        }
+        if level <= 3 && (self.eat(' ') || self.peek().is_none_or(|c| c == '\n')) {
+            let text_start = self.pos;
+            self.skip_line();
+            let text: String = self.chars[text_start..self.pos].iter().collect();
+            return Some((level, text.trim_end_matches('\n').to_string()));
+        }
+        self.pos = start;
+        None
+    }
+
+    fn identifier(&mut self) -> Result<String, ()> {
+        let start = self.pos;
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() => self.bump(),
+            _ => return Err(()),
+        }
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+            self.bump();
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn message(&mut self) -> Result<Message, ()> {
+        let term = self.eat('-');
+        let id = self.identifier()?;
+        self.skip_inline();
+        self.expect('=')?;
+        self.skip_inline();
+        let value = self.pattern()?;
+
+        let mut attrs = vec![];
+        while let Some(attr) = self.attribute()? {
+            attrs.push(attr);
+        }
+        match self.peek() {
+            None => {}
+            Some('\n') => self.bump(),
+            Some(_) => return Err(()),
+        }
+        if value.is_none() && (term || attrs.is_empty()) {
+            return Err(());
+        }
+        Ok(Message { id, term, value, attrs })
+    }
+
+    fn attribute(&mut self) -> Result<Option<(String, Vec<Element>)>, ()> {
+        let start = self.pos;
+        self.skip_blank();
+        let indented = self.pos > start && self.chars[self.pos - 1] == ' ';
+        if !indented || !self.eat('.') {
+            self.pos = start;
+            return Ok(None);
+        }
+        let id = self.identifier()?;
+        self.skip_inline();
+        self.expect('=')?;
+        self.skip_inline();
+        match self.pattern()? {
+            Some(value) => Ok(Some((id, value))),
+            None => Err(()),
+        }
+    }
+
+    /// Parses a pattern, up to the end of its last line, and removes the
+    /// indentation its lines have in common.
+    fn pattern(&mut self) -> Result<Option<Vec<Element>>, ()> {
+        let mut raw = vec![];
+        loop {
+            match self.peek() {
+                None | Some('}') => break,
+                Some('{') => {
+                    self.bump();
+                    raw.push(Raw::Placeable(self.placeable()?));
+                }
+                Some('\n') => {
+                    // the pattern goes on if the next line that is not
+                    // blank is indented, and does not start a variant, an
+                    // attribute or the end of a select expression
+                    let start = self.pos;
+                    let mut lines = 0;
+                    let mut indent = 0;
+                    while self.eat('\n') {
+                        lines += 1;
+                        indent = 0;
+                        while self.eat(' ') {
+                            indent += 1;
+                        }
+                    }
+                    match self.peek() {
+                        Some(c) if indent > 0 && !"[*.}".contains(c) => raw.push(Raw::Line(lines, indent)),
+                        _ => {
+                            self.pos = start;
+                            break;
+                        }
+                    }
+                }
+                Some(c) => {
+                    self.bump();
+                    if let Some(&mut Raw::Text(ref mut text)) = raw.last_mut() {
+                        text.push(c);
+                        continue;
+                    }
+                    raw.push(Raw::Text(c.to_string()));
+                }
+            }
+        }
+
+        let common = raw.iter().filter_map(|r| match *r {
+            Raw::Line(_, indent) => Some(indent),
+            _ => None,
+        }).min().unwrap_or(0);
+        let mut elements: Vec<Element> = vec![];
+        for (i, r) in raw.into_iter().enumerate() {
+            let text = match r {
+                Raw::Placeable(expr) => {
+                    elements.push(Element::Placeable(expr));
+                    continue;
+                }
+                Raw::Text(text) => text,
+                // a pattern that starts on the line after the `=` starts
+                // with its first line
+                Raw::Line(lines, indent) => {
+                    let lines = if i == 0 { 0 } else { lines };
+                    format!("{}{}", "\n".repeat(lines), " ".repeat(indent - common))
+                }
+            };
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(&mut Element::Text(ref mut last)) = elements.last_mut() {
+                last.push_str(&text);
+                continue;
+            }
+            elements.push(Element::Text(text));
+        }
+        if let Some(&mut Element::Text(ref mut text)) = elements.last_mut() {
+            let len = text.trim_end_matches(' ').len();
+            text.truncate(len);
+        }
+        if let Some(Element::Text(text)) = elements.last() {
+            if text.is_empty() {
+                elements.pop();
+            }
+        }
+        Ok(if elements.is_empty() { None } else { Some(elements) })
+    }
+
+    /// Parses a placeable, after its `{`.
+    fn placeable(&mut self) -> Result<Expression, ()> {
+        self.skip_blank();
+        let mut expr = self.inline_expression()?;
+        self.skip_blank();
+        if self.peek() == Some('-') && self.peek_at(1) == Some('>') {
+            self.bump();
+            self.bump();
+            self.skip_inline();
+            expr = Expression::Select(Box::new(expr), self.variants()?);
+            self.skip_blank();
+        }
+        self.expect('}')?;
+        Ok(expr)
+    }
+
+    fn variants(&mut self) -> Result<Vec<Variant>, ()> {
+        let mut variants = vec![];
+        loop {
+            self.skip_blank();
+            let default = self.eat('*');
+            if !self.eat('[') {
+                if default {
+                    return Err(());
+                }
+                break;
+            }
+            self.skip_blank();
+            let key = match self.peek() {
+                Some(c) if c.is_ascii_digit() || c == '-' => self.number()?,
+                _ => self.identifier()?,
+            };
+            self.skip_blank();
+            self.expect(']')?;
+            self.skip_inline();
+            let value = self.pattern()?.unwrap_or_default();
+            variants.push(Variant { key, default, value });
+        }
+        if variants.iter().filter(|v| v.default).count() != 1 {
+            return Err(());
+        }
+        Ok(variants)
+    }
+
+    fn inline_expression(&mut self) -> Result<Expression, ()> {
+        match self.peek() {
+            Some('"') => self.string().map(Expression::Str),
+            Some('-') if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) => self.number().map(Expression::Num),
+            Some(c) if c.is_ascii_digit() => self.number().map(Expression::Num),
+            Some('-') => {
+                self.bump();
+                let id = self.identifier()?;
+                let attr = if self.eat('.') { Some(self.identifier()?) } else { None };
+                let args = self.peek() == Some('(');
+                if args {
+                    self.arguments()?;
+                }
+                Ok(Expression::Term(id, attr, args))
+            }
+            Some('$') => {
+                self.bump();
+                self.identifier().map(Expression::Var)
+            }
+            Some('{') => {
+                self.bump();
+                self.placeable()
+            }
+            _ => {
+                let id = self.identifier()?;
+                if self.peek() == Some('(') {
+                    let (args, named) = self.arguments()?;
+                    return Ok(Expression::Function(id, args, named));
+                }
+                let attr = if self.eat('.') { Some(self.identifier()?) } else { None };
+                Ok(Expression::Message(id, attr))
+            }
+        }
+    }
+
+    /// Parses call arguments, returning the positional ones and whether
+    /// there were named ones.
+    fn arguments(&mut self) -> Result<(Vec<Expression>, bool), ()> {
+        self.expect('(')?;
+        let mut positional = vec![];
+        let mut named = false;
+        loop {
+            self.skip_blank();
+            if self.eat(')') {
+                break;
+            }
+            let start = self.pos;
+            let is_named = self.identifier().is_ok() && {
+                self.skip_blank();
+                self.eat(':')
+            };
+            if is_named {
+                self.skip_blank();
+                self.inline_expression()?;
+                named = true;
+            } else {
+                self.pos = start;
+                positional.push(self.inline_expression()?);
+            }
+            self.skip_blank();
+            if !self.eat(',') {
+                self.expect(')')?;
+                break;
+            }
+        }
+        Ok((positional, named))
+    }
+
+    fn number(&mut self) -> Result<String, ()> {
+        let start = self.pos;
+        self.eat('-');
+        let digits = |this: &mut Parser| {
+            let start = this.pos;
+            while this.peek().is_some_and(|c| c.is_ascii_digit()) {
+                this.bump();
+            }
+            if this.pos > start { Ok(()) } else { Err(()) }
+        };
+        digits(self)?;
+        if self.eat('.') {
+            digits(self)?;
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    fn string(&mut self) -> Result<String, ()> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err(()),
+                Some('"') => {
+                    self.bump();
+                    return Ok(s);
+                }
+                Some('\\') => {
+                    self.bump();
+                    let c = match self.peek() {
+                        Some(c @ '\\') | Some(c @ '"') => {
+                            self.bump();
+                            c
+                        }
+                        Some(u @ 'u') | Some(u @ 'U') => {
+                            self.bump();
+                            let len = if u == 'u' { 4 } else { 6 };
+                            let hex: String = self.chars[self.pos..].iter().take(len).collect();
+                            self.pos += hex.chars().count();
+                            u32::from_str_radix(&hex, 16).ok()
+                                .filter(|_| hex.len() == len)
+                                .and_then(::std::char::from_u32)
+                                .ok_or(())?
+                        }
+                        _ => return Err(()),
+                    };
+                    s.push(c);
+                }
+                Some(c) => {
+                    self.bump();
+                    s.push(c);
+                }
+            }
+        }
+    }
+}
+
+struct Importer {
+    issues: Vec<Issue>,
+}
+
+impl Importer {
+    fn entry(&mut self, message: Message) -> Entry<'static> {
+        let source_id = if message.term { format!("-{}", message.id) } else { message.id.clone() };
+        let id = self.rename(&source_id, &message.id);
+        let id = if message.term { format!("_{}", id) } else { id };
+
+        let (value, indices) = match message.value {
+            Some(elements) => self.value(&source_id, elements),
+            None => (Str(Cow::Borrowed("")), vec![]),
+        };
+        let attrs = message.attrs.into_iter().map(|(name, elements)| {
+            let name = self.rename(&format!("{}.{}", source_id, name), &name);
+            let (value, indices) = self.value(&source_id, elements);
+            Attr(Cow::Owned(name), value, indices)
+        }).collect();
+        Entity(Cow::Owned(id), value, indices, attrs)
+    }
+
+    /// `name` with any `-` made into `_`, reported as a rename of `id`.
+    fn rename(&mut self, id: &str, name: &str) -> String {
+        let renamed = name.replace('-', "_");
+        if renamed != name {
+            self.issues.push(Issue::new(id, IssueKind::Renamed(renamed.clone())));
+        }
+        renamed
+    }
+
+    /// The value of a pattern, and the index to pick from it if it is a
+    /// Hash.
+    fn value(&mut self, id: &str, elements: Vec<Element>) -> (Value<'static>, Vec<Expr<'static>>) {
+        let selects: Vec<usize> = elements.iter().enumerate().filter_map(|(i, e)| match *e {
+            Element::Placeable(Expression::Select(..)) => Some(i),
+            _ => None,
+        }).collect();
+        if let [i] = selects[..] {
+            if let Element::Placeable(Expression::Select(ref selector, ref variants)) = elements[i] {
+                if let Some(index) = self.selector(id, selector, variants) {
+                    let mut map = HashMap::new();
+                    let mut default = None;
+                    for variant in variants {
+                        let mut parts = elements[..i].to_vec();
+                        parts.extend(variant.value.iter().cloned());
+                        parts.extend(elements[i + 1..].iter().cloned());
+                        let key = variant.key.replace('-', "_");
+                        if variant.default {
+                            default = Some(Cow::Owned(key.clone()));
+                        }
+                        map.insert(Cow::Owned(key), self.flat(id, &parts));
+                    }
+                    return (Hash(map, default, None), vec![index]);
+                }
+            }
+        }
+        (self.flat(id, &elements), vec![])
+    }
+
+    /// The index of a Hash made from a select expression, if it can be
+    /// one.
+    fn selector(&mut self, id: &str, selector: &Expression, variants: &[Variant]) -> Option<Expr<'static>> {
+        let names = variants.iter().all(|v| v.key.starts_with(|c: char| c.is_ascii_alphabetic()));
+        if !names {
+            return None;
+        }
+        let plural = variants.iter().all(|v| PLURAL_CATEGORIES.contains(&&v.key[..]));
+        let plural_of = |expr: Expr<'static>| CallExpr(Box::new(IdentExpr(Cow::Borrowed(PLURAL))), vec![expr]);
+        match *selector {
+            Expression::Var(_) if plural => self.expr(id, selector).map(plural_of),
+            // NUMBER($n) only formats the number, and selects the same way
+            Expression::Function(ref name, ref args, false) if plural && name == "NUMBER" && args.len() == 1 => {
+                self.expr(id, &args[0]).map(plural_of)
+            }
+            _ => self.expr(id, selector),
+        }
+    }
+
+    /// A string with any select expressions in it replaced by their default
+    /// variants.
+    fn flat(&mut self, id: &str, elements: &[Element]) -> Value<'static> {
+        let mut pieces = Pieces::default();
+        self.push(id, elements, &mut pieces);
+        pieces.finish()
+    }
+
+    fn push(&mut self, id: &str, elements: &[Element], pieces: &mut Pieces) {
+        for element in elements {
+            match *element {
+                Element::Text(ref text) => pieces.text.push_str(text),
+                Element::Placeable(Expression::Select(_, ref variants)) => {
+                    self.issues.push(Issue::new(id, IssueKind::Select));
+                    let default = variants.iter().find(|v| v.default).unwrap();
+                    self.push(id, &default.value, pieces);
+                }
+                Element::Placeable(ref expr) => match self.expr(id, expr) {
+                    // `{ "{" }` is how Fluent writes text it cannot write
+                    // otherwise
+                    Some(ValExpr(Str(text))) => pieces.text.push_str(&text),
+                    Some(expr) => pieces.expr(expr),
+                    None => {}
+                },
+            }
+        }
+    }
+
+    fn expr(&mut self, id: &str, expr: &Expression) -> Option<Expr<'static>> {
+        let reference = |name: String, attr: &Option<String>| {
+            let ident = IdentExpr(Cow::Owned(name));
+            match *attr {
+                Some(ref attr) => AttrExpr(Box::new(ident), Box::new(IdentExpr(Cow::Owned(attr.replace('-', "_")))), Static),
+                None => ident,
+            }
+        };
+        Some(match *expr {
+            Expression::Str(ref s) => ValExpr(Str(Cow::Owned(s.clone()))),
+            Expression::Num(ref n) => match n.parse() {
+                Ok(n) => NumExpr(n),
+                Err(_) => ValExpr(Str(Cow::Owned(n.clone()))),
+            },
+            Expression::Var(ref name) => {
+                let name = self.rename(&format!("${}", name), name);
+                VarExpr(Cow::Owned(name))
+            }
+            Expression::Message(ref name, ref attr) => reference(name.replace('-', "_"), attr),
+            Expression::Term(ref name, ref attr, args) => {
+                if args {
+                    self.issues.push(Issue::new(id, IssueKind::Function(format!("-{}", name))));
+                }
+                reference(format!("_{}", name.replace('-', "_")), attr)
+            }
+            Expression::Function(ref name, ref args, _) => {
+                self.issues.push(Issue::new(id, IssueKind::Function(name.clone())));
+                return args.first().and_then(|arg| self.expr(id, arg));
+            }
+            Expression::Select(..) => unreachable!("select expressions are flattened"),
+        })
+    }
+}
+
+struct Exporter {
+    issues: Vec<Issue>,
+}
+
+impl Exporter {
+    fn entity(&mut self, id: &str, value: &Value, indices: &[Expr], attrs: &[Attr]) -> Result<String, IssueKind> {
+        let mut out = match id.strip_prefix('_') {
+            Some(term) => format!("-{} =", self.name(id, term)),
+            None => format!("{} =", self.name(id, id)),
+        };
+        match *value {
+            Str(ref s) if s.is_empty() && !attrs.is_empty() => {}
+            _ => out.push_str(&self.pattern(id, value, indices, 4)?),
+        }
+        for Attr(attr, value, indices) in attrs {
+            out.push_str("\n    .");
+            out.push_str(&self.name(id, attr));
+            out.push_str(" =");
+            out.push_str(&self.pattern(id, value, indices, 8)?);
+        }
+        Ok(out)
+    }
+
+    /// A valid Fluent name for an L20n one, reporting a rename of `id` if
+    /// it had to change.
+    fn name(&mut self, id: &str, name: &str) -> String {
+        if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return name.to_string();
+        }
+        let renamed = format!("x{}", name);
+        self.issues.push(Issue::new(id, IssueKind::Renamed(renamed.clone())));
+        renamed
+    }
+
+    /// A pattern, starting with what goes right after the `=`: a space for
+    /// one on a single line, or a line break for one on several lines,
+    /// which are indented by `indent`.
+    fn pattern(&mut self, id: &str, value: &Value, indices: &[Expr], indent: usize) -> Result<String, IssueKind> {
+        let body = self.body(id, value, indices)?;
+        if !body.contains('\n') {
+            return Ok(format!(" {}", body));
+        }
+        let mut out = String::new();
+        for line in body.split('\n') {
+            out.push('\n');
+            if !line.is_empty() {
+                out.push_str(&" ".repeat(indent));
+                out.push_str(line);
+            }
+        }
+        Ok(out)
+    }
+
+    fn body(&mut self, id: &str, value: &Value, indices: &[Expr]) -> Result<String, IssueKind> {
+        let mut out = String::new();
+        match *value {
+            Str(ref s) => text(&mut out, s),
+            ComplexStr(ref pieces) => {
+                let mut text_next = true;
+                for piece in pieces {
+                    match *piece {
+                        ValExpr(Str(ref s)) if text_next => {
+                            text(&mut out, s);
+                            text_next = false;
+                        }
+                        ref expr => {
+                            out.push_str("{ ");
+                            out.push_str(&placeable(expr)?);
+                            out.push_str(" }");
+                            text_next = true;
+                        }
+                    }
+                }
+            }
+            Hash(ref map, ref default, _) => {
+                let mut keys: Vec<&Cow<str>> = map.keys().collect();
+                keys.sort();
+                let default = default.as_ref()
+                    .or_else(|| keys.iter().cloned().find(|k| *k == "other"))
+                    .or_else(|| keys.first().cloned());
+                let valid = keys.iter().all(|k| k.starts_with(|c: char| c.is_ascii_alphabetic()));
+                let (index, rest) = match indices.split_first() {
+                    Some((index, rest)) if valid => (index, rest),
+                    _ => {
+                        self.issues.push(Issue::new(id, IssueKind::Hash));
+                        return match default {
+                            Some(key) => self.body(id, &map[key], &[]),
+                            None => Ok(String::new()),
+                        };
+                    }
+                };
+                out.push_str("{ ");
+                out.push_str(&selector(index)?);
+                out.push_str(" ->");
+                for key in keys {
+                    out.push_str(if Some(key) == default { "\n   *[" } else { "\n    [" });
+                    out.push_str(key);
+                    out.push(']');
+                    out.push_str(&self.pattern(id, &map[key], rest, 8)?);
+                }
+                out.push_str("\n}");
+            }
+        }
+        // Fluent drops blank lines at the start of a pattern and spaces at
+        // its end, and has no empty patterns
+        if out.is_empty() || out.starts_with('\n') {
+            out.insert_str(0, "{\"\"}");
+        }
+        if out.ends_with(' ') {
+            out.pop();
+            out.push_str("{\" \"}");
+        }
+        Ok(out)
+    }
+}
+
+/// Writes text, with braces, spaces at the start of a pattern, and the
+/// characters Fluent would not take as text at the start of a line, as
+/// string literals.
+fn text(out: &mut String, text: &str) {
+    for c in text.chars() {
+        let line_start = out.is_empty() || out.ends_with('\n');
+        if c == '{' || c == '}' || out.is_empty() && c == ' ' || line_start && "[*.".contains(c) {
+            out.push_str(&format!("{{\"{}\"}}", c));
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+fn placeable(expr: &Expr) -> Result<String, IssueKind> {
+    let unsupported = || IssueKind::Expression(serialize_expr(expr));
+    Ok(match *expr {
+        VarExpr(ref name) if name.starts_with(|c: char| c.is_ascii_alphabetic()) => format!("${}", name),
+        IdentExpr(ref name) => reference(name).ok_or_else(unsupported)?,
+        AttrExpr(ref parent, ref attr, Static) => match (&**parent, &**attr) {
+            (IdentExpr(name), IdentExpr(attr)) if !name.starts_with('_') => {
+                format!("{}.{}", reference(name).ok_or_else(unsupported)?, attr)
+            }
+            _ => return Err(unsupported()),
+        },
+        ValExpr(Str(ref s)) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        NumExpr(n) => n.to_string(),
+        ParenExpr(ref expr) => placeable(expr)?,
+        _ => return Err(unsupported()),
+    })
+}
+
+fn selector(index: &Expr) -> Result<String, IssueKind> {
+    match *index {
+        CallExpr(ref callee, ref args) if **callee == IdentExpr(Cow::Borrowed(PLURAL)) && args.len() == 1 => {
+            match args[0] {
+                VarExpr(_) => placeable(&args[0]),
+                _ => Err(IssueKind::Expression(serialize_expr(index))),
+            }
+        }
+        // attributes of terms can only be used to select
+        AttrExpr(ref parent, ref attr, Static) => match (&**parent, &**attr) {
+            (IdentExpr(name), IdentExpr(attr)) if name.starts_with('_') => {
+                let term = reference(name).ok_or_else(|| IssueKind::Expression(serialize_expr(index)))?;
+                Ok(format!("{}.{}", term, attr))
+            }
+            _ => placeable(index),
+        },
+        ParenExpr(ref expr) => selector(expr),
+        _ => placeable(index),
+    }
+}
+
+/// A reference to a message, or to a term for a name starting with `_`.
+fn reference(name: &str) -> Option<String> {
+    let (prefix, bare) = match name.strip_prefix('_') {
+        Some(bare) => ("-", bare),
+        None => ("", name),
+    };
+    if bare.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        Some(format!("{}{}", prefix, bare))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use ast::*;
+    use context::Locale;
+    use convert::{Issue, IssueKind};
+    use parser::parse;
+    use serializer::serialize;
+    use super::{from_l20n, to_l20n};
+
+    fn l20n(ftl: &str) -> String {
+        let (entries, issues) = to_l20n(ftl);
+        assert_eq!(issues, vec![]);
+        serialize(&entries)
+    }
+
+    #[test]
+    fn test_to_l20n() {
+        assert_eq!(l20n("hi = Hello, { $name }!\n  .title = Hi { -brand-name.gender }"),
+                   "<hi \"Hello, {{ $name }}!\" title: \"Hi {{ _brand_name::gender }}\">\n");
+        assert_eq!(l20n("multi =\n    one\n      two\n\n    three   \nnext = { \"{\" }{ 42 }{ -1.5 }"),
+                   "<multi \"one\n  two\n\nthree\">\n<next \"\\{{{ 42 }}-1.5\">\n");
+        assert_eq!(l20n("### Resource\n\n## Group\n# Note\n# more\nonlyAttrs =\n    .label = Label\n"),
+                   "/* Resource */\n/* Group */\n/* Note\nmore */\n<onlyAttrs \"\" label: \"Label\">\n");
+        assert_eq!(l20n("emails = You have { $n ->\n    [one] one email\n   *[other] { $n } emails\n  }."),
+                   "<emails[plural($n)] { one: \"You have one email.\", \
+                    *other: \"You have {{ $n }} emails.\" }>\n");
+        assert_eq!(l20n("-brand = { $case ->\n *[nominative] Firefox\n  [genitive] Firefoxa\n}"),
+                   "<_brand[$case] { genitive: \"Firefoxa\", *nominative: \"Firefox\" }>\n");
+        assert_eq!(l20n("n = { NUMBER($n) ->\n *[one] A\n [other] B\n}"),
+                   "<n[plural($n)] { *one: \"A\", other: \"B\" }>\n");
+    }
+
+    #[test]
+    fn test_to_l20n_issues() {
+        let (entries, issues) = to_l20n("\
+both = { $a ->
+   *[x] X
+} { $b ->
+   *[y] Y
+}
+num = { $n ->
+    [0] None
+   *[other] Some
+}
+date = { DATETIME($d, month: \"long\") } { -term(case: \"gen\") }
+bad = { $x
+junk here
+dashed = { $user-name }
+ok = OK
+");
+        assert_eq!(serialize(&entries), "\
+<both \"X Y\">
+<num \"Some\">
+<date \"{{ $d }} {{ _term }}\">
+<dashed \"{{ $user_name }}\">
+<ok \"OK\">
+");
+        let issue = |id: &str, kind| Issue { id: id.to_string(), kind };
+        assert_eq!(issues, vec![
+            issue("both", IssueKind::Select),
+            issue("both", IssueKind::Select),
+            issue("num", IssueKind::Select),
+            issue("date", IssueKind::Function(String::from("DATETIME"))),
+            issue("date", IssueKind::Function(String::from("-term"))),
+            issue("", IssueKind::Syntax(10)),
+            issue("", IssueKind::Syntax(11)),
+            issue("$user-name", IssueKind::Renamed(String::from("user_name"))),
+        ]);
+    }
+
+    #[test]
+    fn test_from_l20n() {
+        let entries = parse(r#"
+            /* The brand.
+               Do not translate. */
+            <_brand "Firefox" gender: "masculine">
+            <hi "Hi {{ _brand }}, {{ $name }} {{ hi::title }}, {{ 'a \"b\"' }}" title: "Title">
+            <odd "{braces}
+[starts a line
+ and a space " >
+            <attrs "" a: "A">
+            <empty "">
+            <nested[plural($n), _brand::gender] {
+              one: { masculine: "his", *feminine: "her" },
+              *other: "their"
+            }>
+            <unindexed { a: "A", *b: "B" }>
+            <cond "{{ $n > 1 ? 'many' : 'one' }}">
+            <macro($n) { $n }>
+            import('other.l20n')
+        "#).unwrap();
+        let (ftl, issues) = from_l20n(&entries);
+        assert_eq!(ftl, r#"# The brand.
+# Do not translate.
+-brand = Firefox
+    .gender = masculine
+hi = Hi { -brand }, { $name } { hi.title }, { "a \"b\"" }
+    .title = Title
+odd =
+    {"{"}braces{"}"}
+    {"["}starts a line
+     and a space{" "}
+attrs =
+    .a = A
+empty = {""}
+nested =
+    { $n ->
+        [one]
+            { -brand.gender ->
+               *[feminine] her
+                [masculine] his
+            }
+       *[other] their
+    }
+unindexed = B
+"#);
+        let issue = |id: &str, kind| Issue { id: id.to_string(), kind };
+        assert_eq!(issues, vec![
+            issue("unindexed", IssueKind::Hash),
+            issue("cond", IssueKind::Expression(String::from("$n > 1 ? \"many\" : \"one\""))),
+            issue("macro", IssueKind::Macro),
+            issue("other.l20n", IssueKind::Import),
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ftl = "\
+# A note.
+-brand = Firefox
+    .gender = masculine
+hi = Hi { -brand }, { $name }!
+    .title = { hi } and { hi.title }
+multi =
+    First line
+      indented
+    {\"[\"}bracket
+emails =
+    { $n ->
+        [one] One email
+       *[other] { $n } emails
+    }
+";
+        let (entries, issues) = to_l20n(ftl);
+        assert_eq!(issues, vec![]);
+        assert_eq!(from_l20n(&entries), (String::from(ftl), vec![]));
+
+        // and the result localizes as Fluent would
+        let mut locale = Locale::new();
+        locale.add_resource("<plural($n) { $n == 1 ? 'one' : 'other' }>").unwrap();
+        locale.add_resource(&serialize(&entries)).unwrap();
+        let mut data = ::std::collections::HashMap::new();
+        data.insert("n", 3);
+        assert_eq!(locale.localize_entity("emails", &data).unwrap(), "3 emails");
+        data.insert("n", 1);
+        assert_eq!(locale.localize_entity("emails", &data).unwrap(), "One email");
+        assert_eq!(locale.localize_entity("multi", ()).unwrap(), "First line\n  indented\n[bracket");
+
+        let entries = vec![Entity(Cow::Borrowed("x"), Str(Cow::Borrowed("a}b")), vec![], vec![])];
+        assert_eq!(to_l20n(&from_l20n(&entries).0).0, entries);
+    }
+}
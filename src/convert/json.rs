@@ -0,0 +1,180 @@
+//! JSON translation files, flat or nested, as i18next has them.
+//!
+//! | JSON                                         | L20n                               |
+//! |----------------------------------------------|------------------------------------|
+//! | `"hello": "Hi"`                              | `<hello "Hi">`                     |
+//! | `"menu": { "file": "File" }`                 | `<menu_file "File">`               |
+//! | `"menu.file": "File"`                        | `<menu_file "File">`               |
+//! | `"hi": "Hi, {{name}}"`, `"hi": "Hi, {name}"` | `<hi "Hi, {{ $name }}">`           |
+//! | `"item_one": "One item"`, `"item_other": "{{count}} items"` | `<item[plural($count)] { one: "One item", *other: "{{ $count }} items" }>` |
+//!
+//! Keys ending in `_` and a CLDR plural category (`_zero`, `_one`, `_two`,
+//! `_few`, `_many`, `_other`) make a plural Hash together, indexed by
+//! `plural($count)` as i18next counts with `count`. The `plural` macro of
+//! the language is not in the file; `po::PluralForms::to_macro` makes one.
+//! `.` between levels and in keys becomes `_`, since L20n names cannot have
+//! one.
+//!
+//! # Issues
+//!
+//! - `Renamed`: a key with characters that cannot be in a name, other than
+//!   `.`, which become `_`.
+//! - `Duplicate`: a key that is the same name as an earlier one. It is
+//!   left out.
+//! - `Unsupported`: a value that is not a string or an object, such as a
+//!   number or an array, by its type. It is left out.
+//! - `Syntax`: JSON that could not be read, by its line. Nothing is read.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use ast::*;
+use convert::{key_name, placeholders, Issue, IssueKind, PLURAL, PLURAL_CATEGORIES};
+use json::{self, Json};
+
+/// Converts a JSON translation file into L20n entries.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::json;
+/// use l20n::convert::po::PluralForms;
+///
+/// let (mut entries, issues) = json::to_l20n(r#"{
+///     "inbox": {
+///         "title": "Inbox of {{name}}",
+///         "unread_one": "One unread email",
+///         "unread_other": "{{count}} unread emails"
+///     }
+/// }"#);
+/// assert!(issues.is_empty());
+/// let forms = PluralForms::for_language(&"en".parse().unwrap()).unwrap();
+/// entries.insert(0, forms.to_macro().unwrap());
+///
+/// let mut locale = l20n::Locale::new();
+/// locale.add_resource(&l20n::serialize(&entries)).unwrap();
+/// let mut data = std::collections::HashMap::new();
+/// data.insert("count", 3);
+/// assert_eq!(locale.localize_entity("inbox_unread", &data).unwrap(), "3 unread emails");
+/// ```
+pub fn to_l20n(source: &str) -> (Vec<Entry<'static>>, Vec<Issue>) {
+    let root = match json::parse(source) {
+        Ok(Json::Object(members)) => members,
+        Ok(_) => return (vec![], vec![Issue::new("", IssueKind::Syntax(0))]),
+        Err(line) => return (vec![], vec![Issue::new("", IssueKind::Syntax(line))]),
+    };
+    let mut importer = Importer { entries: vec![], names: HashMap::new(), issues: vec![] };
+    importer.object("", root);
+
+    // a plural Hash defaults to `other`, or else its last category
+    let mut entries = importer.entries;
+    for entry in &mut entries {
+        if let Entity(_, Hash(ref map, ref mut default, _), ..) = *entry {
+            *default = PLURAL_CATEGORIES.iter().rev()
+                .find(|&&category| map.contains_key(category))
+                .map(|&category| Cow::Borrowed(category));
+        }
+    }
+    (entries, importer.issues)
+}
+
+struct Importer {
+    entries: Vec<Entry<'static>>,
+    /// Where each name is in `entries`, and whether it is a plural Hash.
+    names: HashMap<String, (usize, bool)>,
+    issues: Vec<Issue>,
+}
+
+impl Importer {
+    fn object(&mut self, prefix: &str, members: Vec<(String, Json)>) {
+        for (key, value) in members {
+            let key = if prefix.is_empty() { key } else { format!("{}.{}", prefix, key) };
+            match value {
+                Json::Str(text) => self.string(key, &text),
+                Json::Object(members) => self.object(&key, members),
+                other => self.issues.push(Issue::new(key, IssueKind::Unsupported(other.kind().to_string()))),
+            }
+        }
+    }
+
+    fn string(&mut self, key: String, text: &str) {
+        let plural = key.rfind('_')
+            .map(|i| (&key[..i], &key[i + 1..]))
+            .filter(|&(_, category)| PLURAL_CATEGORIES.contains(&category));
+        let (name, renamed) = key_name(plural.map_or(&key[..], |(base, _)| base));
+        let value = placeholders(text);
+
+        match (self.names.get(&name), plural) {
+            (Some(&(i, true)), Some((_, category))) => {
+                match self.entries[i] {
+                    Entity(_, Hash(ref mut map, ..), ..) if !map.contains_key(category) => {
+                        map.insert(Cow::Owned(category.to_string()), value);
+                    }
+                    _ => self.issues.push(Issue::new(key, IssueKind::Duplicate)),
+                }
+                return;
+            }
+            (Some(_), _) => return self.issues.push(Issue::new(key, IssueKind::Duplicate)),
+            (None, _) => {}
+        }
+        if renamed {
+            self.issues.push(Issue::new(&key[..], IssueKind::Renamed(name.clone())));
+        }
+        self.names.insert(name.clone(), (self.entries.len(), plural.is_some()));
+        let entity = match plural {
+            Some((_, category)) => {
+                let mut map = HashMap::new();
+                map.insert(Cow::Owned(category.to_string()), value);
+                let count = VarExpr(Cow::Borrowed("count"));
+                let index = CallExpr(Box::new(IdentExpr(Cow::Borrowed(PLURAL))), vec![count]);
+                Entity(Cow::Owned(name), Hash(map, None, None), vec![index], vec![])
+            }
+            None => Entity(Cow::Owned(name), value, vec![], vec![]),
+        };
+        self.entries.push(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use convert::{Issue, IssueKind};
+    use serializer::serialize;
+    use super::to_l20n;
+
+    #[test]
+    fn test_to_l20n() {
+        let (entries, issues) = to_l20n(r#"{
+            "hello": "Hi, {{ name }} and {name}",
+            "menu": {
+                "file": "File",
+                "edit.cut": "Cut \"é😀\"\n"
+            },
+            "menu.file": "again",
+            "item_one": "One item",
+            "item_few": "{{count}} items",
+            "item_one": "again",
+            "things_many": "Many",
+            "count": 3,
+            "list": [1, {"a": null}],
+            "no-dash": "{0}"
+        }"#);
+        assert_eq!(issues, vec![
+            Issue { id: "menu.file".into(), kind: IssueKind::Duplicate },
+            Issue { id: "item_one".into(), kind: IssueKind::Duplicate },
+            Issue { id: "count".into(), kind: IssueKind::Unsupported("number".into()) },
+            Issue { id: "list".into(), kind: IssueKind::Unsupported("array".into()) },
+            Issue { id: "no-dash".into(), kind: IssueKind::Renamed("no_dash".into()) },
+        ]);
+        assert_eq!(serialize(&entries), "\
+<hello \"Hi, {{ $name }} and {{ $name }}\">
+<menu_file \"File\">
+<menu_edit_cut \"Cut \\\"é😀\\\"\n\">
+<item[plural($count)] { *few: \"{{ $count }} items\", one: \"One item\" }>
+<things[plural($count)] { *many: \"Many\" }>
+<no_dash \"{0}\">
+");
+
+        let (entries, issues) = to_l20n("{\n  \"a\": \"b\",\n}");
+        assert_eq!(entries, vec![]);
+        assert_eq!(issues, vec![Issue { id: "".into(), kind: IssueKind::Syntax(2) }]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Conversion between L20n resources and other localization formats.
+//!
+//! Formats differ in what they can say, so a conversion returns, along with
+//! its result, the `Issue`s it ran into: every entry that could not be
+//! converted as it was, and what was done with it instead. Nothing is left
+//! out or changed without an `Issue` for it.
+//!
+//! Plurals are written the L20n way, as a Hash keyed by CLDR plural
+//! category (`zero`, `one`, `two`, `few`, `many`, `other`) and indexed by a
+//! macro named `plural`, which the resource is expected to define for its
+//! language:
+//!
+//! ```text
+//! <plural($n) { $n == 1 ? "one" : "other" }>
+//! <emails[plural($n)] { one: "One email", *other: "{{ $n }} emails" }>
+//! ```
+//!
+//! - `ftl`: Fluent, in both directions.
+//! - `po`: gettext catalogs, in both directions.
+//! - `xliff`: XLIFF 1.2 and 2.0, from a source `Locale` and back.
+//! - `properties`: Java `.properties` files, into L20n.
+//! - `json`: flat or nested JSON, as i18next has it, into L20n.
+//! - `android`: Android `strings.xml` resources, from L20n.
+//! - `apple`: Apple `.strings` and `.stringsdict` files, from L20n.
+
+use std::borrow::Cow;
+use std::mem;
+
+use ast::*;
+
+pub mod android;
+pub mod apple;
+pub mod ftl;
+pub mod json;
+mod native;
+pub mod po;
+pub mod properties;
+pub mod xliff;
+
+/// The name of the macro that picks the plural category of a number.
+pub(crate) const PLURAL: &str = "plural";
+
+/// The CLDR plural categories, which are the keys of plural Hashes.
+pub(crate) const PLURAL_CATEGORIES: [&str; 6] = ["zero", "one", "two", "few", "many", "other"];
+
+/// Whether an index is `plural(...)`.
+pub(crate) fn is_plural(index: &Expr) -> bool {
+    match *index {
+        CallExpr(ref callee, ref args) => **callee == IdentExpr(Cow::Borrowed(PLURAL)) && args.len() == 1,
+        _ => false,
+    }
+}
+
+/// The L20n name for a key from another format, where `.` and anything
+/// else that cannot be in a name become `_`, and whether anything but a `.`
+/// had to.
+pub(crate) fn key_name(key: &str) -> (String, bool) {
+    let mut renamed = false;
+    let mut name: String = key.chars().map(|c| match c {
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c,
+        '.' => '_',
+        _ => {
+            renamed = true;
+            '_'
+        }
+    }).collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+        renamed = true;
+    }
+    (name, renamed)
+}
+
+/// Text with its `{name}` and `{{name}}` placeholders made into variables.
+/// Braces around anything but a name are left as text.
+pub(crate) fn placeholders(text: &str) -> Value<'static> {
+    let mut pieces = Pieces::default();
+    let mut rest = text;
+    while let Some(i) = rest.find('{') {
+        let close = if rest[i..].starts_with("{{") { "}}" } else { "}" };
+        let inside = &rest[i + close.len()..];
+        let name = inside.find(close).map(|end| inside[..end].trim());
+        match name {
+            Some(name) if is_name(name) => {
+                pieces.text.push_str(&rest[..i]);
+                pieces.expr(VarExpr(Cow::Owned(name.to_string())));
+                rest = &inside[inside.find(close).unwrap_or(0) + close.len()..];
+            }
+            _ => {
+                pieces.text.push_str(&rest[..i + 1]);
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    pieces.text.push_str(rest);
+    pieces.finish()
+}
+
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Something a conversion could not carry over as it was.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Issue {
+    /// The id of the entry, as it is in the source, or empty if the issue
+    /// is not about one entry.
+    pub id: String,
+    /// What the issue is.
+    pub kind: IssueKind,
+}
+
+/// What could not be converted. Each conversion documents what it does in
+/// each case.
+#[derive(Debug, PartialEq, Clone)]
+pub enum IssueKind {
+    /// An L20n macro.
+    Macro,
+    /// An L20n import.
+    Import,
+    /// An expression the other format has no equivalent of, written out as
+    /// L20n.
+    Expression(String),
+    /// A Hash that no index picks from, or one that only its default can
+    /// be taken from.
+    Hash,
+    /// A selection the other side cannot express, such as one of several
+    /// in a string.
+    Select,
+    /// A function call, by the function's name, or a Fluent term called
+    /// with arguments.
+    Function(String),
+    /// A name that is not valid on the other side, and what it was renamed
+    /// to.
+    Renamed(String),
+    /// Plural forms that are not known, by the language tag or the
+    /// `Plural-Forms` they were given as.
+    PluralForms(String),
+    /// A placeholder that the translation lost, or one it has that the
+    /// source does not, by its id.
+    Placeholder(String),
+    /// An entry that is not in the source.
+    Unknown,
+    /// An entry whose name is already taken by an earlier one, which is
+    /// kept.
+    Duplicate,
+    /// A value L20n has no equivalent of, by its type, such as a JSON
+    /// array.
+    Unsupported(String),
+    /// Source that could not be parsed, by its line, counted from 0.
+    Syntax(usize),
+}
+
+impl Issue {
+    fn new<S: Into<String>>(id: S, kind: IssueKind) -> Issue {
+        Issue { id: id.into(), kind }
+    }
+}
+
+/// Collects the text and placeables of a string, the way the L20n parser
+/// lays them out.
+#[derive(Default)]
+pub(crate) struct Pieces {
+    exprs: Vec<Expr<'static>>,
+    pub(crate) text: String,
+}
+
+impl Pieces {
+    pub(crate) fn expr(&mut self, expr: Expr<'static>) {
+        self.exprs.push(ValExpr(Str(Cow::Owned(mem::take(&mut self.text)))));
+        self.exprs.push(expr);
+    }
+
+    pub(crate) fn finish(mut self) -> Value<'static> {
+        if self.exprs.is_empty() {
+            return Str(Cow::Owned(self.text));
+        }
+        if !self.text.is_empty() {
+            self.exprs.push(ValExpr(Str(Cow::Owned(self.text))));
+        }
+        ComplexStr(self.exprs)
+    }
+}
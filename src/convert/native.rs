@@ -0,0 +1,257 @@
+//! What the Android and Apple exporters share: entities resolved into the
+//! printf-style strings of native resources.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use ast::*;
+use convert::{is_plural, Issue, IssueKind, PLURAL_CATEGORIES};
+use serializer::serialize_expr;
+
+/// How deep references to other entities are followed, so that a cycle
+/// ends.
+const MAX_DEPTH: usize = 8;
+
+/// A piece of a native string.
+pub(crate) enum Part {
+    Text(String),
+    /// An argument, by its index in `Native::args`.
+    Arg(usize),
+}
+
+pub(crate) enum Body {
+    Single(Vec<Part>),
+    /// The variants, by CLDR category, in the order of the categories.
+    /// There is always an `other`.
+    Plural(Vec<(String, Vec<Part>)>),
+}
+
+/// An entity, or an attribute, resolved into a native string.
+pub(crate) struct Native {
+    /// The entity's id, or `id.attr` for an attribute.
+    pub(crate) name: String,
+    /// The variables, in the order of their arguments, with the count of a
+    /// plural first.
+    pub(crate) args: Vec<String>,
+    pub(crate) body: Body,
+}
+
+pub(crate) enum Item {
+    Comment(String),
+    Native(Native),
+}
+
+impl Native {
+    /// Writes out parts of the body as a format string, with `object` as
+    /// the conversion of arguments other than the count of a plural. A `%`
+    /// in the text is doubled when there are arguments, since it is only
+    /// formatted then.
+    pub(crate) fn format(&self, parts: &[Part], object: char) -> String {
+        let plural = matches!(self.body, Body::Plural(_));
+        let mut out = String::new();
+        for part in parts {
+            match *part {
+                Part::Text(ref text) if self.args.is_empty() => out.push_str(text),
+                Part::Text(ref text) => out.push_str(&text.replace('%', "%%")),
+                Part::Arg(i) => {
+                    let conversion = if plural && i == 0 { 'd' } else { object };
+                    let _ = write!(out, "%{}${}", i + 1, conversion);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Resolves each entity and attribute, reporting those that cannot be.
+pub(crate) fn natives(entries: &[Entry]) -> (Vec<Item>, Vec<Issue>) {
+    let mut resolver = Resolver {
+        entities: entries.iter().filter_map(|entry| match *entry {
+            Entity(ref id, ..) => Some((&id[..], entry)),
+            _ => None,
+        }).collect(),
+        issues: vec![],
+    };
+    let mut items = vec![];
+    for entry in entries {
+        match *entry {
+            Entity(ref id, ref value, ref indices, ref attrs) => {
+                let values = Some((id.to_string(), value, indices))
+                    .into_iter()
+                    .chain(attrs.iter().map(|attr| (format!("{}.{}", id, attr.0), &attr.1, &attr.2)));
+                for (name, value, indices) in values {
+                    match resolver.native(&name, value, indices) {
+                        Ok(native) => items.push(Item::Native(native)),
+                        Err(kind) => resolver.issues.push(Issue::new(name, kind)),
+                    }
+                }
+            }
+            Comment(ref text) => items.push(Item::Comment(text.trim().to_string())),
+            Macro(ref id, ..) => resolver.issues.push(Issue::new(&id[..], IssueKind::Macro)),
+            Import(ref path) => resolver.issues.push(Issue::new(&path[..], IssueKind::Import)),
+        }
+    }
+    (items, resolver.issues)
+}
+
+struct Resolver<'e> {
+    entities: HashMap<&'e str, &'e Entry<'e>>,
+    issues: Vec<Issue>,
+}
+
+impl<'e> Resolver<'e> {
+    fn native(&mut self, name: &str, value: &Value, indices: &[Expr]) -> Result<Native, IssueKind> {
+        let mut args = vec![];
+        if let (Hash(map, default, None), [index]) = (value, indices) {
+            let plural = is_plural(index) && map.keys().all(|key| PLURAL_CATEGORIES.contains(&&key[..]));
+            if plural {
+                let count = match *index {
+                    CallExpr(_, ref call_args) => match call_args[0] {
+                        VarExpr(ref count) => count.to_string(),
+                        _ => return Err(IssueKind::Expression(serialize_expr(index))),
+                    },
+                    _ => unreachable!(),
+                };
+                args.push(count);
+                let mut variants = vec![];
+                for &category in &PLURAL_CATEGORIES {
+                    // every language has `other`, which the default stands
+                    // in for if there is none
+                    let value = match map.get(category) {
+                        Some(value) => value,
+                        None if category == "other" => {
+                            default.as_ref().and_then(|d| map.get(d)).ok_or(IssueKind::Hash)?
+                        }
+                        None => continue,
+                    };
+                    variants.push((category.to_string(), self.parts(name, value, &mut args, 0)?));
+                }
+                return Ok(Native { name: name.to_string(), args, body: Body::Plural(variants) });
+            }
+        }
+        let value = self.pick(name, value, indices)?;
+        let parts = self.parts(name, value, &mut args, 0)?;
+        Ok(Native { name: name.to_string(), args, body: Body::Single(parts) })
+    }
+
+    /// The value without data: the default of a Hash, which is reported if
+    /// an index could pick another.
+    fn pick<'v>(&mut self, name: &str, value: &'v Value, indices: &[Expr]) -> Result<&'v Value<'v>, IssueKind> {
+        let mut value = value;
+        let mut level = 0;
+        let mut reported = false;
+        while let Hash(ref map, ref default, ref def_index) = *value {
+            if let Some(index) = indices.get(level).or(def_index.as_ref().map(|index| &**index)) {
+                if dynamic(index) {
+                    return Err(IssueKind::Expression(serialize_expr(index)));
+                }
+                if !reported {
+                    self.issues.push(Issue::new(name, IssueKind::Hash));
+                    reported = true;
+                }
+            }
+            value = default.as_ref().and_then(|default| map.get(default)).ok_or(IssueKind::Hash)?;
+            level += 1;
+        }
+        Ok(value)
+    }
+
+    fn parts(&mut self, name: &str, value: &Value, args: &mut Vec<String>, depth: usize)
+             -> Result<Vec<Part>, IssueKind> {
+        let mut parts = vec![];
+        match *value {
+            Str(ref s) => push_text(&mut parts, s),
+            ComplexStr(ref pieces) => {
+                // text and placeables take turns, as the parser leaves them
+                let mut text_next = true;
+                for piece in pieces {
+                    match *piece {
+                        ValExpr(Str(ref s)) if text_next => push_text(&mut parts, s),
+                        ref expr => {
+                            for part in self.placeable(name, expr, args, depth)? {
+                                match part {
+                                    Part::Text(text) => push_text(&mut parts, &text),
+                                    arg => parts.push(arg),
+                                }
+                            }
+                        }
+                    }
+                    text_next = !text_next;
+                }
+            }
+            Hash(..) => {
+                let value = self.pick(name, value, &[])?;
+                return self.parts(name, value, args, depth);
+            }
+        }
+        Ok(parts)
+    }
+
+    fn placeable(&mut self, name: &str, expr: &Expr, args: &mut Vec<String>, depth: usize)
+                 -> Result<Vec<Part>, IssueKind> {
+        let unsupported = || IssueKind::Expression(serialize_expr(expr));
+        match *expr {
+            VarExpr(ref var) => {
+                let i = match args.iter().position(|arg| arg == var) {
+                    Some(i) => i,
+                    None => {
+                        args.push(var.to_string());
+                        args.len() - 1
+                    }
+                };
+                Ok(vec![Part::Arg(i)])
+            }
+            ValExpr(ref value) => self.parts(name, value, args, depth),
+            NumExpr(n) => Ok(vec![Part::Text(n.to_string())]),
+            ParenExpr(ref expr) => self.placeable(name, expr, args, depth),
+            IdentExpr(ref id) => self.reference(name, id, None, args, depth).ok_or_else(unsupported)?,
+            AttrExpr(ref parent, ref attr, Static) => match (&**parent, &**attr) {
+                (IdentExpr(id), IdentExpr(attr)) => {
+                    self.reference(name, id, Some(attr), args, depth).ok_or_else(unsupported)?
+                }
+                _ => Err(unsupported()),
+            },
+            _ => Err(unsupported()),
+        }
+    }
+
+    /// The parts of another entity, or of its attribute, or None if there
+    /// is no such string or it goes too deep.
+    fn reference(&mut self, name: &str, id: &str, attr: Option<&str>, args: &mut Vec<String>, depth: usize)
+                 -> Option<Result<Vec<Part>, IssueKind>> {
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+        let (value, indices) = match *self.entities.get(id)? {
+            Entity(_, ref value, ref indices, ref attrs) => match attr {
+                Some(attr) => attrs.iter().find(|a| a.0 == attr).map(|a| (&a.1, &a.2[..]))?,
+                None => (value, &indices[..]),
+            },
+            _ => return None,
+        };
+        Some(self.pick(name, value, indices).and_then(|value| self.parts(name, value, args, depth + 1)))
+    }
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Part::Text(last)) = parts.last_mut() {
+        last.push_str(text);
+        return;
+    }
+    parts.push(Part::Text(text.to_string()));
+}
+
+/// Whether an expression depends on anything other than data: a global or
+/// a macro.
+fn dynamic(expr: &Expr) -> bool {
+    match *expr {
+        GlobalExpr(..) | CallExpr(..) => true,
+        CondExpr(ref a, ref b, ref c) => dynamic(a) || dynamic(b) || dynamic(c),
+        BinExpr(ref a, _, ref b) | PropExpr(ref a, ref b, _) | AttrExpr(ref a, ref b, _) => dynamic(a) || dynamic(b),
+        UnExpr(_, ref a) | ParenExpr(ref a) => dynamic(a),
+        _ => false,
+    }
+}
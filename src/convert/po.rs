@@ -0,0 +1,1172 @@
+//! GNU gettext (`.po`) catalogs.
+//!
+//! A catalog holds a translation next to the source it translates, so
+//! `from_l20n` takes both resources, and `to_l20n` gives the translation
+//! back.
+//!
+//! | PO                                  | L20n                                     |
+//! |-------------------------------------|------------------------------------------|
+//! | `msgctxt "hello"`                   | the entity `hello`                       |
+//! | `msgctxt "hello.title"`             | its attribute `title`                    |
+//! | `msgid "Hi, {{ $name }}"`           | the value in the source                  |
+//! | `msgstr "Salut, {{ $name }}"`       | the value in the translation             |
+//! | `msgctxt "emails[plural($n)]"`      | a plural Hash, `<emails[plural($n)] {...}>` |
+//! | `#. note`                           | `/* note */` before the entity in the source |
+//! | `# note`                            | `/* note */` before it in the translation |
+//! | `Plural-Forms` in the header        | the `plural` macro                       |
+//!
+//! Text is written as it is inside an L20n string, so placeables stay as
+//! `{{ $name }}` for translators to leave alone. For a plural Hash,
+//! `msgid` is the source's `one` value and `msgid_plural` its `other`
+//! value, while `msgstr[n]` is the translation for the `n`th plural form of
+//! the language, which `PluralForms` matches up with a CLDR category.
+//!
+//! An entry that a PO entry cannot hold is kept whole, written as L20n, in
+//! an entry flagged `#, l20n`: `msgid` has the source entry and `msgstr`
+//! the translated one, which translators can still edit. Nothing is lost on
+//! the way there and back.
+//!
+//! # Issues
+//!
+//! To PO:
+//!
+//! - `Macro`, `Import`: a macro other than `plural`, or an import, kept as
+//!   L20n.
+//! - `Hash`: a Hash that is not plural, or a plural Hash on one side only
+//!   where the source has a string, kept as L20n.
+//! - `Expression`: an index other than `plural($n)`, kept as L20n.
+//! - `Unknown`: an entity or macro that only the translation has, kept as
+//!   L20n.
+//! - `PluralForms`: a language whose plural forms are not known. Those of
+//!   English are used. It is also reported against `plural` when the
+//!   translation's `plural` macro is not the one the language's plural
+//!   forms make, which replace it so that `msgstr[n]` matches the header.
+//!
+//! From PO:
+//!
+//! - `PluralForms`: plural forms that are not the language's, nor the usual
+//!   one or two forms, which become the keys `form0`, `form1` and so on. It
+//!   is also reported if no `plural` macro can be made from them, and for
+//!   none or more than six forms, where those of English are used.
+//! - `Syntax`: an entry that could not be parsed, or that has no
+//!   `msgctxt`, or a `msgstr[n]` for a form the catalog does not have. It
+//!   is left out.
+//!
+//! Untranslated and fuzzy entries are left out, as are `#.` notes, which
+//! belong to the source.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::slice;
+
+use ast::*;
+use convert::{is_plural, Issue, IssueKind, PLURAL, PLURAL_CATEGORIES};
+use langtag::LanguageTag;
+use parser::{parse, parse_text};
+use serializer::{serialize, serialize_expr, serialize_text};
+
+/// The most plural forms a catalog can have: one for each CLDR category.
+const MAX_PLURALS: usize = 6;
+
+/// The plural forms of a language, as gettext numbers them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PluralForms {
+    /// The CLDR category of each form, in the order of `msgstr[n]`.
+    pub categories: Vec<String>,
+    /// The C expression that picks the form for `n`, as in the
+    /// `Plural-Forms` header.
+    pub expression: String,
+}
+
+impl PluralForms {
+    /// The plural forms of a language, if they are known.
+    ///
+    /// ```rust
+    /// # #![allow(deprecated)]
+    /// use l20n::convert::po::PluralForms;
+    ///
+    /// let forms = PluralForms::for_language(&"fr-CA".parse().unwrap()).unwrap();
+    /// assert_eq!(forms.expression, "(n > 1)");
+    /// assert_eq!(forms.categories, vec!["one", "other"]);
+    /// ```
+    pub fn for_language(tag: &LanguageTag) -> Option<PluralForms> {
+        let (expression, categories): (&str, &[&str]) = match &tag.language[..] {
+            "pt" if tag.region.as_ref().map(|r| &r[..]) == Some("BR") => ("(n > 1)", &["one", "other"]),
+            "en" | "de" | "nl" | "sv" | "da" | "nb" | "nn" | "no" | "it" | "es" | "pt" | "el" | "fi"
+                | "et" | "bg" | "he" | "hu" | "tr" | "ca" | "eu" | "gl" | "af" | "sq" => {
+                ("(n != 1)", &["one", "other"])
+            }
+            "fr" => ("(n > 1)", &["one", "other"]),
+            "ja" | "zh" | "ko" | "vi" | "th" | "id" => ("0", &["other"]),
+            "ru" | "uk" | "be" | "sr" | "hr" | "bs" => {
+                ("(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2)",
+                 &["one", "few", "many"])
+            }
+            "pl" => {
+                ("(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2)",
+                 &["one", "few", "many"])
+            }
+            "cs" | "sk" => ("(n==1) ? 0 : (n>=2 && n<=4) ? 1 : 2", &["one", "few", "other"]),
+            "sl" => {
+                ("(n%100==1 ? 0 : n%100==2 ? 1 : n%100==3 || n%100==4 ? 2 : 3)",
+                 &["one", "two", "few", "other"])
+            }
+            "ro" => ("(n==1 ? 0 : (n==0 || (n%100 > 0 && n%100 < 20)) ? 1 : 2)", &["one", "few", "other"]),
+            "lt" => {
+                ("(n%10==1 && n%100!=11 ? 0 : n%10>=2 && (n%100<10 || n%100>=20) ? 1 : 2)",
+                 &["one", "few", "other"])
+            }
+            "lv" => ("(n%10==1 && n%100!=11 ? 1 : n != 0 ? 2 : 0)", &["zero", "one", "other"]),
+            "ar" => {
+                ("(n==0 ? 0 : n==1 ? 1 : n==2 ? 2 : n%100>=3 && n%100<=10 ? 3 : n%100>=11 ? 4 : 5)",
+                 &PLURAL_CATEGORIES)
+            }
+            _ => return None,
+        };
+        Some(PluralForms {
+            categories: categories.iter().map(|c| c.to_string()).collect(),
+            expression: expression.to_string(),
+        })
+    }
+
+    /// The `plural` macro for these forms, for a resource that has plurals
+    /// but does not define one, or None if the expression is not one L20n
+    /// can evaluate.
+    ///
+    /// ```rust
+    /// # #![allow(deprecated)]
+    /// use l20n::convert::po::PluralForms;
+    ///
+    /// let forms = PluralForms::for_language(&"fr".parse().unwrap()).unwrap();
+    /// let plural = forms.to_macro().unwrap();
+    /// assert_eq!(l20n::serialize(&[plural]), "<plural($n) { $n > 1 ? \"other\" : \"one\" }>\n");
+    /// ```
+    pub fn to_macro(&self) -> Option<Entry<'static>> {
+        plural_macro(&self.expression, &self.categories)
+    }
+
+    fn english() -> PluralForms {
+        PluralForms {
+            categories: vec!["one".to_string(), "other".to_string()],
+            expression: "(n != 1)".to_string(),
+        }
+    }
+}
+
+/// Converts an L20n resource and its translation into a PO catalog for
+/// `language`, or into a template (`.pot`) without a translation if there
+/// is no language.
+///
+/// Entities of the source that are not in the translation are left for
+/// translators with an empty `msgstr`.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::po;
+///
+/// let source = l20n::parse("/* On the start page. */ <hi 'Hi, {{ $name }}' title: 'Hi'>").unwrap();
+/// let translation = l20n::parse("<hi 'Salut, {{ $name }}'>").unwrap();
+/// let (catalog, issues) = po::from_l20n(&source, &translation, Some(&"fr".parse().unwrap()));
+/// assert!(issues.is_empty());
+/// assert!(catalog.ends_with(r#"
+/// #. On the start page.
+/// msgctxt "hi"
+/// msgid "Hi, {{ $name }}"
+/// msgstr "Salut, {{ $name }}"
+///
+/// msgctxt "hi.title"
+/// msgid "Hi"
+/// msgstr ""
+/// "#));
+/// ```
+pub fn from_l20n(source: &[Entry], translation: &[Entry], language: Option<&LanguageTag>) -> (String, Vec<Issue>) {
+    let mut issues = vec![];
+    let forms = match language {
+        Some(tag) => PluralForms::for_language(tag).unwrap_or_else(|| {
+            issues.push(Issue::new("", IssueKind::PluralForms(tag.to_string())));
+            PluralForms::english()
+        }),
+        None => PluralForms::english(),
+    };
+
+    // translated entries, with the comments right before them
+    let mut translated = HashMap::new();
+    let mut comment = None;
+    for entry in translation {
+        match *entry {
+            Comment(ref text) => {
+                comment = Some(&text[..]);
+                continue;
+            }
+            Entity(ref id, ..) | Macro(ref id, ..) => {
+                translated.insert(&id[..], (entry, comment));
+            }
+            Import(..) => {}
+        }
+        comment = None;
+    }
+
+    if let Some(&(ours, _)) = translated.get(PLURAL) {
+        if forms.to_macro().is_none_or(|plural| serialize(&[plural]) != serialize(slice::from_ref(ours))) {
+            let language = language.map_or_else(String::new, LanguageTag::to_string);
+            issues.push(Issue::new(PLURAL, IssueKind::PluralForms(language)));
+        }
+    }
+
+    let mut units = vec![header(language, &forms)];
+    let mut notes = vec![];
+    let mut ids = HashSet::new();
+    for entry in source {
+        let start = units.len();
+        if let Entity(ref id, ..) | Macro(ref id, ..) = *entry {
+            ids.insert(&id[..]);
+        }
+        match *entry {
+            Comment(ref text) => {
+                notes.extend(comment_lines(text));
+                continue;
+            }
+            // the Plural-Forms header takes its place
+            Macro(ref id, ..) if id == PLURAL => continue,
+            Macro(ref id, ..) => {
+                issues.push(Issue::new(&id[..], IssueKind::Macro));
+                let theirs = translated.get(&id[..]).map(|t| t.0);
+                units.push(preserved(id, entry, theirs));
+            }
+            Import(ref path) => {
+                issues.push(Issue::new(&path[..], IssueKind::Import));
+                units.push(preserved(path, entry, Some(entry)));
+            }
+            Entity(ref id, ref value, ref indices, ref attrs) => {
+                let (theirs, comment) = match translated.get(&id[..]) {
+                    Some(&(Entity(_, value, indices, attrs), comment)) => {
+                        (Some((value, &indices[..], &attrs[..])), comment)
+                    }
+                    _ => (None, None),
+                };
+                match entity_units(id, value, indices, attrs, theirs, &forms) {
+                    Ok(entity) => units.extend(entity),
+                    Err(kind) => {
+                        issues.push(Issue::new(&id[..], kind));
+                        let theirs = translated.get(&id[..]).map(|t| t.0);
+                        units.push(preserved(id, entry, theirs));
+                    }
+                }
+                units[start].comments = comment.map(comment_lines).unwrap_or_default();
+            }
+        }
+        units[start].notes = mem::take(&mut notes);
+    }
+
+    // entries only the translation has, which would be lost otherwise
+    for entry in translation {
+        if let Entity(ref id, ..) | Macro(ref id, ..) = *entry {
+            if id != PLURAL && ids.insert(&id[..]) {
+                issues.push(Issue::new(&id[..], IssueKind::Unknown));
+                let (theirs, comment) = translated[&id[..]];
+                let mut unit = preserved(id, theirs, Some(theirs));
+                unit.comments = comment.map(comment_lines).unwrap_or_default();
+                units.push(unit);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (i, unit) in units.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        unit.write(&mut out);
+    }
+    (out, issues)
+}
+
+/// Converts a PO catalog into the L20n translation it holds.
+///
+/// A `plural` macro is made from the `Plural-Forms` header if there are
+/// plurals, unless the catalog has one kept as L20n.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::po;
+///
+/// let (entries, issues) = po::to_l20n(r#"
+/// msgid ""
+/// msgstr "Plural-Forms: nplurals=2; plural=(n > 1);\n"
+///
+/// msgctxt "emails[plural($n)]"
+/// msgid "One email"
+/// msgid_plural "{{ $n }} emails"
+/// msgstr[0] "Un courriel"
+/// msgstr[1] "{{ $n }} courriels"
+/// "#);
+/// assert!(issues.is_empty());
+/// assert_eq!(l20n::serialize(&entries), "\
+/// <plural($n) { $n > 1 ? \"other\" : \"one\" }>
+/// <emails[plural($n)] { one: \"Un courriel\", *other: \"{{ $n }} courriels\" }>
+/// ");
+/// ```
+pub fn to_l20n(po: &str) -> (Vec<Entry<'static>>, Vec<Issue>) {
+    let forms = PluralForms::english();
+    let mut importer = Importer {
+        entries: vec![],
+        entities: HashMap::new(),
+        issues: vec![],
+        categories: forms.categories,
+        expression: forms.expression,
+        plurals: false,
+        plural_macro: false,
+    };
+    for unit in parse_catalog(po, &mut importer.issues) {
+        importer.unit(unit);
+    }
+
+    let mut entries = importer.entries;
+    if importer.plurals && !importer.plural_macro {
+        match plural_macro(&importer.expression, &importer.categories) {
+            Some(entry) => entries.insert(0, entry),
+            None => importer.issues.push(Issue::new(PLURAL, IssueKind::PluralForms(importer.expression))),
+        }
+    }
+    (entries, importer.issues)
+}
+
+/// An entry of a catalog.
+#[derive(Debug, Default)]
+struct Unit {
+    /// The line it starts on.
+    line: usize,
+    /// `#.` lines.
+    notes: Vec<String>,
+    /// `#` lines.
+    comments: Vec<String>,
+    flags: Vec<String>,
+    context: Option<String>,
+    id: String,
+    plural: Option<String>,
+    /// `msgstr`, or each `msgstr[n]`.
+    strs: Vec<String>,
+}
+
+impl Unit {
+    fn write(&self, out: &mut String) {
+        for note in &self.notes {
+            comment_line(out, "#.", note);
+        }
+        for comment in &self.comments {
+            comment_line(out, "#", comment);
+        }
+        if !self.flags.is_empty() {
+            out.push_str("#, ");
+            out.push_str(&self.flags.join(", "));
+            out.push('\n');
+        }
+        if let Some(ref context) = self.context {
+            keyword(out, "msgctxt", context);
+        }
+        keyword(out, "msgid", &self.id);
+        match self.plural {
+            Some(ref plural) => {
+                keyword(out, "msgid_plural", plural);
+                for (i, text) in self.strs.iter().enumerate() {
+                    keyword(out, &format!("msgstr[{}]", i), text);
+                }
+            }
+            None => keyword(out, "msgstr", &self.strs[0]),
+        }
+    }
+}
+
+fn header(language: Option<&LanguageTag>, forms: &PluralForms) -> Unit {
+    let (language, plural_forms) = match language {
+        Some(tag) => (tag.to_string(), format!("nplurals={}; plural={};", forms.categories.len(), forms.expression)),
+        None => (String::new(), "nplurals=INTEGER; plural=EXPRESSION;".to_string()),
+    };
+    let text = format!("Language: {}\nMIME-Version: 1.0\nContent-Type: text/plain; charset=UTF-8\n\
+                        Content-Transfer-Encoding: 8bit\nPlural-Forms: {}\n", language, plural_forms);
+    Unit { strs: vec![text], ..Unit::default() }
+}
+
+/// An entry kept whole as L20n.
+fn preserved(context: &str, source: &Entry, translation: Option<&Entry>) -> Unit {
+    let l20n = |entry: &Entry| serialize(slice::from_ref(entry)).trim_end().to_string();
+    Unit {
+        flags: vec!["l20n".to_string()],
+        context: Some(context.to_string()),
+        id: l20n(source),
+        strs: vec![translation.map(l20n).unwrap_or_default()],
+        ..Unit::default()
+    }
+}
+
+/// The units of an entity: its value, unless it is empty and there are
+/// attributes, and then each attribute.
+fn entity_units(id: &str, value: &Value, indices: &[Expr], attrs: &[Attr],
+                theirs: Option<(&Value, &[Expr], &[Attr])>, forms: &PluralForms) -> Result<Vec<Unit>, IssueKind> {
+    let mut units = vec![];
+    let empty = match *value {
+        Str(ref s) => s.is_empty(),
+        _ => false,
+    };
+    if !empty || attrs.is_empty() {
+        let theirs = theirs.map(|(value, indices, _)| (value, indices));
+        units.push(value_unit(id.to_string(), value, indices, theirs, forms)?);
+    }
+    for Attr(name, value, indices) in attrs {
+        let theirs = theirs
+            .and_then(|(_, _, attrs)| attrs.iter().find(|attr| attr.0 == *name))
+            .map(|attr| (&attr.1, &attr.2[..]));
+        units.push(value_unit(format!("{}.{}", id, name), value, indices, theirs, forms)?);
+    }
+    Ok(units)
+}
+
+fn value_unit(context: String, value: &Value, indices: &[Expr], theirs: Option<(&Value, &[Expr])>,
+              forms: &PluralForms) -> Result<Unit, IssueKind> {
+    let translation = match theirs {
+        Some((value, indices)) => Some(Texts::of(value, indices)?),
+        None => None,
+    };
+    Ok(match Texts::of(value, indices)? {
+        Texts::Single(text) => {
+            let strs = match translation {
+                Some(Texts::Single(text)) => vec![text],
+                Some(Texts::Plural(..)) => return Err(IssueKind::Hash),
+                None => vec![String::new()],
+            };
+            Unit { context: Some(context), id: text, strs, ..Unit::default() }
+        }
+        Texts::Plural(index, ref source) => {
+            let strs = forms.categories.iter().map(|category| match translation {
+                Some(Texts::Single(ref text)) => text.clone(),
+                Some(Texts::Plural(_, ref texts)) => texts.get(category),
+                None => String::new(),
+            }).collect();
+            Unit {
+                context: Some(format!("{}[{}]", context, index)),
+                id: source.get("one"),
+                plural: Some(source.get("other")),
+                strs,
+                ..Unit::default()
+            }
+        }
+    })
+}
+
+/// The text of a value that a PO entry can hold.
+enum Texts {
+    Single(String),
+    /// The index, and the text for each category.
+    Plural(String, Plural),
+}
+
+struct Plural {
+    texts: HashMap<String, String>,
+    default: String,
+}
+
+impl Plural {
+    fn get(&self, category: &str) -> String {
+        self.texts.get(category).unwrap_or(&self.default).clone()
+    }
+}
+
+impl Texts {
+    fn of(value: &Value, indices: &[Expr]) -> Result<Texts, IssueKind> {
+        match (value, indices) {
+            (Hash(map, Some(default), None), [index]) if is_plural(index) => {
+                let mut texts = HashMap::new();
+                for (key, value) in map {
+                    match *value {
+                        Str(..) | ComplexStr(..) if PLURAL_CATEGORIES.contains(&&key[..]) => {
+                            texts.insert(key.to_string(), serialize_text(value));
+                        }
+                        _ => return Err(IssueKind::Hash),
+                    }
+                }
+                let default = texts.get(&default[..]).cloned().ok_or(IssueKind::Hash)?;
+                Ok(Texts::Plural(serialize_expr(index), Plural { texts, default }))
+            }
+            (Hash(..), [index]) if is_plural(index) => Err(IssueKind::Hash),
+            (Hash(..), []) => Err(IssueKind::Hash),
+            (_, []) => Ok(Texts::Single(serialize_text(value))),
+            _ => {
+                let indices: Vec<_> = indices.iter().map(serialize_expr).collect();
+                Err(IssueKind::Expression(indices.join(", ")))
+            }
+        }
+    }
+}
+
+fn comment_lines(text: &str) -> Vec<String> {
+    text.trim().lines().map(|line| line.trim().to_string()).collect()
+}
+
+fn comment_line(out: &mut String, prefix: &str, text: &str) {
+    out.push_str(prefix);
+    if !text.is_empty() {
+        out.push(' ');
+        out.push_str(text);
+    }
+    out.push('\n');
+}
+
+/// Writes a keyword and its string, over several lines if the text has
+/// several, as gettext does.
+fn keyword(out: &mut String, keyword: &str, text: &str) {
+    out.push_str(keyword);
+    let mut lines: Vec<_> = text.split_inclusive('\n').collect();
+    if lines.len() > 1 {
+        out.push_str(" \"\"\n");
+    } else {
+        out.push(' ');
+    }
+    if lines.is_empty() {
+        lines.push("");
+    }
+    for line in lines {
+        out.push('"');
+        for c in line.chars() {
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\t' => out.push_str("\\t"),
+                '\r' => out.push_str("\\r"),
+                c => out.push(c),
+            }
+        }
+        out.push_str("\"\n");
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Context,
+    Id,
+    Plural,
+    Str(usize),
+}
+
+/// Reads the entries of a catalog, leaving out obsolete ones and reporting
+/// those that cannot be read.
+fn parse_catalog(po: &str, issues: &mut Vec<Issue>) -> Vec<Unit> {
+    let mut units = vec![];
+    let mut unit = Unit::default();
+    // where a string on a line of its own goes
+    let mut field = None;
+    let mut bad = false;
+    for (n, line) in po.lines().enumerate() {
+        let line = line.trim();
+        let next = line.is_empty() || line.starts_with('#') || line.starts_with("msgctxt") || line.starts_with("msgid ");
+        if next {
+            if let Some(Field::Str(_)) = field {
+                if !mem::replace(&mut bad, false) {
+                    units.push(mem::take(&mut unit));
+                }
+                unit = Unit::default();
+                field = None;
+            }
+        }
+        if line.starts_with("#~") && field.is_none() {
+            // the comments were those of an obsolete entry
+            unit = Unit::default();
+        }
+        if line.is_empty() || line.starts_with("#~") || bad {
+            continue;
+        }
+
+        if let Some(flags) = line.strip_prefix("#,") {
+            unit.flags.extend(flags.split(',').map(str::trim).filter(|f| !f.is_empty()).map(String::from));
+        } else if let Some(note) = line.strip_prefix("#.") {
+            unit.notes.push(note.trim().to_string());
+        } else if line.starts_with("#:") || line.starts_with("#|") {
+            // references and previous strings are of no use to L20n
+        } else if let Some(comment) = line.strip_prefix('#') {
+            unit.comments.push(comment.strip_prefix(' ').unwrap_or(comment).to_string());
+        } else {
+            let (keyword, string) = match line.find([' ', '\t']) {
+                _ if line.starts_with('"') => ("", line),
+                Some(i) => (&line[..i], line[i..].trim_start()),
+                None => (line, ""),
+            };
+            let new = match keyword {
+                "" => None,
+                "msgctxt" => Some(Field::Context),
+                "msgid" => Some(Field::Id),
+                "msgid_plural" => Some(Field::Plural),
+                "msgstr" => Some(Field::Str(0)),
+                _ => keyword.strip_prefix("msgstr[")
+                    .and_then(|rest| rest.strip_suffix(']'))
+                    .and_then(|i| i.parse().ok())
+                    .filter(|&i| i < MAX_PLURALS)
+                    .map(Field::Str)
+                    .or(Some(Field::Str(usize::MAX))),
+            };
+            if field.is_none() {
+                unit.line = n;
+            }
+            if new.is_some() {
+                field = new;
+            }
+            match (field, unquote(string)) {
+                (Some(field), Some(text)) if !matches!(field, Field::Str(usize::MAX)) => {
+                    let target = match field {
+                        Field::Context => unit.context.get_or_insert_with(String::new),
+                        Field::Id => &mut unit.id,
+                        Field::Plural => unit.plural.get_or_insert_with(String::new),
+                        Field::Str(i) => {
+                            if unit.strs.len() <= i {
+                                unit.strs.resize(i + 1, String::new());
+                            }
+                            &mut unit.strs[i]
+                        }
+                    };
+                    target.push_str(&text);
+                }
+                _ => {
+                    issues.push(Issue::new(unit.context.take().unwrap_or_default(), IssueKind::Syntax(n)));
+                    bad = true;
+                    // the entry ends at the next one
+                    field = Some(Field::Str(0));
+                }
+            }
+        }
+    }
+    if field.is_some() && !bad {
+        units.push(unit);
+    }
+    units
+}
+
+/// The text of a quoted PO string.
+fn unquote(string: &str) -> Option<String> {
+    let inner = string.strip_prefix('"')?.strip_suffix('"')?;
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        text.push(match c {
+            '"' => return None,
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                c @ ('"' | '\\') => c,
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(text)
+}
+
+struct Importer {
+    entries: Vec<Entry<'static>>,
+    /// Where each entity is in `entries`, to add its attributes to.
+    entities: HashMap<String, usize>,
+    issues: Vec<Issue>,
+    /// The category of each plural form.
+    categories: Vec<String>,
+    expression: String,
+    /// Whether there are plural Hashes.
+    plurals: bool,
+    /// Whether a `plural` macro was kept as L20n.
+    plural_macro: bool,
+}
+
+impl Importer {
+    fn unit(&mut self, unit: Unit) {
+        if unit.context.is_none() && unit.id.is_empty() {
+            if let Some(text) = unit.strs.first() {
+                self.header(text);
+            }
+            return;
+        }
+        if unit.flags.iter().any(|flag| flag == "fuzzy") {
+            return;
+        }
+        let context = match unit.context {
+            Some(ref context) => context,
+            None => return self.syntax("", unit.line),
+        };
+        if unit.flags.iter().any(|flag| flag == "l20n") {
+            return self.preserved(context, &unit);
+        }
+        let (id, attr, indices) = match parse_context(context) {
+            Some(parts) => parts,
+            None => return self.syntax(context, unit.line),
+        };
+
+        let value = if unit.plural.is_some() {
+            // a form the language does not have
+            if unit.strs.len() > self.categories.len() {
+                return self.syntax(context, unit.line);
+            }
+            if unit.strs.iter().any(String::is_empty) {
+                return;
+            }
+            if unit.strs.len() != self.categories.len() {
+                return self.syntax(context, unit.line);
+            }
+            let mut map = HashMap::new();
+            for (category, text) in self.categories.iter().zip(&unit.strs) {
+                match parse_text(text) {
+                    Ok(value) => map.insert(Cow::Owned(category.clone()), value),
+                    Err(_) => return self.syntax(context, unit.line),
+                };
+            }
+            let default = if map.contains_key("other") { "other" } else { &self.categories[self.categories.len() - 1] };
+            self.plurals = true;
+            Hash(map, Some(Cow::Owned(default.to_string())), None)
+        } else {
+            match unit.strs.first().map(|text| parse_text(text)) {
+                Some(Ok(Str(ref s))) if s.is_empty() => return,
+                Some(Ok(value)) => value,
+                Some(Err(_)) => return self.syntax(context, unit.line),
+                None => return,
+            }
+        };
+        let indices = match indices {
+            ref indices if indices.is_empty() && unit.plural.is_some() => {
+                vec![CallExpr(Box::new(IdentExpr(Cow::Borrowed(PLURAL))), vec![VarExpr(Cow::Borrowed("n"))])]
+            }
+            indices => indices,
+        };
+
+        let position = match self.entities.get(&id) {
+            Some(&position) => position,
+            None => {
+                if !unit.comments.is_empty() {
+                    self.entries.push(Comment(Cow::Owned(format!(" {} ", unit.comments.join("\n")))));
+                }
+                self.entries.push(Entity(Cow::Owned(id.clone()), Str(Cow::Borrowed("")), vec![], vec![]));
+                self.entities.insert(id, self.entries.len() - 1);
+                self.entries.len() - 1
+            }
+        };
+        if let Entity(_, ref mut entity_value, ref mut entity_indices, ref mut attrs) = self.entries[position] {
+            match attr {
+                Some(name) => attrs.push(Attr(Cow::Owned(name), value, indices)),
+                None => {
+                    *entity_value = value;
+                    *entity_indices = indices;
+                }
+            }
+        }
+    }
+
+    fn header(&mut self, text: &str) {
+        let mut language = None;
+        let mut plural_forms = None;
+        for line in text.lines() {
+            if let Some(tag) = line.strip_prefix("Language:") {
+                language = LanguageTag::parse(tag.trim()).ok();
+            } else if let Some(forms) = line.strip_prefix("Plural-Forms:") {
+                plural_forms = Some(forms.trim());
+            }
+        }
+        let (count, expression) = match plural_forms.and_then(parse_plural_forms) {
+            Some((count, _)) if count == 0 || count > MAX_PLURALS => {
+                let forms = plural_forms.unwrap_or_default().to_string();
+                return self.issues.push(Issue::new("", IssueKind::PluralForms(forms)));
+            }
+            Some(forms) => forms,
+            // a template, which has no translations anyway
+            None => return,
+        };
+        let known = language.as_ref()
+            .and_then(PluralForms::for_language)
+            .filter(|forms| forms.categories.len() == count);
+        self.categories = match known {
+            Some(forms) => forms.categories,
+            None if count == 1 => vec!["other".to_string()],
+            None if count == 2 => vec!["one".to_string(), "other".to_string()],
+            None => {
+                let forms = plural_forms.unwrap_or_default().to_string();
+                self.issues.push(Issue::new("", IssueKind::PluralForms(forms)));
+                (0..count).map(|i| format!("form{}", i)).collect()
+            }
+        };
+        self.expression = expression;
+    }
+
+    /// An entry kept as L20n: the translation, or for a macro or import
+    /// that was not translated, the source.
+    fn preserved(&mut self, context: &str, unit: &Unit) {
+        let translated = unit.strs.first().is_some_and(|text| !text.is_empty());
+        let source = if translated { &unit.strs[0] } else { &unit.id };
+        let entries = match parse(source) {
+            Ok(entries) => entries,
+            Err(_) => return self.syntax(context, unit.line),
+        };
+        if !unit.comments.is_empty() {
+            self.entries.push(Comment(Cow::Owned(format!(" {} ", unit.comments.join("\n")))));
+        }
+        for entry in entries {
+            match entry {
+                Entity(..) if !translated => {}
+                Macro(ref id, ..) if id == PLURAL => {
+                    self.plural_macro = true;
+                    self.entries.push(entry.into_owned());
+                }
+                entry => self.entries.push(entry.into_owned()),
+            }
+        }
+    }
+
+    fn syntax(&mut self, context: &str, line: usize) {
+        self.issues.push(Issue::new(context, IssueKind::Syntax(line)));
+    }
+}
+
+/// The id, attribute and indices in a `msgctxt`.
+fn parse_context(context: &str) -> Option<(String, Option<String>, Vec<Expr<'static>>)> {
+    let (name, indices) = match context.find('[') {
+        Some(i) => context.split_at(i),
+        None => (context, ""),
+    };
+    let (id, attr) = match name.find('.') {
+        Some(i) => (&name[..i], Some(&name[i + 1..])),
+        None => (name, None),
+    };
+    // the parser checks the names and the indices
+    let source = format!("<{}{} \"\">", id, indices);
+    let indices = match parse(&source).ok()?.pop()? {
+        Entity(ref parsed, _, indices, _) if parsed == id => indices,
+        _ => return None,
+    };
+    if let Some(attr) = attr {
+        match parse(&format!("<{} \"\">", attr)).ok()?.pop()? {
+            Entity(ref parsed, ..) if parsed == attr => {}
+            _ => return None,
+        }
+    }
+    let indices = indices.into_iter().map(Expr::into_owned).collect();
+    Some((id.to_string(), attr.map(String::from), indices))
+}
+
+/// The number of forms and the expression in a `Plural-Forms` header.
+fn parse_plural_forms(forms: &str) -> Option<(usize, String)> {
+    let rest = forms.strip_prefix("nplurals=")?;
+    let end = rest.find(';')?;
+    let count = rest[..end].trim().parse().ok()?;
+    let expression = rest[end + 1..].trim().strip_prefix("plural=")?;
+    Some((count, expression.trim().trim_end_matches(';').trim().to_string()))
+}
+
+/// The `plural` macro for a gettext plural expression, which gives the
+/// number of a form rather than its category.
+pub(crate) fn plural_macro(expression: &str, categories: &[String]) -> Option<Entry<'static>> {
+    // gettext calls the number `n`
+    let chars: Vec<char> = expression.chars().collect();
+    let word = |i: Option<usize>| i.and_then(|i| chars.get(i)).is_some_and(|c| c.is_alphanumeric() || *c == '_');
+    let mut body = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == 'n' && !word(i.checked_sub(1)) && !word(Some(i + 1)) {
+            body.push('$');
+        }
+        body.push(c);
+    }
+    let source = format!("<{}($n) {{ {} }}>", PLURAL, body);
+    match parse(&source).ok()?.pop()? {
+        Macro(id, args, body) => Some(Macro(id, args, category(body, categories)?).into_owned()),
+        _ => None,
+    }
+}
+
+/// The expression with each form number replaced by its category.
+fn category<'a>(expr: Expr<'a>, categories: &[String]) -> Option<Expr<'a>> {
+    let name = |i: usize| categories.get(i).map(|c| ValExpr(Str(Cow::Owned(c.clone()))));
+    match expr {
+        CondExpr(cond, consequent, alternate) => {
+            let consequent = category(*consequent, categories)?;
+            let alternate = category(*alternate, categories)?;
+            Some(CondExpr(cond, Box::new(consequent), Box::new(alternate)))
+        }
+        ParenExpr(expr) => category(*expr, categories),
+        NumExpr(n) if n >= 0 => name(n as usize),
+        // a condition, such as `n != 1`, is 0 or 1
+        cond if categories.len() == 2 => Some(CondExpr(Box::new(cond), Box::new(name(1)?), Box::new(name(0)?))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ast::{Entity, Hash};
+    use context::Locale;
+    use convert::{Issue, IssueKind};
+    use langtag::LanguageTag;
+    use parser::parse;
+    use serializer::serialize;
+    use super::{from_l20n, to_l20n};
+
+    const SOURCE: &str = r#"
+<plural($n) { $n == 1 ? "one" : "other" }>
+import("brand.l20n")
+/* The greeting. */
+<hi "Hi, {{ $name }}!" title: "Say \"hi\"">
+<emails[plural($n)] { one: "One email", *other: "{{ $n }}
+emails" }>
+<tab "" accesskey: "T">
+<gender[$g] { he: "his", *they: "their" }>
+<double($n) { $n * 2 }>
+<new "Not translated">
+"#;
+
+    const TRANSLATION: &str = r#"
+<plural($n) { $n % 10 == 1 && $n % 100 != 11 ? "one" : "many" }>
+/* Informal. */
+<hi "Привет, {{ $name }}!" title: "Скажи \"привет\"">
+<emails[plural($n)] { one: "{{ $n }} письмо", few: "{{ $n }} письма", *many: "{{ $n }} писем" }>
+<tab "" accesskey: "В">
+<gender[$g] { he: "его", *they: "их" }>
+/* Only here. */
+<extra "Дополнительно">
+"#;
+
+    fn ru() -> LanguageTag {
+        "ru".parse().unwrap()
+    }
+
+    #[test]
+    fn test_from_l20n() {
+        let source = parse(SOURCE).unwrap();
+        let translation = parse(TRANSLATION).unwrap();
+        let (po, issues) = from_l20n(&source, &translation, Some(&ru()));
+        assert_eq!(issues, vec![
+            Issue { id: "plural".into(), kind: IssueKind::PluralForms("ru".into()) },
+            Issue { id: "brand.l20n".into(), kind: IssueKind::Import },
+            Issue { id: "gender".into(), kind: IssueKind::Expression("$g".into()) },
+            Issue { id: "double".into(), kind: IssueKind::Macro },
+            Issue { id: "extra".into(), kind: IssueKind::Unknown },
+        ]);
+        assert_eq!(po, r#"msgid ""
+msgstr ""
+"Language: ru\n"
+"MIME-Version: 1.0\n"
+"Content-Type: text/plain; charset=UTF-8\n"
+"Content-Transfer-Encoding: 8bit\n"
+"Plural-Forms: nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);\n"
+
+#, l20n
+msgctxt "brand.l20n"
+msgid "import(\"brand.l20n\")"
+msgstr "import(\"brand.l20n\")"
+
+#. The greeting.
+# Informal.
+msgctxt "hi"
+msgid "Hi, {{ $name }}!"
+msgstr "Привет, {{ $name }}!"
+
+msgctxt "hi.title"
+msgid "Say \"hi\""
+msgstr "Скажи \"привет\""
+
+msgctxt "emails[plural($n)]"
+msgid "One email"
+msgid_plural ""
+"{{ $n }}\n"
+"emails"
+msgstr[0] "{{ $n }} письмо"
+msgstr[1] "{{ $n }} письма"
+msgstr[2] "{{ $n }} писем"
+
+msgctxt "tab.accesskey"
+msgid "T"
+msgstr "В"
+
+#, l20n
+msgctxt "gender"
+msgid "<gender[$g] { he: \"his\", *they: \"their\" }>"
+msgstr "<gender[$g] { he: \"его\", *they: \"их\" }>"
+
+#, l20n
+msgctxt "double"
+msgid "<double($n) { $n * 2 }>"
+msgstr ""
+
+msgctxt "new"
+msgid "Not translated"
+msgstr ""
+
+# Only here.
+#, l20n
+msgctxt "extra"
+msgid "<extra \"Дополнительно\">"
+msgstr "<extra \"Дополнительно\">"
+"#);
+
+        // a template has the source only
+        let (pot, issues) = from_l20n(&source, &[], None);
+        assert_eq!(issues.len(), 3);
+        assert!(pot.contains("\"Language: \\n\"\n"));
+        assert!(pot.contains("\"Plural-Forms: nplurals=INTEGER; plural=EXPRESSION;\\n\"\n"));
+        assert!(pot.contains("msgstr[0] \"\"\nmsgstr[1] \"\"\n"));
+        assert!(pot.contains("msgid \"<gender[$g] { he: \\\"his\\\", *they: \\\"their\\\" }>\"\nmsgstr \"\"\n"));
+
+        let (_, issues) = from_l20n(&source, &[], Some(&"tlh".parse().unwrap()));
+        assert_eq!(issues[0], Issue { id: "".into(), kind: IssueKind::PluralForms("tlh".into()) });
+
+        // a default that is not one of the keys is kept as L20n
+        let mut source = parse(r#"<n[plural($n)] { one: "one", *other: "other" }>"#).unwrap();
+        if let Entity(_, Hash(ref mut map, ..), ..) = source[0] {
+            map.remove("other");
+        }
+        let (po, issues) = from_l20n(&source, &[], Some(&ru()));
+        assert_eq!(issues, vec![Issue { id: "n".into(), kind: IssueKind::Hash }]);
+        assert!(po.ends_with("#, l20n\nmsgctxt \"n\"\nmsgid \"<n[plural($n)] { one: \\\"one\\\" }>\"\nmsgstr \"\"\n"));
+    }
+
+    #[test]
+    fn test_to_l20n() {
+        let (entries, issues) = to_l20n(r#"
+# An obsolete entry, and a note that is not kept.
+#~ msgctxt "old"
+#~ msgid "Old"
+#~ msgstr "Vieux"
+
+#. Only in the source.
+#: src/main.rs:1
+#, c-format
+msgctxt "hi"
+msgid "Hi"
+msgstr ""
+"Salut, "
+"\"{{ $name }}\"\t!"
+
+#, fuzzy
+msgctxt "fuzzy"
+msgid "Fuzzy"
+msgstr "Flou"
+
+msgctxt "hi.title"
+msgid "Hi"
+msgstr "Salut"
+msgid "no context"
+msgstr "pas de contexte"
+
+msgctxt "bad id"
+msgid "Bad"
+msgstr "Mauvais"
+
+msgctxt "bad"
+msgid "Bad"
+msgstr "Mauvais {{"
+
+msgctxt "escape"
+msgid "Bad"
+msgstr "\q"
+
+msgctxt "untranslated"
+msgid "Untranslated"
+msgstr ""
+"#);
+        assert_eq!(issues, vec![
+            Issue { id: "escape".into(), kind: IssueKind::Syntax(36) },
+            Issue { id: "".into(), kind: IssueKind::Syntax(23) },
+            Issue { id: "bad id".into(), kind: IssueKind::Syntax(26) },
+            Issue { id: "bad".into(), kind: IssueKind::Syntax(30) },
+        ]);
+        assert_eq!(serialize(&entries), "<hi \"Salut, \\\"{{ $name }}\\\"\t!\" title: \"Salut\">\n");
+    }
+
+    #[test]
+    fn test_plural_forms() {
+        let (entries, issues) = to_l20n(r#"
+msgid ""
+msgstr ""
+"Language: xx\n"
+"Plural-Forms: nplurals=3; plural=n==1 ? 0 : n==2 ? 1 : 2;\n"
+
+msgctxt "n[plural($n)]"
+msgid "{{ $n }}"
+msgid_plural "{{ $n }}"
+msgstr[0] "a"
+msgstr[1] "b"
+msgstr[2] "c"
+"#);
+        assert_eq!(issues, vec![Issue {
+            id: "".into(),
+            kind: IssueKind::PluralForms("nplurals=3; plural=n==1 ? 0 : n==2 ? 1 : 2;".into()),
+        }]);
+        assert_eq!(serialize(&entries), "\
+<plural($n) { $n == 1 ? \"form0\" : $n == 2 ? \"form1\" : \"form2\" }>
+<n[plural($n)] { form0: \"a\", form1: \"b\", *form2: \"c\" }>
+");
+
+        // too many forms, or a form the catalog does not have, is reported
+        // without making room for it
+        let (entries, issues) = to_l20n(r#"
+msgid ""
+msgstr ""
+"Plural-Forms: nplurals=4294967295; plural=0;\n"
+
+msgctxt "a[plural($n)]"
+msgid "a"
+msgid_plural "a"
+msgstr[0] "a"
+msgstr[1] "a"
+
+msgctxt "b[plural($n)]"
+msgid "b"
+msgid_plural "b"
+msgstr[0] "b"
+msgstr[2] "b"
+
+msgctxt "c[plural($n)]"
+msgid "c"
+msgid_plural "c"
+msgstr[0] "c"
+msgstr[18446744073709551615] "c"
+"#);
+        assert_eq!(issues, vec![
+            Issue { id: "c[plural($n)]".into(), kind: IssueKind::Syntax(21) },
+            Issue { id: "".into(), kind: IssueKind::PluralForms("nplurals=4294967295; plural=0;".into()) },
+            Issue { id: "b[plural($n)]".into(), kind: IssueKind::Syntax(11) },
+        ]);
+        assert_eq!(serialize(&entries), "\
+<plural($n) { $n != 1 ? \"other\" : \"one\" }>
+<a[plural($n)] { one: \"a\", *other: \"a\" }>
+");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = parse(SOURCE).unwrap();
+        let translation = parse(TRANSLATION).unwrap();
+        let (po, issues) = from_l20n(&source, &translation, Some(&ru()));
+        // the translation's own plural macro is reported, and replaced by
+        // the one of the catalog's plural forms, while an entity only the
+        // translation has is kept
+        assert_eq!(issues[0], Issue { id: "plural".into(), kind: IssueKind::PluralForms("ru".into()) });
+        assert_eq!(issues[4], Issue { id: "extra".into(), kind: IssueKind::Unknown });
+        let (entries, issues) = to_l20n(&po);
+        assert_eq!(issues, vec![]);
+        assert_eq!(serialize(&entries), r#"<plural($n) { $n % 10 == 1 && $n % 100 != 11 ? "one" : $n % 10 >= 2 && $n % 10 <= 4 && ($n % 100 < 10 || $n % 100 >= 20) ? "few" : "many" }>
+import("brand.l20n")
+/* Informal. */
+<hi "Привет, {{ $name }}!" title: "Скажи \"привет\"">
+<emails[plural($n)] { few: "{{ $n }} письма", *many: "{{ $n }} писем", one: "{{ $n }} письмо" }>
+<tab "" accesskey: "В">
+<gender[$g] { he: "его", *they: "их" }>
+<double($n) { $n * 2 }>
+/* Only here. */
+<extra "Дополнительно">
+"#);
+
+        // and the plural macro picks the forms gettext would
+        let mut locale = Locale::new();
+        locale.add_resource(&serialize(&entries[..1])).unwrap();
+        locale.add_resource(&serialize(&entries[4..5])).unwrap();
+        let mut data = HashMap::new();
+        for &(n, text) in &[(1, "1 письмо"), (3, "3 письма"), (5, "5 писем"), (11, "11 писем"), (22, "22 письма")] {
+            data.insert("n", n);
+            assert_eq!(locale.localize_entity("emails", &data).unwrap(), text);
+        }
+    }
+}
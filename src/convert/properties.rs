@@ -0,0 +1,268 @@
+//! Java `.properties` files.
+//!
+//! | Properties                  | L20n                                 |
+//! |-----------------------------|--------------------------------------|
+//! | `hello = Hi`                | `<hello "Hi">`                       |
+//! | `app.title = Hi`            | `<app_title "Hi">`                   |
+//! | `hi = Hi, {name}`           | `<hi "Hi, {{ $name }}">`             |
+//! | `hi = Hi, {{name}}`         | `<hi "Hi, {{ $name }}">`             |
+//! | `# note`, `! note`          | `/* note */`                         |
+//!
+//! Keys, values and comments are read as Java reads them: a key ends at the
+//! first `=`, `:` or space that is not escaped, a line ending in `\`
+//! continues on the next one, and `\t`, `\n`, `\uXXXX` and the like are
+//! escapes. `.` in keys becomes `_`, since L20n names cannot have one.
+//! Braces around anything but a name, such as the `{0}` of `MessageFormat`,
+//! are left as text.
+//!
+//! # Issues
+//!
+//! - `Renamed`: a key with characters that cannot be in a name, other than
+//!   `.`, which become `_`.
+//! - `Duplicate`: a key that is the same name as an earlier one. It is
+//!   left out.
+//! - `Syntax`: a `\u` that is not followed by four hex digits, or a
+//!   surrogate that is not part of a pair, by the line its entry starts on.
+//!   It is kept as written.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use ast::*;
+use convert::{key_name, placeholders, Issue, IssueKind};
+
+/// Converts a `.properties` file into L20n entries.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::properties;
+///
+/// let (entries, issues) = properties::to_l20n("
+/// ! The start page.
+/// start.title = Welcome, {name}!
+/// start.body = A long \\
+///              line
+/// ");
+/// assert!(issues.is_empty());
+/// assert_eq!(l20n::serialize(&entries), "\
+/// /* The start page. */
+/// <start_title \"Welcome, {{ $name }}!\">
+/// <start_body \"A long line\">
+/// ");
+/// ```
+pub fn to_l20n(source: &str) -> (Vec<Entry<'static>>, Vec<Issue>) {
+    let mut entries = vec![];
+    let mut issues = vec![];
+    let mut names = HashSet::new();
+    let mut comment: Option<String> = None;
+
+    let mut lines = source.lines().enumerate();
+    while let Some((n, line)) = lines.next() {
+        let line = line.trim_start();
+        if line.starts_with('#') || line.starts_with('!') {
+            let text = line[1..].trim();
+            match comment {
+                Some(ref mut comment) => {
+                    comment.push('\n');
+                    comment.push_str(text);
+                }
+                None => comment = Some(text.to_string()),
+            }
+            continue;
+        }
+        if let Some(text) = comment.take() {
+            entries.push(Comment(Cow::Owned(format!(" {} ", text))));
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        // a line ending in an odd number of `\` goes on with the next one
+        let mut logical = line.to_string();
+        while logical.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1 {
+            logical.pop();
+            match lines.next() {
+                Some((_, next)) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let (key, value, malformed) = split(&logical);
+        if malformed {
+            issues.push(Issue::new(&key[..], IssueKind::Syntax(n)));
+        }
+        let (name, renamed) = key_name(&key);
+        if !names.insert(name.clone()) {
+            issues.push(Issue::new(key, IssueKind::Duplicate));
+            continue;
+        }
+        if renamed {
+            issues.push(Issue::new(&key[..], IssueKind::Renamed(name.clone())));
+        }
+        entries.push(Entity(Cow::Owned(name), placeholders(&value), vec![], vec![]));
+    }
+    if let Some(text) = comment {
+        entries.push(Comment(Cow::Owned(format!(" {} ", text))));
+    }
+    (entries, issues)
+}
+
+/// The key and the value of a logical line, unescaped, and whether either
+/// has a malformed escape.
+fn split(line: &str) -> (String, String, bool) {
+    let mut well_formed = true;
+    let mut key = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c == '=' || c == ':' || c.is_whitespace() {
+            break;
+        }
+        chars.next();
+        match c {
+            '\\' => well_formed &= unescape(&mut chars, &mut key),
+            c => key.push(c),
+        }
+    }
+    // the separator is a `=` or `:` with any space around it, or just space
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+    if let Some(&('=' | ':')) = chars.peek() {
+        chars.next();
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => well_formed &= unescape(&mut chars, &mut value),
+            c => value.push(c),
+        }
+    }
+    (key, value, !well_formed)
+}
+
+/// Reads the escape after a `\`, or returns false if it is a malformed
+/// `\u`, which is kept as written.
+fn unescape(chars: &mut Peekable<Chars>, out: &mut String) -> bool {
+    match chars.next() {
+        Some('t') => out.push('\t'),
+        Some('n') => out.push('\n'),
+        Some('r') => out.push('\r'),
+        Some('f') => out.push('\u{c}'),
+        Some('u') => {
+            let start = chars.clone();
+            match unicode(chars) {
+                Some(c) => out.push(c),
+                // Java would refuse it; keep what there is
+                None => {
+                    *chars = start;
+                    out.push_str("\\u");
+                    return false;
+                }
+            }
+        }
+        Some(c) => out.push(c),
+        None => {}
+    }
+    true
+}
+
+/// The character of a `\uXXXX` escape, after its `\u`, taking the `\uXXXX`
+/// after it too if the two are a surrogate pair.
+fn unicode(chars: &mut Peekable<Chars>) -> Option<char> {
+    let unit = hex(chars)?;
+    if (0xd800..0xdc00).contains(&unit) {
+        if chars.next() != Some('\\') || chars.next() != Some('u') {
+            return None;
+        }
+        let low = hex(chars)?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return None;
+        }
+        return ::std::char::from_u32(0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00));
+    }
+    // a low surrogate on its own is not a char
+    ::std::char::from_u32(unit)
+}
+
+/// Four hex digits, and no fewer.
+fn hex(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let hex: String = chars.by_ref().take(4).collect();
+    if hex.len() != 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use convert::{Issue, IssueKind};
+    use serializer::serialize;
+    use super::to_l20n;
+
+    #[test]
+    fn test_to_l20n() {
+        let (entries, issues) = to_l20n("\
+! A note
+#   on two lines.
+a.b=x
+  spaced   :   value with   spaces
+colon:value
+space value
+empty
+escaped\\ key\\=x = \\u00e9t\\u00E9\\n\\t\\\\
+braces = {0} {{ count }} {not a name} {name}
+multi = one \\
+        two \\\\
+a-b = also a_b
+a_b = duplicate
+# trailing");
+        assert_eq!(issues, vec![
+            Issue { id: "escaped key=x".into(), kind: IssueKind::Renamed("escaped_key_x".into()) },
+            Issue { id: "a-b".into(), kind: IssueKind::Duplicate },
+            Issue { id: "a_b".into(), kind: IssueKind::Duplicate },
+        ]);
+        assert_eq!(serialize(&entries), "\
+/* A note
+on two lines. */
+<a_b \"x\">
+<spaced \"value with   spaces\">
+<colon \"value\">
+<space \"value\">
+<empty \"\">
+<escaped_key_x \"été\n\t\\\\\">
+<braces \"{0} {{ $count }} {not a name} {{ $name }}\">
+<multi \"one two \\\\\">
+/* trailing */
+");
+    }
+
+    #[test]
+    fn test_unicode_escapes() {
+        let (entries, issues) = to_l20n("\
+smile = \\uD83D\\uDE00
+short = \\u12 left
+sign = \\u+abc
+lone = \\uD83D!
+
+low = \\uDE00\\
+  x");
+        assert_eq!(issues, vec![
+            Issue { id: "short".into(), kind: IssueKind::Syntax(1) },
+            Issue { id: "sign".into(), kind: IssueKind::Syntax(2) },
+            Issue { id: "lone".into(), kind: IssueKind::Syntax(3) },
+            Issue { id: "low".into(), kind: IssueKind::Syntax(5) },
+        ]);
+        assert_eq!(serialize(&entries), "\
+<smile \"\u{1f600}\">
+<short \"\\\\u12 left\">
+<sign \"\\\\u+abc\">
+<lone \"\\\\uD83D!\">
+<low \"\\\\uDE00x\">
+");
+    }
+}
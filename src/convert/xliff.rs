@@ -0,0 +1,864 @@
+//! XLIFF 1.2 and 2.0 documents, for translation tools.
+//!
+//! `from_locale` writes the text of a source `Locale` out as units for
+//! translators, and `to_l20n` reads their translation back in as a resource
+//! for the target language. Only text goes out; everything else, such as
+//! the indices of Hashes and macros, is taken from the source on the way
+//! back in.
+//!
+//! | L20n                                 | XLIFF                                    |
+//! |--------------------------------------|------------------------------------------|
+//! | `<hi "Hi">`                          | the unit `hi`                            |
+//! | `<hi "" title: "Hi">`                | the unit `hi.title`, and none for the empty value |
+//! | `"Hi, {{ $name }}"`                  | `Hi, <ph id="1">{{ $name }}</ph>` in 1.2 |
+//! |                                      | `Hi, <ph id="1" dataRef="d1"/>`, with `{{ $name }}` in `originalData`, in 2.0 |
+//! | `<n { a: "A", *b: { c: "C" } }>`     | the units `n[a]` and `n[b][c]`           |
+//! | `<n[plural($n)] { ... }>`            | a unit for each plural category of the target language |
+//! | `/* note */` before an entity        | a note on its first unit                 |
+//!
+//! A placeable is an inline code that translators can move but not change:
+//! on the way back in, each placeholder is replaced by the source's
+//! placeable with the same id, whatever a tool left in it. A translation
+//! that loses a placeholder, or has one the source does not, is not used.
+//!
+//! The plural categories of the target language are those of
+//! `po::PluralForms`. Its `plural` macro is made the same way as for PO
+//! catalogs, while other macros are copied from the source. 2.0 requires
+//! unit ids that are names, so units are numbered there, and the names
+//! above are in their `name`.
+//!
+//! # Issues
+//!
+//! To XLIFF:
+//!
+//! - `Macro`: a macro, which is not translated.
+//! - `PluralForms`: a target language whose plural forms are not known.
+//!   Plural Hashes have the keys of the source.
+//!
+//! From XLIFF:
+//!
+//! - `Placeholder`: a placeholder that does not match the source, or that
+//!   is used more than once. The entity is left out.
+//! - `Unknown`: a unit for an entity that is not in the source.
+//! - `PluralForms`: a target language whose plural forms are not known.
+//!   The `plural` macro of the source is kept.
+//! - `Syntax`: XML that could not be read, by its line. Nothing is read.
+//!
+//! Entities with a unit that has not been translated are left out.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use ast::*;
+use context::Locale;
+use convert::{is_plural, Issue, IssueKind, Pieces, PLURAL};
+use convert::po::PluralForms;
+use langtag::LanguageTag;
+use serializer::serialize_expr;
+
+/// A version of XLIFF.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Version {
+    /// XLIFF 1.2.
+    V1_2,
+    /// XLIFF 2.0.
+    V2_0,
+}
+
+/// Writes the text of a Locale out for translation into `target`.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::xliff::{self, Version};
+///
+/// let mut locale = l20n::Locale::with_language("en".parse().unwrap());
+/// locale.add_resource("<hi 'Hi, {{ $name }}!'>").unwrap();
+/// let (xliff, issues) = xliff::from_locale(&locale, &"fr".parse().unwrap(), Version::V1_2);
+/// assert!(issues.is_empty());
+/// assert!(xliff.contains(r#"<source>Hi, <ph id="1">{{ $name }}</ph>!</source>"#));
+/// ```
+pub fn from_locale(source: &Locale, target: &LanguageTag, version: Version) -> (String, Vec<Issue>) {
+    let mut issues = vec![];
+    let forms = PluralForms::for_language(target);
+    if forms.is_none() {
+        issues.push(Issue::new("", IssueKind::PluralForms(target.to_string())));
+    }
+    let categories = forms.as_ref().map(|forms| &forms.categories[..]);
+
+    source.with_definitions(|definitions| {
+        let mut units = vec![];
+        for &(id, entry) in definitions {
+            match *entry {
+                Entity(_, ref value, ref indices, ref attrs) => {
+                    let start = units.len();
+                    leaves(id.to_string(), value, indices, 0, categories, &mut units);
+                    for Attr(name, value, indices) in attrs {
+                        leaves(format!("{}.{}", id, name), value, indices, 0, categories, &mut units);
+                    }
+                    if let Some(unit) = units.get_mut(start) {
+                        unit.note = source.note(id).map(str::trim);
+                    }
+                }
+                Macro(..) => issues.push(Issue::new(id, IssueKind::Macro)),
+                _ => {}
+            }
+        }
+
+        let source_language = source.language().map_or_else(|| "und".to_string(), |tag| tag.to_string());
+        (write(&units, &source_language, target, version), issues)
+    })
+}
+
+/// Writes the units as an XLIFF document.
+fn write(units: &[Unit], source_language: &str, target: &LanguageTag, version: Version) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    match version {
+        Version::V1_2 => {
+            let _ = write!(out, "<xliff version=\"1.2\" xmlns=\"urn:oasis:names:tc:xliff:document:1.2\">\n  \
+                                 <file original=\"l20n\" datatype=\"plaintext\" source-language=\"{}\" \
+                                 target-language=\"{}\">\n    <body>\n", source_language, target);
+            for unit in units {
+                out.push_str("      <trans-unit id=\"");
+                escape(&mut out, &unit.key, true);
+                out.push_str("\" xml:space=\"preserve\">\n        <source>");
+                write_segments(&mut out, &unit.segments, version);
+                out.push_str("</source>\n");
+                if let Some(note) = unit.note {
+                    out.push_str("        <note>");
+                    escape(&mut out, note, false);
+                    out.push_str("</note>\n");
+                }
+                out.push_str("      </trans-unit>\n");
+            }
+            out.push_str("    </body>\n  </file>\n</xliff>\n");
+        }
+        Version::V2_0 => {
+            let _ = write!(out, "<xliff version=\"2.0\" xmlns=\"urn:oasis:names:tc:xliff:document:2.0\" \
+                                 srcLang=\"{}\" trgLang=\"{}\">\n  <file id=\"f1\">\n", source_language, target);
+            for (i, unit) in units.iter().enumerate() {
+                let _ = write!(out, "    <unit id=\"u{}\" name=\"", i + 1);
+                escape(&mut out, &unit.key, true);
+                out.push_str("\" xml:space=\"preserve\">\n");
+                if let Some(note) = unit.note {
+                    out.push_str("      <notes>\n        <note>");
+                    escape(&mut out, note, false);
+                    out.push_str("</note>\n      </notes>\n");
+                }
+                let codes: Vec<_> = unit.segments.iter().filter_map(|segment| match *segment {
+                    Segment::Code(expr) => Some(expr),
+                    Segment::Text(_) => None,
+                }).collect();
+                if !codes.is_empty() {
+                    out.push_str("      <originalData>\n");
+                    for (i, expr) in codes.into_iter().enumerate() {
+                        let _ = write!(out, "        <data id=\"d{}\">", i + 1);
+                        escape(&mut out, &placeable(expr), false);
+                        out.push_str("</data>\n");
+                    }
+                    out.push_str("      </originalData>\n");
+                }
+                out.push_str("      <segment>\n        <source>");
+                write_segments(&mut out, &unit.segments, version);
+                out.push_str("</source>\n      </segment>\n    </unit>\n");
+            }
+            out.push_str("  </file>\n</xliff>\n");
+        }
+    }
+    out
+}
+
+/// Reads a translated XLIFF document into a resource, taking everything
+/// but the text from `source`, the Locale it was written from.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::convert::xliff;
+///
+/// let mut locale = l20n::Locale::new();
+/// locale.add_resource("<hi 'Hi, {{ $name }}!'>").unwrap();
+/// let (entries, issues) = xliff::to_l20n(r#"
+/// <xliff version="1.2"><file target-language="fr"><body>
+///   <trans-unit id="hi">
+///     <source>Hi, <ph id="1">{{ $name }}</ph>!</source>
+///     <target>Salut, <ph id="1">{{ $name }}</ph> !</target>
+///   </trans-unit>
+/// </body></file></xliff>
+/// "#, &locale);
+/// assert!(issues.is_empty());
+/// assert_eq!(l20n::serialize(&entries), "<hi \"Salut, {{ $name }} !\">\n");
+/// ```
+pub fn to_l20n(xliff: &str, source: &Locale) -> (Vec<Entry<'static>>, Vec<Issue>) {
+    let mut reader = Reader { source: xliff, pos: 0, depth: 0 };
+    let root = match reader.document() {
+        Ok(root) => root,
+        Err(()) => return (vec![], vec![Issue::new("", IssueKind::Syntax(reader.line()))]),
+    };
+
+    let mut issues = vec![];
+    let target = root.attr("trgLang")
+        .or_else(|| root.elements().find(|e| e.name == "file").and_then(|file| file.attr("target-language")));
+    let forms = match target {
+        Some(tag) => {
+            let forms = LanguageTag::parse(tag).ok().as_ref().and_then(PluralForms::for_language);
+            if forms.is_none() {
+                issues.push(Issue::new("", IssueKind::PluralForms(tag.to_string())));
+            }
+            forms
+        }
+        None => None,
+    };
+
+    let mut units = vec![];
+    collect_units(&root, &mut units);
+    let definitions: Vec<_> = source.with_definitions(|definitions| {
+        definitions.iter().map(|&(id, entry)| (id.to_string(), entry.clone().into_owned())).collect()
+    });
+    for (key, _) in &units {
+        let id = &key[..key.find(['.', '[']).unwrap_or(key.len())];
+        if !source.defines(id) {
+            issues.push(Issue::new(&key[..], IssueKind::Unknown));
+        }
+    }
+
+    let mut importer = Importer {
+        targets: units.into_iter().filter_map(|(key, target)| target.map(|t| (key, t))).collect(),
+        categories: forms.as_ref().map(|forms| forms.categories.clone()),
+        issues,
+    };
+    let mut entries = vec![];
+    for (id, entry) in definitions {
+        match entry {
+            Entity(_, ref value, ref indices, ref attrs) => {
+                if let Some(entity) = importer.entity(&id, value, indices, attrs) {
+                    entries.push(entity);
+                }
+            }
+            Macro(..) if id == PLURAL => {
+                match forms.as_ref().and_then(PluralForms::to_macro) {
+                    Some(plural) => entries.push(plural),
+                    None => entries.push(entry),
+                }
+            }
+            _ => entries.push(entry),
+        }
+    }
+    (entries, importer.issues)
+}
+
+/// A unit to translate.
+struct Unit<'v, 'a: 'v> {
+    key: String,
+    segments: Vec<Segment<'v, 'a>>,
+    note: Option<&'v str>,
+}
+
+enum Segment<'v, 'a: 'v> {
+    Text(&'v str),
+    /// A placeable.
+    Code(&'v Expr<'a>),
+}
+
+/// Adds a unit for each string in a value, the Hashes in it keyed as
+/// described in the module.
+fn leaves<'v, 'a>(key: String, value: &'v Value<'a>, indices: &[Expr], level: usize, categories: Option<&[String]>,
+                  units: &mut Vec<Unit<'v, 'a>>) {
+    match *value {
+        Hash(ref map, ref default, ref def_index) => {
+            let index = indices.get(level).or(def_index.as_ref().map(|index| &**index));
+            let categories = categories.filter(|_| index.is_some_and(is_plural));
+            for (k, value) in branches(map, default, categories) {
+                leaves(format!("{}[{}]", key, k), value, indices, level + 1, categories, units);
+            }
+        }
+        _ => {
+            let segments = segments(value);
+            if !segments.is_empty() {
+                units.push(Unit { key, segments, note: None });
+            }
+        }
+    }
+}
+
+/// The keys of a Hash that are translated, and the source value of each:
+/// the plural categories of the target language for a plural Hash, with the
+/// default value for those the source does not have, or else its own keys.
+fn branches<'v, 'a>(map: &'v HashMap<Cow<'a, str>, Value<'a>>, default: &Option<Cow<'a, str>>,
+                    categories: Option<&[String]>) -> Vec<(String, &'v Value<'a>)> {
+    match categories {
+        Some(categories) => categories.iter().filter_map(|category| {
+            map.get(&category[..])
+                .or_else(|| default.as_ref().and_then(|default| map.get(default)))
+                .map(|value| (category.clone(), value))
+        }).collect(),
+        None => {
+            let mut branches: Vec<_> = map.iter().map(|(key, value)| (key.to_string(), value)).collect();
+            branches.sort_by(|a, b| a.0.cmp(&b.0));
+            branches
+        }
+    }
+}
+
+/// The text and placeables of a string, leaving out empty text.
+fn segments<'v, 'a>(value: &'v Value<'a>) -> Vec<Segment<'v, 'a>> {
+    match *value {
+        Str(ref s) if s.is_empty() => vec![],
+        Str(ref s) => vec![Segment::Text(s)],
+        ComplexStr(ref pieces) => {
+            // text and placeables take turns, as the parser leaves them
+            let mut segments = vec![];
+            let mut text_next = true;
+            for piece in pieces {
+                match *piece {
+                    ValExpr(Str(ref s)) if text_next => {
+                        if !s.is_empty() {
+                            segments.push(Segment::Text(s));
+                        }
+                        text_next = false;
+                    }
+                    ref expr => {
+                        segments.push(Segment::Code(expr));
+                        text_next = true;
+                    }
+                }
+            }
+            segments
+        }
+        Hash(..) => vec![],
+    }
+}
+
+fn placeable(expr: &Expr) -> String {
+    format!("{{{{ {} }}}}", serialize_expr(expr))
+}
+
+fn write_segments(out: &mut String, segments: &[Segment], version: Version) {
+    let mut id = 0;
+    for segment in segments {
+        match *segment {
+            Segment::Text(text) => escape(out, text, false),
+            Segment::Code(expr) => {
+                id += 1;
+                match version {
+                    Version::V1_2 => {
+                        let _ = write!(out, "<ph id=\"{}\">", id);
+                        escape(out, &placeable(expr), false);
+                        out.push_str("</ph>");
+                    }
+                    Version::V2_0 => {
+                        let _ = write!(out, "<ph id=\"{}\" dataRef=\"d{}\"/>", id, id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn escape(out: &mut String, text: &str, attr: bool) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attr => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Finds the units of a document, with their targets if they are
+/// translated: one `target` in 1.2, one for each segment in 2.0.
+fn collect_units<'d>(element: &'d Element, units: &mut Vec<(String, Option<Vec<&'d Element>>)>) {
+    for child in element.elements() {
+        let key = child.attr("resname").or_else(|| child.attr("name")).or_else(|| child.attr("id"));
+        match (&child.name[..], key) {
+            ("trans-unit", Some(key)) => {
+                let target = child.elements().find(|e| e.name == "target").map(|target| vec![target]);
+                units.push((key.to_string(), target));
+            }
+            ("unit", Some(key)) => {
+                let mut targets = Some(vec![]);
+                for part in child.elements().filter(|e| e.name == "segment" || e.name == "ignorable") {
+                    let target = part.elements().find(|e| e.name == "target");
+                    // whitespace between segments need not be translated
+                    let target = match target {
+                        None if part.name == "ignorable" => part.elements().find(|e| e.name == "source"),
+                        target => target,
+                    };
+                    match (target, targets.as_mut()) {
+                        (Some(target), Some(targets)) => targets.push(target),
+                        _ => targets = None,
+                    }
+                }
+                units.push((key.to_string(), targets));
+            }
+            _ => collect_units(child, units),
+        }
+    }
+}
+
+struct Importer<'d> {
+    targets: HashMap<String, Vec<&'d Element>>,
+    categories: Option<Vec<String>>,
+    issues: Vec<Issue>,
+}
+
+impl<'d> Importer<'d> {
+    fn entity(&mut self, id: &str, value: &Value<'static>, indices: &[Expr<'static>], attrs: &[Attr<'static>])
+              -> Option<Entry<'static>> {
+        let value = self.value(id.to_string(), value, indices, 0)?;
+        let mut translated = vec![];
+        for Attr(name, value, indices) in attrs {
+            let value = self.value(format!("{}.{}", id, name), value, indices, 0)?;
+            translated.push(Attr(name.clone(), value, indices.clone()));
+        }
+        Some(Entity(Cow::Owned(id.to_string()), value, indices.to_vec(), translated))
+    }
+
+    fn value(&mut self, key: String, value: &Value<'static>, indices: &[Expr<'static>], level: usize)
+             -> Option<Value<'static>> {
+        match *value {
+            Hash(ref map, ref default, ref def_index) => {
+                let index = indices.get(level).or(def_index.as_ref().map(|index| &**index));
+                let categories = self.categories.clone().filter(|_| index.is_some_and(is_plural));
+                let mut translated = HashMap::new();
+                for (k, value) in branches(map, default, categories.as_ref().map(|c| &c[..])) {
+                    let value = self.value(format!("{}[{}]", key, k), value, indices, level + 1)?;
+                    translated.insert(Cow::Owned(k), value);
+                }
+                let default = match categories {
+                    Some(ref categories) if categories.iter().any(|c| c == "other") => Some(Cow::Borrowed("other")),
+                    Some(ref categories) => categories.last().map(|c| Cow::Owned(c.clone())),
+                    None => default.clone(),
+                };
+                Some(Hash(translated, default, def_index.clone()))
+            }
+            _ => self.text(&key, value),
+        }
+    }
+
+    /// The translation of a string, with the placeables of the source.
+    fn text(&mut self, key: &str, value: &Value<'static>) -> Option<Value<'static>> {
+        let codes: Vec<_> = segments(value).into_iter().filter_map(|segment| match segment {
+            Segment::Code(expr) => Some(expr),
+            Segment::Text(_) => None,
+        }).collect();
+        if segments(value).is_empty() {
+            return Some(value.clone());
+        }
+        let targets = self.targets.get(key)?;
+        if targets.iter().all(|target| target.children.is_empty()) {
+            return None;
+        }
+
+        let mut pieces = Pieces::default();
+        let mut used = vec![false; codes.len()];
+        for target in targets {
+            if let Err(id) = inline(target, &codes, &mut pieces, &mut used) {
+                self.issues.push(Issue::new(key, IssueKind::Placeholder(id)));
+                return None;
+            }
+        }
+        if let Some(i) = used.iter().position(|&used| !used) {
+            self.issues.push(Issue::new(key, IssueKind::Placeholder((i + 1).to_string())));
+            return None;
+        }
+        Some(pieces.finish())
+    }
+}
+
+/// Adds the text of a target to `pieces`, with each placeholder replaced
+/// by its code. Other inline elements are left out, but not their text. A
+/// placeholder that is not in the source, or is used twice, is returned.
+fn inline(element: &Element, codes: &[&Expr<'static>], pieces: &mut Pieces, used: &mut [bool]) -> Result<(), String> {
+    for child in &element.children {
+        match *child {
+            Node::Text(ref text) => pieces.text.push_str(text),
+            Node::Element(ref child) if child.name == "ph" || child.name == "x" => {
+                let id = child.attr("id").unwrap_or_default();
+                match id.parse::<usize>() {
+                    Ok(i) if i >= 1 && i <= codes.len() && !used[i - 1] => {
+                        pieces.expr(codes[i - 1].clone());
+                        used[i - 1] = true;
+                    }
+                    _ => return Err(id.to_string()),
+                }
+            }
+            Node::Element(ref child) => inline(child, codes, pieces, used)?,
+        }
+    }
+    Ok(())
+}
+
+/// An XML element, with the namespace prefixes of names left out.
+#[derive(Debug)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|attr| attr.0 == name).map(|attr| &attr.1[..])
+    }
+
+    fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match *child {
+            Node::Element(ref element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    fn push_text(&mut self, text: String) {
+        if let Some(Node::Text(last)) = self.children.last_mut() {
+            last.push_str(&text);
+            return;
+        }
+        self.children.push(Node::Text(text));
+    }
+}
+
+/// Reads just enough XML for XLIFF: elements, attributes, text, character
+/// references, CDATA, and comments and declarations to skip.
+struct Reader<'a> {
+    source: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+// deep enough for any real document, shallow enough not to overflow the stack
+const MAX_DEPTH: usize = 256;
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn line(&self) -> usize {
+        self.source[..self.pos].matches('\n').count()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let found = self.rest().starts_with(s);
+        if found {
+            self.pos += s.len();
+        }
+        found
+    }
+
+    /// Moves past `end`, returning what came before it.
+    fn until(&mut self, end: &str) -> Result<&'a str, ()> {
+        let rest = self.rest();
+        let i = rest.find(end).ok_or(())?;
+        self.pos += i + end.len();
+        Ok(&rest[..i])
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips comments, processing instructions and declarations.
+    fn skip_misc(&mut self) -> Result<(), ()> {
+        loop {
+            self.skip_space();
+            if self.eat("<!--") {
+                self.until("-->")?;
+            } else if self.eat("<?") {
+                self.until("?>")?;
+            } else if self.eat("<!") {
+                self.until(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, ()> {
+        let rest = self.rest();
+        let end = rest.find(|c: char| c.is_whitespace() || "/>=".contains(c)).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(());
+        }
+        self.pos += end;
+        Ok(&rest[..end])
+    }
+
+    fn document(&mut self) -> Result<Element, ()> {
+        self.skip_misc()?;
+        let root = self.element()?;
+        self.skip_misc()?;
+        if self.rest().is_empty() {
+            Ok(root)
+        } else {
+            Err(())
+        }
+    }
+
+    fn element(&mut self) -> Result<Element, ()> {
+        if !self.eat("<") {
+            return Err(());
+        }
+        let name = self.name()?;
+        let mut element = Element { name: local(name), attrs: vec![], children: vec![] };
+        loop {
+            self.skip_space();
+            if self.eat("/>") {
+                return Ok(element);
+            }
+            if self.eat(">") {
+                break;
+            }
+            let attr = local(self.name()?);
+            self.skip_space();
+            if !self.eat("=") {
+                return Err(());
+            }
+            self.skip_space();
+            let quote = if self.eat("\"") { "\"" } else if self.eat("'") { "'" } else { return Err(()) };
+            let value = unescape(self.until(quote)?)?;
+            element.attrs.push((attr, value));
+        }
+        loop {
+            let rest = self.rest();
+            let text = rest.find('<').ok_or(())?;
+            if text > 0 {
+                element.push_text(unescape(&rest[..text])?);
+                self.pos += text;
+            }
+            if self.eat("</") {
+                if self.name()? != name {
+                    return Err(());
+                }
+                self.skip_space();
+                return if self.eat(">") { Ok(element) } else { Err(()) };
+            } else if self.eat("<![CDATA[") {
+                let text = self.until("]]>")?;
+                element.push_text(text.to_string());
+            } else if self.eat("<!--") {
+                self.until("-->")?;
+            } else if self.eat("<?") {
+                self.until("?>")?;
+            } else {
+                if self.depth == MAX_DEPTH {
+                    return Err(());
+                }
+                self.depth += 1;
+                let child = self.element();
+                self.depth -= 1;
+                element.children.push(Node::Element(child?));
+            }
+        }
+    }
+}
+
+/// A name without its namespace prefix.
+fn local(name: &str) -> String {
+    name[name.find(':').map_or(0, |i| i + 1)..].to_string()
+}
+
+/// Text with its entity and character references replaced.
+fn unescape(text: &str) -> Result<String, ()> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        let end = rest[i..].find(';').ok_or(())? + i;
+        let reference = &rest[i + 1..end];
+        let c = match reference {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match reference.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => reference.strip_prefix('#').ok_or(())?.parse(),
+                };
+                code.ok().and_then(::std::char::from_u32).ok_or(())?
+            }
+        };
+        out.push(c);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use context::Locale;
+    use convert::{Issue, IssueKind};
+    use serializer::serialize;
+    use super::{from_locale, to_l20n, Version};
+
+    const SOURCE: &str = r#"
+<plural($n) { $n == 1 ? "one" : "other" }>
+/* On the start page. */
+<hi "Hi, {{ $name }} & {{ hi::title }}!" title: "Say <hi>">
+<emails[plural($n)] { one: "One email", *other: "{{ $n }} emails" }>
+<tab "" accesskey: "T">
+<gender[$g] { he: "his", *they: { a: "their", b: "{{ $g }}" } }>
+"#;
+
+    fn source() -> Locale {
+        let mut locale = Locale::with_language("en".parse().unwrap());
+        locale.add_resource(SOURCE).unwrap();
+        locale
+    }
+
+    #[test]
+    fn test_from_locale() {
+        let (xliff, issues) = from_locale(&source(), &"ru".parse().unwrap(), Version::V1_2);
+        assert_eq!(issues, vec![Issue { id: "plural".into(), kind: IssueKind::Macro }]);
+        assert_eq!(xliff, r#"<?xml version="1.0" encoding="UTF-8"?>
+<xliff version="1.2" xmlns="urn:oasis:names:tc:xliff:document:1.2">
+  <file original="l20n" datatype="plaintext" source-language="en" target-language="ru">
+    <body>
+      <trans-unit id="hi" xml:space="preserve">
+        <source>Hi, <ph id="1">{{ $name }}</ph> &amp; <ph id="2">{{ hi::title }}</ph>!</source>
+        <note>On the start page.</note>
+      </trans-unit>
+      <trans-unit id="hi.title" xml:space="preserve">
+        <source>Say &lt;hi&gt;</source>
+      </trans-unit>
+      <trans-unit id="emails[one]" xml:space="preserve">
+        <source>One email</source>
+      </trans-unit>
+      <trans-unit id="emails[few]" xml:space="preserve">
+        <source><ph id="1">{{ $n }}</ph> emails</source>
+      </trans-unit>
+      <trans-unit id="emails[many]" xml:space="preserve">
+        <source><ph id="1">{{ $n }}</ph> emails</source>
+      </trans-unit>
+      <trans-unit id="tab.accesskey" xml:space="preserve">
+        <source>T</source>
+      </trans-unit>
+      <trans-unit id="gender[he]" xml:space="preserve">
+        <source>his</source>
+      </trans-unit>
+      <trans-unit id="gender[they][a]" xml:space="preserve">
+        <source>their</source>
+      </trans-unit>
+      <trans-unit id="gender[they][b]" xml:space="preserve">
+        <source><ph id="1">{{ $g }}</ph></source>
+      </trans-unit>
+    </body>
+  </file>
+</xliff>
+"#);
+
+        let (xliff, issues) = from_locale(&source(), &"tlh".parse().unwrap(), Version::V2_0);
+        assert_eq!(issues[0], Issue { id: "".into(), kind: IssueKind::PluralForms("tlh".into()) });
+        assert!(xliff.contains(r#"<xliff version="2.0" xmlns="urn:oasis:names:tc:xliff:document:2.0" srcLang="en" trgLang="tlh">"#));
+        assert!(xliff.contains(r#"    <unit id="u1" name="hi" xml:space="preserve">
+      <notes>
+        <note>On the start page.</note>
+      </notes>
+      <originalData>
+        <data id="d1">{{ $name }}</data>
+        <data id="d2">{{ hi::title }}</data>
+      </originalData>
+      <segment>
+        <source>Hi, <ph id="1" dataRef="d1"/> &amp; <ph id="2" dataRef="d2"/>!</source>
+      </segment>
+    </unit>
+"#));
+        // the source's own plural keys
+        assert!(xliff.contains(r#"name="emails[other]""#));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &version in &[Version::V1_2, Version::V2_0] {
+            let (xliff, _) = from_locale(&source(), &"fr".parse().unwrap(), version);
+            // translate each unit as its source
+            let mut translated = String::new();
+            for line in xliff.lines() {
+                translated.push_str(line);
+                translated.push('\n');
+                if let Some(source) = line.trim().strip_prefix("<source>") {
+                    translated.push_str(&format!("<target>{}\n", source.replace("</source>", "</target>")));
+                }
+            }
+            let (entries, issues) = to_l20n(&translated, &source());
+            assert_eq!(issues, vec![]);
+            assert_eq!(serialize(&entries), r#"<plural($n) { $n > 1 ? "other" : "one" }>
+<hi "Hi, {{ $name }} & {{ hi::title }}!" title: "Say <hi>">
+<emails[plural($n)] { one: "One email", *other: "{{ $n }} emails" }>
+<tab "" accesskey: "T">
+<gender[$g] { he: "his", *they: { a: "their", b: "{{ $g }}" } }>
+"#);
+        }
+    }
+
+    #[test]
+    fn test_to_l20n() {
+        let (entries, issues) = to_l20n(r#"<?xml version="1.0"?>
+<!-- translated -->
+<xliff xmlns="urn:oasis:names:tc:xliff:document:2.0" version="2.0" srcLang="en" trgLang="de">
+  <file id="f">
+    <unit id="hi" name="hi">
+      <segment><source>Hi, <ph id="1"/></source><target>Hallo <pc id="b">&#x201E;<ph id="2"/>&#8220;</pc>, <ph id="1"/></target></segment>
+    </unit>
+    <unit id="title" name="hi.title">
+      <segment><source>Say</source><target><![CDATA[Sag <hi>]]></target></segment>
+    </unit>
+    <unit id="x" name="emails[one]">
+      <segment><source>One email</source><target>Eine E-Mail</target></segment>
+    </unit>
+    <unit id="y" name="emails[other]">
+      <segment><source><ph id="1"/> emails</source><target>E-Mails</target></segment>
+    </unit>
+    <unit id="z" name="tab.accesskey">
+      <segment><source>T</source><target>R</target></segment>
+      <ignorable><source> </source></ignorable>
+      <segment><source>T</source><target>S</target></segment>
+    </unit>
+    <unit id="t" name="gender[they][a]">
+      <segment><source>their</source><target>ihr <ph id="1"/></target></segment>
+    </unit>
+    <unit id="g" name="gender[he]">
+      <segment><source>his</source><target>sein</target></segment>
+    </unit>
+    <unit id="a" name="tab"><segment><source>T</source><target/></segment></unit>
+    <unit id="n" name="new"><segment><source>New</source><target>Neu</target></segment></unit>
+  </file>
+</xliff>
+"#, &source());
+        assert_eq!(issues, vec![
+            Issue { id: "new".into(), kind: IssueKind::Unknown },
+            Issue { id: "emails[other]".into(), kind: IssueKind::Placeholder("1".into()) },
+            Issue { id: "gender[they][a]".into(), kind: IssueKind::Placeholder("1".into()) },
+        ]);
+        assert_eq!(serialize(&entries), "\
+<plural($n) { $n != 1 ? \"other\" : \"one\" }>
+<hi \"Hallo „{{ hi::title }}“, {{ $name }}\" title: \"Sag <hi>\">
+<tab \"\" accesskey: \"R S\">
+");
+
+        let (entries, issues) = to_l20n("<xliff>\n<file>\n</xliff>", &source());
+        assert_eq!(entries, vec![]);
+        assert_eq!(issues, vec![Issue { id: "".into(), kind: IssueKind::Syntax(2) }]);
+
+        let (entries, issues) = to_l20n(r#"<xliff version="1.2"><file><body>
+<trans-unit id="hi"><source/><target>Hallo <x id="2"/><x id="1"/><x id="1"/></target></trans-unit>
+</body></file></xliff>"#, &source());
+        assert_eq!(serialize(&entries), "<plural($n) { $n == 1 ? \"one\" : \"other\" }>\n");
+        assert_eq!(issues, vec![Issue { id: "hi".into(), kind: IssueKind::Placeholder("1".into()) }]);
+
+        let deep = format!("<xliff>{}{}</xliff>", "<g>".repeat(100_000), "</g>".repeat(100_000));
+        let (entries, issues) = to_l20n(&deep, &source());
+        assert_eq!(entries, vec![]);
+        assert_eq!(issues, vec![Issue { id: "".into(), kind: IssueKind::Syntax(0) }]);
+    }
+}
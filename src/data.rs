@@ -25,6 +25,28 @@ impl Data {
     }
 }
 
+/// A `$var` of any type, so variables of different types can be passed
+/// together in one map, as the accessors generated by `build` do.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arg<'a> {
+    /// A string.
+    Str(&'a str),
+    /// A number.
+    Num(i64),
+    /// A boolean.
+    Bool(bool),
+}
+
+impl<'a> serde::Serialize for Arg<'a> {
+    fn serialize<S: serde::Serializer>(&self, s: &mut S) -> Result<(), S::Error> {
+        match *self {
+            Arg::Str(v) => s.visit_str(v),
+            Arg::Num(v) => s.visit_i64(v),
+            Arg::Bool(v) => s.visit_bool(v),
+        }
+    }
+}
+
 #[doc(hidden)]
 pub struct Encoder {
     data: Vec<Data>
@@ -81,7 +103,7 @@ impl serde::Serializer for Encoder {
         match self.data.pop() {
             Some(List(mut list)) => {
                 let mut elt_encoder = Encoder::new();
-                try!(v.serialize(&mut elt_encoder));
+                v.serialize(&mut elt_encoder)?;
                 list.push(elt_encoder.data.pop().unwrap());
                 self.data.push(List(list));
                 Ok(())
@@ -97,13 +119,13 @@ impl serde::Serializer for Encoder {
         match self.data.pop() {
             Some(Map(mut map)) => {
                 let mut map_encoder = Encoder::new();
-                try!(k.serialize(&mut map_encoder));
+                k.serialize(&mut map_encoder)?;
                 let k = match map_encoder.data.pop() {
                     Some(Data::Str(s)) => s,
                     _ => return Err(EncodeError::KeyIsNotString)
                 };
 
-                try!(v.serialize(&mut map_encoder));
+                v.serialize(&mut map_encoder)?;
                 let v = map_encoder.data.pop().unwrap();
                 map.insert(k, v);
                 self.data.push(Map(map));
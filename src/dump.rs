@@ -0,0 +1,539 @@
+//! The syntax tree of a resource as JSON, for runtimes and tools that do
+//! not parse L20n themselves.
+//!
+//! # Schema
+//!
+//! A document is an object with the `version` of the schema, currently `1`,
+//! and the `entries` of the resource, in order:
+//!
+//! ```text
+//! { "version": 1, "entries": [Entry, ...] }
+//! ```
+//!
+//! Every entry, value and expression is an object whose `type` is the name
+//! of its variant in `ast`, with its fields as members:
+//!
+//! | `type`       | Members                                                      |
+//! |--------------|--------------------------------------------------------------|
+//! | `Entity`     | `id`, `value` (Value), `index` ([Expr]), `attrs` ([Attr])    |
+//! | `Macro`      | `id`, `args` ([Expr]), `body` (Expr)                         |
+//! | `Comment`    | `content`                                                    |
+//! | `Import`     | `path`                                                       |
+//! | `Attr`       | `id`, `value` (Value), `index` ([Expr])                      |
+//! | `Str`        | `content`                                                    |
+//! | `ComplexStr` | `content` ([Expr]), literal text being `ValExpr`s of `Str`s  |
+//! | `Hash`       | `items` ({key: Value}), `default` (key or null), `index` (Expr or null) |
+//! | `CondExpr`   | `test`, `consequent`, `alternate` (Expr)                     |
+//! | `BinExpr`    | `left` (Expr), `operator`, `right` (Expr)                    |
+//! | `UnExpr`     | `operator`, `argument` (Expr)                                |
+//! | `VarExpr`    | `name`, without the `$`                                      |
+//! | `ValExpr`    | `value` (Value)                                              |
+//! | `PropExpr`   | `expression`, `property` (Expr), `computed` (boolean)        |
+//! | `AttrExpr`   | `expression`, `attribute` (Expr), `computed` (boolean)       |
+//! | `CallExpr`   | `callee` (Expr), `args` ([Expr])                             |
+//! | `IdentExpr`  | `name`                                                       |
+//! | `NumExpr`    | `value`, an integer                                          |
+//! | `ParenExpr`  | `expression` (Expr)                                          |
+//! | `GlobalExpr` | `name`, without the `@`                                      |
+//! | `ThisExpr`   |                                                              |
+//!
+//! Operators are written as in L20n source, such as `"+"`, `"=="` or `"!"`.
+//! A `NumExpr` is a 64-bit integer; JavaScript reads those beyond 2^53 as
+//! the nearest double.
+//!
+//! Nothing is lost: loading a dump gives back the same entries. Members
+//! that are not in the schema are ignored, so a later version may add them
+//! without breaking readers of this one; anything else changes the version.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use ast::*;
+use json::{self, write_str, Json};
+
+/// The version of the schema that `dump_json` writes and `load_json` reads.
+pub const JSON_VERSION: u64 = 1;
+
+// deep enough for any real resource, shallow enough not to overflow the stack
+const MAX_DEPTH: usize = 256;
+
+const BIN_OPS: [(BinOp, &str); 13] = [
+    (BiAdd, "+"), (BiSub, "-"), (BiMul, "*"), (BiDiv, "/"), (BiRem, "%"), (BiAnd, "&&"), (BiOr, "||"),
+    (BiEq, "=="), (BiNe, "!="), (BiLt, "<"), (BiLe, "<="), (BiGt, ">"), (BiGe, ">="),
+];
+const UN_OPS: [(UnOp, &str); 3] = [(UnAdd, "+"), (UnSub, "-"), (UnNot, "!")];
+
+/// An error loading a resource dumped as JSON.
+#[derive(Debug, PartialEq)]
+pub enum JsonError {
+    /// The data is not JSON, at the given line, counted from 0.
+    Syntax(usize),
+    /// The data is in a version of the schema this crate cannot read.
+    UnsupportedVersion(u64),
+    /// The data is JSON, but not of the schema. The message says where, as
+    /// a path of members and indices from the root, and what is wrong.
+    Malformed(String),
+}
+
+type Result<T> = ::std::result::Result<T, JsonError>;
+
+/// Writes entries out as JSON, as described in the schema above.
+///
+/// Hash items are written in sorted order, so the same entries always give
+/// the same JSON.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// let entries = l20n::parse("<hi \"Hi, {{ $name }}\">").unwrap();
+/// let json = l20n::dump_json(&entries);
+/// assert!(json.starts_with(r#"{"version":1,"entries":[{"type":"Entity","id":"hi","#));
+/// assert_eq!(l20n::load_json(&json).unwrap(), entries);
+/// ```
+pub fn dump_json(entries: &[Entry]) -> String {
+    let mut out = format!("{{\"version\":{},\"entries\":[", JSON_VERSION);
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_entry(&mut out, entry);
+    }
+    out.push_str("]}");
+    out
+}
+
+/// Reads entries back from JSON written by `dump_json`.
+///
+/// Everything is checked, so that JSON that is not a resource is rejected
+/// with a `JsonError` rather than a panic.
+pub fn load_json(source: &str) -> Result<Vec<Entry<'static>>> {
+    let root = json::parse(source).map_err(JsonError::Syntax)?;
+    let r = Reader { depth: 0 };
+    match *field(&root, "", "version")? {
+        Json::Number(ref n) => match n.parse::<u64>() {
+            Ok(JSON_VERSION) => {}
+            Ok(version) => return Err(JsonError::UnsupportedVersion(version)),
+            Err(_) => return Err(malformed("version", "is not a version")),
+        },
+        ref other => return Err(expected("version", "a number", other)),
+    }
+    array(&root, "", "entries")?.iter().enumerate()
+        .map(|(i, entry)| r.entry(entry, &format!("entries[{}]", i)))
+        .collect()
+}
+
+fn write_entry(out: &mut String, entry: &Entry) {
+    match *entry {
+        Entity(ref id, ref value, ref index, ref attrs) => {
+            out.push_str("{\"type\":\"Entity\",\"id\":");
+            write_str(out, id);
+            out.push_str(",\"value\":");
+            write_value(out, value);
+            out.push_str(",\"index\":");
+            write_exprs(out, index);
+            out.push_str(",\"attrs\":[");
+            for (i, attr) in attrs.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str("{\"type\":\"Attr\",\"id\":");
+                write_str(out, &attr.0);
+                out.push_str(",\"value\":");
+                write_value(out, &attr.1);
+                out.push_str(",\"index\":");
+                write_exprs(out, &attr.2);
+                out.push('}');
+            }
+            out.push_str("]}");
+        }
+        Macro(ref id, ref args, ref body) => {
+            out.push_str("{\"type\":\"Macro\",\"id\":");
+            write_str(out, id);
+            out.push_str(",\"args\":");
+            write_exprs(out, args);
+            out.push_str(",\"body\":");
+            write_expr(out, body);
+            out.push('}');
+        }
+        Comment(ref content) => {
+            out.push_str("{\"type\":\"Comment\",\"content\":");
+            write_str(out, content);
+            out.push('}');
+        }
+        Import(ref path) => {
+            out.push_str("{\"type\":\"Import\",\"path\":");
+            write_str(out, path);
+            out.push('}');
+        }
+    }
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match *value {
+        Str(ref content) => {
+            out.push_str("{\"type\":\"Str\",\"content\":");
+            write_str(out, content);
+            out.push('}');
+        }
+        ComplexStr(ref content) => {
+            out.push_str("{\"type\":\"ComplexStr\",\"content\":");
+            write_exprs(out, content);
+            out.push('}');
+        }
+        Hash(ref items, ref default, ref index) => {
+            out.push_str("{\"type\":\"Hash\",\"items\":{");
+            let mut keys: Vec<_> = items.keys().collect();
+            keys.sort();
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_str(out, key);
+                out.push(':');
+                write_value(out, &items[key]);
+            }
+            out.push_str("},\"default\":");
+            match *default {
+                Some(ref key) => write_str(out, key),
+                None => out.push_str("null"),
+            }
+            out.push_str(",\"index\":");
+            match *index {
+                Some(ref index) => write_expr(out, index),
+                None => out.push_str("null"),
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_exprs(out: &mut String, exprs: &[Expr]) {
+    out.push('[');
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_expr(out, expr);
+    }
+    out.push(']');
+}
+
+fn write_expr(out: &mut String, expr: &Expr) {
+    let ty = |out: &mut String, ty: &str| {
+        let _ = write!(out, "{{\"type\":\"{}\"", ty);
+    };
+    let member = |out: &mut String, name: &str| {
+        let _ = write!(out, ",\"{}\":", name);
+    };
+    match *expr {
+        CondExpr(ref test, ref consequent, ref alternate) => {
+            ty(out, "CondExpr");
+            member(out, "test");
+            write_expr(out, test);
+            member(out, "consequent");
+            write_expr(out, consequent);
+            member(out, "alternate");
+            write_expr(out, alternate);
+        }
+        BinExpr(ref left, op, ref right) => {
+            ty(out, "BinExpr");
+            member(out, "left");
+            write_expr(out, left);
+            member(out, "operator");
+            write_str(out, BIN_OPS.iter().find(|o| o.0 == op).unwrap().1);
+            member(out, "right");
+            write_expr(out, right);
+        }
+        UnExpr(op, ref argument) => {
+            ty(out, "UnExpr");
+            member(out, "operator");
+            write_str(out, UN_OPS.iter().find(|o| o.0 == op).unwrap().1);
+            member(out, "argument");
+            write_expr(out, argument);
+        }
+        VarExpr(ref name) => {
+            ty(out, "VarExpr");
+            member(out, "name");
+            write_str(out, name);
+        }
+        ValExpr(ref value) => {
+            ty(out, "ValExpr");
+            member(out, "value");
+            write_value(out, value);
+        }
+        PropExpr(ref expression, ref property, access) => {
+            ty(out, "PropExpr");
+            member(out, "expression");
+            write_expr(out, expression);
+            member(out, "property");
+            write_expr(out, property);
+            member(out, "computed");
+            out.push_str(if access == Computed { "true" } else { "false" });
+        }
+        AttrExpr(ref expression, ref attribute, access) => {
+            ty(out, "AttrExpr");
+            member(out, "expression");
+            write_expr(out, expression);
+            member(out, "attribute");
+            write_expr(out, attribute);
+            member(out, "computed");
+            out.push_str(if access == Computed { "true" } else { "false" });
+        }
+        CallExpr(ref callee, ref args) => {
+            ty(out, "CallExpr");
+            member(out, "callee");
+            write_expr(out, callee);
+            member(out, "args");
+            write_exprs(out, args);
+        }
+        IdentExpr(ref name) => {
+            ty(out, "IdentExpr");
+            member(out, "name");
+            write_str(out, name);
+        }
+        NumExpr(value) => {
+            ty(out, "NumExpr");
+            member(out, "value");
+            let _ = write!(out, "{}", value);
+        }
+        ParenExpr(ref expression) => {
+            ty(out, "ParenExpr");
+            member(out, "expression");
+            write_expr(out, expression);
+        }
+        GlobalExpr(ref name) => {
+            ty(out, "GlobalExpr");
+            member(out, "name");
+            write_str(out, name);
+        }
+        ThisExpr => ty(out, "ThisExpr"),
+    }
+    out.push('}');
+}
+
+fn malformed(path: &str, problem: &str) -> JsonError {
+    JsonError::Malformed(format!("{}: {}", if path.is_empty() { "(root)" } else { path }, problem))
+}
+
+fn expected(path: &str, what: &str, found: &Json) -> JsonError {
+    malformed(path, &format!("expected {}, found {}", what, found.kind()))
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn field<'j>(json: &'j Json, path: &str, key: &str) -> Result<&'j Json> {
+    match *json {
+        Json::Object(_) => json.get(key).ok_or_else(|| malformed(path, &format!("missing `{}`", key))),
+        ref other => Err(expected(path, "an object", other)),
+    }
+}
+
+fn string(json: &Json, path: &str, key: &str) -> Result<Cow<'static, str>> {
+    match *field(json, path, key)? {
+        Json::Str(ref s) => Ok(Cow::Owned(s.clone())),
+        ref other => Err(expected(&join(path, key), "a string", other)),
+    }
+}
+
+fn array<'j>(json: &'j Json, path: &str, key: &str) -> Result<&'j [Json]> {
+    match *field(json, path, key)? {
+        Json::Array(ref items) => Ok(items),
+        ref other => Err(expected(&join(path, key), "an array", other)),
+    }
+}
+
+fn op<T: Copy>(json: &Json, path: &str, ops: &[(T, &str)]) -> Result<T> {
+    let name = string(json, path, "operator")?;
+    ops.iter().find(|o| o.1 == name).map(|o| o.0)
+        .ok_or_else(|| malformed(&join(path, "operator"), &format!("unknown operator `{}`", name)))
+}
+
+fn access(json: &Json, path: &str) -> Result<AccessType> {
+    match *field(json, path, "computed")? {
+        Json::Bool(true) => Ok(Computed),
+        Json::Bool(false) => Ok(Static),
+        ref other => Err(expected(&join(path, "computed"), "a boolean", other)),
+    }
+}
+
+/// Reads the parts of a document below its root, keeping count of how
+/// deeply values and expressions nest. Each part is read from its JSON and
+/// its path, for errors.
+#[derive(Clone, Copy)]
+struct Reader {
+    depth: usize,
+}
+
+impl Reader {
+    fn nested(self, path: &str) -> Result<Reader> {
+        if self.depth == MAX_DEPTH {
+            return Err(malformed(path, "nested too deeply"));
+        }
+        Ok(Reader { depth: self.depth + 1 })
+    }
+
+    /// Reads a member of an object with `read`.
+    fn member<T, F>(self, json: &Json, path: &str, key: &str, read: F) -> Result<T>
+        where F: FnOnce(Reader, &Json, &str) -> Result<T>
+    {
+        read(self, field(json, path, key)?, &join(path, key))
+    }
+
+    fn entry(self, json: &Json, path: &str) -> Result<Entry<'static>> {
+        match &*string(json, path, "type")? {
+            "Entity" => {
+                let attrs = array(json, path, "attrs")?.iter().enumerate().map(|(i, attr)| {
+                    let path = format!("{}.attrs[{}]", path, i);
+                    match &*string(attr, &path, "type")? {
+                        "Attr" => Ok(Attr(string(attr, &path, "id")?,
+                                          self.member(attr, &path, "value", Reader::value)?,
+                                          self.member(attr, &path, "index", Reader::exprs)?)),
+                        other => Err(malformed(&join(&path, "type"), &format!("expected `Attr`, found `{}`", other))),
+                    }
+                }).collect::<Result<_>>()?;
+                Ok(Entity(string(json, path, "id")?, self.member(json, path, "value", Reader::value)?,
+                          self.member(json, path, "index", Reader::exprs)?, attrs))
+            }
+            "Macro" => Ok(Macro(string(json, path, "id")?, self.member(json, path, "args", Reader::exprs)?,
+                                self.member(json, path, "body", Reader::expr)?)),
+            "Comment" => Ok(Comment(string(json, path, "content")?)),
+            "Import" => Ok(Import(string(json, path, "path")?)),
+            other => Err(malformed(&join(path, "type"), &format!("unknown entry `{}`", other))),
+        }
+    }
+
+    fn value(self, json: &Json, path: &str) -> Result<Value<'static>> {
+        let r = self.nested(path)?;
+        match &*string(json, path, "type")? {
+            "Str" => Ok(Str(string(json, path, "content")?)),
+            "ComplexStr" => Ok(ComplexStr(r.member(json, path, "content", Reader::exprs)?)),
+            "Hash" => {
+                let items_path = join(path, "items");
+                let items = match *field(json, path, "items")? {
+                    Json::Object(ref members) => {
+                        let mut items = HashMap::new();
+                        for (key, value) in members {
+                            let value = r.value(value, &join(&items_path, key))?;
+                            if items.insert(Cow::Owned(key.clone()), value).is_some() {
+                                return Err(malformed(&items_path, &format!("duplicate key `{}`", key)));
+                            }
+                        }
+                        items
+                    }
+                    ref other => return Err(expected(&items_path, "an object", other)),
+                };
+                let default = match *field(json, path, "default")? {
+                    Json::Null => None,
+                    Json::Str(ref key) if items.contains_key(&key[..]) => Some(Cow::Owned(key.clone())),
+                    Json::Str(..) => return Err(malformed(&join(path, "default"), "not a key of items")),
+                    ref other => return Err(expected(&join(path, "default"), "a string or null", other)),
+                };
+                let index = match *field(json, path, "index")? {
+                    Json::Null => None,
+                    _ => Some(Box::new(r.member(json, path, "index", Reader::expr)?)),
+                };
+                Ok(Hash(items, default, index))
+            }
+            other => Err(malformed(&join(path, "type"), &format!("unknown value `{}`", other))),
+        }
+    }
+
+    fn exprs(self, json: &Json, path: &str) -> Result<Vec<Expr<'static>>> {
+        match *json {
+            Json::Array(ref items) => {
+                items.iter().enumerate().map(|(i, item)| self.expr(item, &format!("{}[{}]", path, i))).collect()
+            }
+            ref other => Err(expected(path, "an array", other)),
+        }
+    }
+
+    fn expr(self, json: &Json, path: &str) -> Result<Expr<'static>> {
+        let r = self.nested(path)?;
+        let boxed = |key: &str| r.member(json, path, key, Reader::expr).map(Box::new);
+        match &*string(json, path, "type")? {
+            "CondExpr" => Ok(CondExpr(boxed("test")?, boxed("consequent")?, boxed("alternate")?)),
+            "BinExpr" => Ok(BinExpr(boxed("left")?, op(json, path, &BIN_OPS)?, boxed("right")?)),
+            "UnExpr" => Ok(UnExpr(op(json, path, &UN_OPS)?, boxed("argument")?)),
+            "VarExpr" => Ok(VarExpr(string(json, path, "name")?)),
+            "ValExpr" => Ok(ValExpr(r.member(json, path, "value", Reader::value)?)),
+            "PropExpr" => Ok(PropExpr(boxed("expression")?, boxed("property")?, access(json, path)?)),
+            "AttrExpr" => Ok(AttrExpr(boxed("expression")?, boxed("attribute")?, access(json, path)?)),
+            "CallExpr" => Ok(CallExpr(boxed("callee")?, r.member(json, path, "args", Reader::exprs)?)),
+            "IdentExpr" => Ok(IdentExpr(string(json, path, "name")?)),
+            "NumExpr" => match *field(json, path, "value")? {
+                Json::Number(ref n) => n.parse().map(NumExpr)
+                    .map_err(|_| malformed(&join(path, "value"), "is not a 64-bit integer")),
+                ref other => Err(expected(&join(path, "value"), "a number", other)),
+            },
+            "ParenExpr" => Ok(ParenExpr(boxed("expression")?)),
+            "GlobalExpr" => Ok(GlobalExpr(string(json, path, "name")?)),
+            "ThisExpr" => Ok(ThisExpr),
+            other => Err(malformed(&join(path, "type"), &format!("unknown expression `{}`", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parser::parse;
+    use super::{dump_json, load_json, JsonError};
+
+    #[test]
+    fn test_round_trip() {
+        let source = "\
+/* A \"comment\" */
+import('shared.l20n')
+<plural($n) { $n == 0 ? \"zero\" : $n == 1 ? \"one\" : \"other\" }>
+<hi \"Hi {{ $user.name }}, {{ -9223372036854775807 }} {{ (@os) }}\\n\"
+  title[~::gender]: { *a: \"A\", b: { x: \"{{ brand::[$k] }}\" } }>
+<emails[plural($n), $g] { one: \"\", *other: { *he: \"He\" } }>
+<check($a, $b) { !$a || +$b != -2 % 3 ? check($a)[\"k\"] : $b.c }>
+";
+        let entries = parse(source).unwrap();
+        let json = dump_json(&entries);
+        assert_eq!(load_json(&json).unwrap(), entries);
+        assert_eq!(dump_json(&load_json(&json).unwrap()), json);
+    }
+
+    #[test]
+    fn test_dump() {
+        let entries = parse("<a[$n] { *x: \"{{ 1 }}\" }>").unwrap();
+        assert_eq!(dump_json(&entries), "\
+{\"version\":1,\"entries\":[{\"type\":\"Entity\",\"id\":\"a\",\
+\"value\":{\"type\":\"Hash\",\"items\":{\"x\":{\"type\":\"ComplexStr\",\"content\":[\
+{\"type\":\"ValExpr\",\"value\":{\"type\":\"Str\",\"content\":\"\"}},{\"type\":\"NumExpr\",\"value\":1}]}},\
+\"default\":\"x\",\"index\":null},\
+\"index\":[{\"type\":\"VarExpr\",\"name\":\"n\"}],\"attrs\":[]}]}");
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert_eq!(load_json("{\n\"version\": 1,\n"), Err(JsonError::Syntax(2)));
+        assert_eq!(load_json("{\"version\": 2, \"entries\": []}"), Err(JsonError::UnsupportedVersion(2)));
+        assert_eq!(load_json("[]"), Err(JsonError::Malformed("(root): expected an object, found array".into())));
+        assert_eq!(load_json("{\"version\": 1}"), Err(JsonError::Malformed("(root): missing `entries`".into())));
+        assert_eq!(load_json(r#"{"version": 1, "entries": [{"type": "Entity", "id": "a",
+            "value": {"type": "ComplexStr", "content": [{"type": "NumExpr", "value": 1.5}]},
+            "index": [], "attrs": []}]}"#),
+            Err(JsonError::Malformed("entries[0].value.content[0].value: is not a 64-bit integer".into())));
+        assert_eq!(load_json(r#"{"version": 1, "entries": [{"type": "Macro", "id": "m", "args": [],
+            "body": {"type": "BinExpr", "left": {"type": "ThisExpr"}, "operator": "**",
+            "right": {"type": "ThisExpr"}}}], "extra": true}"#),
+            Err(JsonError::Malformed("entries[0].body.operator: unknown operator `**`".into())));
+        assert_eq!(load_json(r#"{"version": 1, "entries": [{"type": "Entity", "id": "a",
+            "value": {"type": "Hash", "items": {"one": {"type": "Str", "content": "1"}}, "default": "other",
+            "index": null}, "index": [], "attrs": []}]}"#),
+            Err(JsonError::Malformed("entries[0].value.default: not a key of items".into())));
+        assert_eq!(load_json(r#"{"version": 1, "entries": [{"type": "Import", "path": "x", "later": 1}]}"#),
+            Ok(vec![::ast::Import("x".into())]));
+
+        let deep = format!("{{\"version\":1,\"entries\":[{{\"type\":\"Macro\",\"id\":\"m\",\"args\":[],\"body\":{}{{\"type\":\"ThisExpr\"}}{}}}]}}",
+                           "{\"type\":\"ParenExpr\",\"expression\":".repeat(300), "}".repeat(300));
+        match load_json(&deep) {
+            Err(JsonError::Syntax(_)) | Err(JsonError::Malformed(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use compiler;
+use loader::LoadError;
+
+/// Reads the resources named by `import('path')`s, for
+/// `Locale::add_resource_from`.
+///
+/// Resources are known by name, and an import's path is relative to the
+/// name of the resource it is in.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use std::collections::HashMap;
+///
+/// let mut resources = HashMap::new();
+/// resources.insert("shared/brand.l20n", "<brandName 'Firefox'>");
+/// resources.insert("app/main.l20n", r#"
+///     import('../shared/brand.l20n')
+///     <about "About {{ brandName }}">
+/// "#);
+///
+/// let mut locale = l20n::Locale::new();
+/// locale.add_resource_from(&resources, "app/main.l20n").unwrap();
+/// assert_eq!(locale.localize_entity("about", ()).unwrap(), "About Firefox");
+/// ```
+pub trait ResourceLoader {
+    /// The name of the resource that `path` refers to, when imported by the
+    /// resource named `base`.
+    ///
+    /// By default names are `/` separated paths, and `path` is relative to
+    /// the directory of `base` unless it starts with a `/`.
+    fn resolve(&self, base: &str, path: &str) -> String {
+        let joined = if path.starts_with('/') {
+            path.to_string()
+        } else {
+            match base.rfind('/') {
+                Some(slash) => format!("{}{}", &base[..slash + 1], path),
+                None => path.to_string(),
+            }
+        };
+        let mut parts: Vec<&str> = vec![];
+        for part in joined.split('/') {
+            match part {
+                "." => {}
+                ".." if parts.last().is_some_and(|p| !p.is_empty() && *p != "..") => {
+                    parts.pop();
+                }
+                _ => parts.push(part),
+            }
+        }
+        parts.join("/")
+    }
+
+    /// Reads the resource named `name`.
+    fn load(&self, name: &str) -> io::Result<String>;
+}
+
+/// Reads resources from files, named by their paths.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileLoader;
+
+impl ResourceLoader for FileLoader {
+    fn resolve(&self, base: &str, path: &str) -> String {
+        let dir = Path::new(base).parent().unwrap_or_else(|| Path::new(""));
+        let mut resolved = PathBuf::new();
+        for component in dir.join(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir if matches!(resolved.components().next_back(), Some(Component::Normal(_))) => {
+                    resolved.pop();
+                }
+                _ => resolved.push(component),
+            }
+        }
+        resolved.to_string_lossy().into_owned()
+    }
+
+    fn load(&self, name: &str) -> io::Result<String> {
+        fs::read_to_string(name)
+    }
+}
+
+/// Resources kept in memory, by name.
+impl<K, V> ResourceLoader for HashMap<K, V>
+    where K: Borrow<str> + Hash + Eq, V: AsRef<str>
+{
+    fn load(&self, name: &str) -> io::Result<String> {
+        match self.get(name) {
+            Some(source) => Ok(source.as_ref().to_string()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no resource named {}", name))),
+        }
+    }
+}
+
+/// Reads the resources named `names` along with everything they import, and
+/// returns their sources in the order to add them: each after the ones it
+/// imports, and each only once. Each is compiled to find its imports, so
+/// they are known to compile.
+pub fn resolve<L: ResourceLoader + ?Sized>(loader: &L, names: &[String])
+                                           -> Result<Vec<(String, String)>, LoadError> {
+    let mut resolved = vec![];
+    let mut done = HashSet::new();
+    for name in names {
+        add(loader, name.clone(), &mut vec![], &mut done, &mut resolved)?;
+    }
+    Ok(resolved)
+}
+
+fn add<L: ResourceLoader + ?Sized>(loader: &L,
+                                   name: String,
+                                   importing: &mut Vec<String>,
+                                   done: &mut HashSet<String>,
+                                   resolved: &mut Vec<(String, String)>) -> Result<(), LoadError> {
+    if done.contains(&name) {
+        return Ok(());
+    }
+    if let Some(i) = importing.iter().position(|n| *n == name) {
+        let mut cycle: Vec<PathBuf> = importing[i..].iter().map(PathBuf::from).collect();
+        cycle.push(PathBuf::from(name));
+        return Err(LoadError::ImportCycle(cycle));
+    }
+
+    let source = loader.load(&name).map_err(|e| LoadError::Io(PathBuf::from(&name), e))?;
+    let imports = compiler::compile(&source).map_err(|e| LoadError::Parse(PathBuf::from(&name), e))?.imports;
+    importing.push(name.clone());
+    for (path, _) in &imports {
+        add(loader, loader.resolve(&name, path), importing, done, resolved)?;
+    }
+    importing.pop();
+
+    done.insert(name.clone());
+    resolved.push((name, source));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use context::Locale;
+    use loader::LoadError;
+    use parser::UnresolvedImportError;
+    use super::{resolve, FileLoader, ResourceLoader};
+
+    #[test]
+    fn test_resolve_names() {
+        let map: HashMap<String, String> = HashMap::new();
+        assert_eq!(map.resolve("app/main.l20n", "brand.l20n"), "app/brand.l20n");
+        assert_eq!(map.resolve("app/main.l20n", "./../shared/./brand.l20n"), "shared/brand.l20n");
+        assert_eq!(map.resolve("main.l20n", "../brand.l20n"), "../brand.l20n");
+        assert_eq!(map.resolve("a/b/main.l20n", "/brand.l20n"), "/brand.l20n");
+        assert_eq!(FileLoader.resolve("/l10n/en/main.l20n", "../shared/brand.l20n"), "/l10n/shared/brand.l20n");
+        assert_eq!(FileLoader.resolve("main.l20n", "brand.l20n"), "brand.l20n");
+    }
+
+    #[test]
+    fn test_imports() {
+        let mut map = HashMap::new();
+        map.insert("brand.l20n", "<brandName 'Firefox'>");
+        map.insert("menu.l20n", "import('brand.l20n')\n<menu 'Menu of {{ brandName }}'>");
+        map.insert("main.l20n", r#"
+            /* Both import the brand, which is only added once. */
+            import('brand.l20n')
+            import("menu.l20n")
+            <brandName 'Nightly'>
+        "#);
+
+        let names: Vec<String> = resolve(&map, &[String::from("main.l20n")]).unwrap()
+            .into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["brand.l20n", "menu.l20n", "main.l20n"]);
+
+        // the importing resource is added last, so it overrides
+        let mut locale = Locale::new();
+        locale.add_resource_from(&map, "main.l20n").unwrap();
+        assert_eq!(locale.localize_entity("menu", ()).unwrap(), "Menu of Nightly");
+        assert!(locale.duplicates().iter().all(|d| d.id == "brandName"));
+        assert_eq!(locale.comments(), &[String::from("Both import the brand, which is only added once.")]);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let mut map = HashMap::new();
+        map.insert("a.l20n", "import('b.l20n')");
+        map.insert("b.l20n", "import('c.l20n')");
+        map.insert("c.l20n", "import('a.l20n')");
+        map.insert("missing.l20n", "import('nope.l20n')");
+        map.insert("broken.l20n", "import('ok.l20n'");
+        let mut locale = Locale::new();
+
+        match locale.add_resource_from(&map, "a.l20n") {
+            Err(LoadError::ImportCycle(ref cycle)) => {
+                let names: Vec<&str> = cycle.iter().map(|p| p.to_str().unwrap()).collect();
+                assert_eq!(names, vec!["a.l20n", "b.l20n", "c.l20n", "a.l20n"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        match locale.add_resource_from(&map, "missing.l20n") {
+            Err(LoadError::Io(ref path, _)) => assert_eq!(*path, PathBuf::from("nope.l20n")),
+            other => panic!("expected an io error, got {:?}", other),
+        }
+        match locale.add_resource_from(&map, "broken.l20n") {
+            Err(LoadError::Parse(ref path, _)) => assert_eq!(*path, PathBuf::from("broken.l20n")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        match locale.add_resource("<hi 'Hi'>\n  import('a.l20n')") {
+            Err(e) => assert_eq!((e.kind, e.line, e.col), (UnresolvedImportError, 1, 4)),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(locale.localize_entity("hi", ()).is_err());
+    }
+}
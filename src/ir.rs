@@ -0,0 +1,793 @@
+//! A lowered form of compiled entries, used to resolve entities quickly and
+//! repeatedly.
+//!
+//! Names are interned into symbols, references to other entries are turned
+//! into indices, and anything that does not depend on the localization data
+//! (literal strings, arithmetic on numbers, conditions on them) is folded
+//! ahead of time. Entities that do not depend on it at all are resolved the
+//! first time they are needed, and their value is kept for later calls.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, OnceLock};
+
+use compiler::ResolveError;
+use compiler::ResolveError::*;
+use data;
+use parser;
+
+/// An interned name, as an index into `Program::names`.
+pub type Sym = usize;
+
+#[derive(Clone)]
+pub struct Program {
+    // each name is allocated once, and shared by both
+    names: Vec<Arc<str>>,
+    symbols: HashMap<Arc<str>, Sym>,
+    entries: Vec<Entry>,
+    // the ids of the entries are interned first, so the symbol of an id is
+    // also the index of its entry, and these are the first `ids` symbols
+    ids: usize,
+    // the resolved value of each entity that does not use any $vars
+    memo: Vec<Option<OnceLock<data::Data>>>,
+}
+
+#[derive(Clone)]
+pub enum Entry {
+    Entity(Sym, Value, Vec<(Sym, Value)>),
+    Macro(Vec<Sym>, Expr),
+}
+
+#[derive(Clone)]
+pub enum Value {
+    Str(String),
+    /// Pieces of a string. Literal pieces are already joined into
+    /// `Const(Str)`s.
+    ComplexStr(Vec<Expr>),
+    Hash(HashMap<Sym, Value>, Option<Sym>, Option<Box<Expr>>),
+}
+
+#[derive(Clone)]
+pub enum Prop {
+    Static(Sym),
+    Computed(Box<Expr>),
+    /// A static access that does not name anything, which fails to resolve.
+    Invalid,
+}
+
+#[derive(Clone)]
+pub enum Expr {
+    Const(data::Data),
+    Val(Value),
+    Var(Sym),
+    /// A reference to another entry, by its index in the Program.
+    Ref(usize),
+    /// A reference to an entry that does not exist.
+    Missing(Sym),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    Bin(Box<Expr>, parser::BinOp, Box<Expr>),
+    Un(parser::UnOp, Box<Expr>),
+    Prop(Box<Expr>, Prop),
+    Attr(Box<Expr>, Prop),
+    Call(Box<Expr>, Vec<Expr>),
+    Unsupported,
+}
+
+impl Program {
+    /// Lowers entries, by their ids. Each id can only be given once.
+    pub fn new(definitions: &[(&str, &parser::Entry)]) -> Program {
+        let mut ids = definitions.to_vec();
+        ids.sort_unstable_by_key(|&(id, _)| id);
+
+        let mut program = Program {
+            names: Vec::with_capacity(ids.len()),
+            symbols: HashMap::with_capacity(ids.len()),
+            entries: Vec::with_capacity(ids.len()),
+            ids: ids.len(),
+            memo: vec![],
+        };
+        for &(id, _) in &ids {
+            program.intern(id);
+        }
+        for (id, entry) in ids {
+            let entry = program.lower_entry(id, entry);
+            program.entries.push(entry);
+        }
+        program.memo = program.independent().into_iter().zip(&program.entries).map(|(ind, entry)| {
+            match *entry {
+                Entry::Entity(..) if ind => Some(OnceLock::new()),
+                _ => None,
+            }
+        }).collect();
+        program
+    }
+
+    /// The index of an entry, by its id.
+    pub fn lookup(&self, id: &str) -> Option<usize> {
+        self.id(id)
+    }
+
+    fn id(&self, id: &str) -> Option<usize> {
+        self.symbols.get(id).cloned().filter(|&sym| sym < self.ids)
+    }
+
+    /// The id and index of every entity, in the order of their ids.
+    pub fn entities(&self) -> impl Iterator<Item=(&str, usize)> {
+        self.entries.iter().enumerate().filter_map(move |(i, entry)| match *entry {
+            Entry::Entity(id, ..) => Some((&self.names[id][..], i)),
+            Entry::Macro(..) => None,
+        })
+    }
+
+    /// Resolves an entry completely, using `data` for its `$vars`.
+    pub fn resolve(&self, entry: usize, data: &data::Data) -> Result<data::Data, ResolveError> {
+        let scope = Scope {
+            program: self,
+            data,
+            locals: &[],
+            index: None,
+        };
+        let target = scope.entry(entry, &self.entries[entry])?;
+        Ok(scope.resolve_data(target)?.into_owned())
+    }
+
+    /// The value of an entity that does not depend on the localization data,
+    /// resolved on first use. Returns None for any other entry.
+    fn memoized(&self, entry: usize) -> Option<Result<&data::Data, ResolveError>> {
+        let cell = self.memo[entry].as_ref()?;
+        if let Some(d) = cell.get() {
+            return Some(Ok(d));
+        }
+        let scope = Scope {
+            program: self,
+            data: &data::Null,
+            locals: &[],
+            index: None,
+        };
+        // errors are not kept, so they are reported again on the next call
+        let result = scope.resolve_data(Target::Value(match self.entries[entry] {
+            Entry::Entity(_, ref value, _) => value,
+            Entry::Macro(..) => unreachable!(),
+        }));
+        Some(result.map(|d| cell.get_or_init(|| d.into_owned())))
+    }
+
+    /// Finds the entries that do not depend on the localization data, either
+    /// directly or through the entries they reference. Everything starts out
+    /// independent, and an entry is marked otherwise when it uses a $var (other
+    /// than its own macro arguments) or references a dependent entry, until
+    /// nothing changes, so that reference cycles are handled.
+    fn independent(&self) -> Vec<bool> {
+        let mut independent = vec![true; self.entries.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, entry) in self.entries.iter().enumerate() {
+                if independent[i] && !entry_independent(entry, &independent) {
+                    independent[i] = false;
+                    changed = true;
+                }
+            }
+        }
+        independent
+    }
+
+    fn intern(&mut self, name: &str) -> Sym {
+        if let Some(&sym) = self.symbols.get(name) {
+            return sym;
+        }
+        let sym = self.names.len();
+        let name: Arc<str> = Arc::from(name);
+        self.names.push(name.clone());
+        self.symbols.insert(name, sym);
+        sym
+    }
+
+    fn lower_entry(&mut self, id: &str, entry: &parser::Entry) -> Entry {
+        match *entry {
+            parser::Entity(_, ref value, _, ref attrs) => {
+                let id = self.intern(id);
+                let value = self.lower_value(value);
+                let mut lowered = Vec::with_capacity(attrs.len());
+                for parser::Attr(name, value, _) in attrs {
+                    lowered.push((self.intern(name), self.lower_value(value)));
+                }
+                Entry::Entity(id, value, lowered)
+            }
+            parser::Macro(_, ref args, ref body) => {
+                let params = args.iter().map(|arg| match *arg {
+                    parser::VarExpr(ref name) => self.intern(name),
+                    // not a VarExpr would be the parser going nuts
+                    _ => unreachable!()
+                }).collect();
+                Entry::Macro(params, self.lower_expr(body))
+            }
+            parser::Comment(..) | parser::Import(..) => {
+                unreachable!("comments and imports are not compiled into an Env")
+            }
+        }
+    }
+
+    fn lower_value(&mut self, value: &parser::Value) -> Value {
+        match *value {
+            parser::Str(ref s) => Value::Str(s.to_string()),
+            parser::ComplexStr(ref exprs) => {
+                let mut pieces = vec![];
+                let mut text = String::new();
+                for expr in exprs {
+                    match self.lower_expr(expr) {
+                        Expr::Const(data::Str(s)) => text.push_str(&s),
+                        Expr::Const(data::Num(n)) => text.push_str(&n.to_string()),
+                        expr => {
+                            if !text.is_empty() {
+                                pieces.push(Expr::Const(data::Str(mem::take(&mut text))));
+                            }
+                            pieces.push(expr);
+                        }
+                    }
+                }
+                if pieces.is_empty() {
+                    return Value::Str(text);
+                }
+                if !text.is_empty() {
+                    pieces.push(Expr::Const(data::Str(text)));
+                }
+                Value::ComplexStr(pieces)
+            }
+            parser::Hash(ref map, ref def_key, ref def_index) => {
+                let mut lowered = HashMap::with_capacity(map.len());
+                for (key, value) in map {
+                    lowered.insert(self.intern(key), self.lower_value(value));
+                }
+                Value::Hash(lowered,
+                            def_key.as_ref().map(|key| self.intern(key)),
+                            def_index.as_ref().map(|index| Box::new(self.lower_expr(index))))
+            }
+        }
+    }
+
+    fn lower_prop(&mut self, prop: &parser::Expr, access: parser::AccessType) -> Prop {
+        match (access, prop) {
+            (parser::Computed, prop) => Prop::Computed(Box::new(self.lower_expr(prop))),
+            (parser::Static, parser::IdentExpr(name)) => Prop::Static(self.intern(name)),
+            (parser::Static, _) => Prop::Invalid,
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &parser::Expr) -> Expr {
+        match *expr {
+            parser::ValExpr(ref value) => match self.lower_value(value) {
+                Value::Str(s) => Expr::Const(data::Str(s)),
+                value => Expr::Val(value),
+            },
+            parser::NumExpr(n) => Expr::Const(data::Num(n)),
+            parser::ParenExpr(ref expr) => self.lower_expr(expr),
+            parser::VarExpr(ref name) => Expr::Var(self.intern(name)),
+            parser::IdentExpr(ref name) => match self.id(name) {
+                Some(i) => Expr::Ref(i),
+                None => Expr::Missing(self.intern(name)),
+            },
+            parser::BinExpr(ref left, op, ref right) => {
+                let left = self.lower_expr(left);
+                let right = self.lower_expr(right);
+                if let (Expr::Const(l), Expr::Const(r)) = (&left, &right) {
+                    // an error is left for when the entity is resolved
+                    if let Ok(d) = binop(op, l, r) {
+                        return Expr::Const(d);
+                    }
+                }
+                Expr::Bin(Box::new(left), op, Box::new(right))
+            }
+            parser::UnExpr(op, ref expr) => {
+                let expr = self.lower_expr(expr);
+                if let Expr::Const(ref d) = expr {
+                    if let Ok(d) = unop(op, d) {
+                        return Expr::Const(d);
+                    }
+                }
+                Expr::Un(op, Box::new(expr))
+            }
+            parser::CondExpr(ref cond, ref consequent, ref alt) => {
+                match self.lower_expr(cond) {
+                    Expr::Const(data::Bool(true)) => self.lower_expr(consequent),
+                    Expr::Const(data::Bool(false)) => self.lower_expr(alt),
+                    cond => Expr::Cond(Box::new(cond),
+                                       Box::new(self.lower_expr(consequent)),
+                                       Box::new(self.lower_expr(alt))),
+                }
+            }
+            parser::PropExpr(ref parent, ref prop, access) => {
+                Expr::Prop(Box::new(self.lower_expr(parent)), self.lower_prop(prop, access))
+            }
+            parser::AttrExpr(ref parent, ref prop, access) => {
+                Expr::Attr(Box::new(self.lower_expr(parent)), self.lower_prop(prop, access))
+            }
+            parser::CallExpr(ref callee, ref args) => {
+                let callee = Box::new(self.lower_expr(callee));
+                Expr::Call(callee, args.iter().map(|arg| self.lower_expr(arg)).collect())
+            }
+            parser::GlobalExpr(..) | parser::ThisExpr => Expr::Unsupported,
+        }
+    }
+}
+
+fn entry_independent(entry: &Entry, independent: &[bool]) -> bool {
+    match *entry {
+        Entry::Entity(_, ref value, ref attrs) => {
+            value_independent(value, &[], independent) &&
+                attrs.iter().all(|(_, value)| value_independent(value, &[], independent))
+        }
+        Entry::Macro(ref params, ref body) => expr_independent(body, params, independent),
+    }
+}
+
+fn value_independent(value: &Value, params: &[Sym], independent: &[bool]) -> bool {
+    match *value {
+        Value::Str(..) => true,
+        Value::ComplexStr(ref pieces) => {
+            pieces.iter().all(|piece| expr_independent(piece, params, independent))
+        }
+        Value::Hash(ref map, _, ref index) => {
+            map.values().all(|value| value_independent(value, params, independent)) &&
+                index.as_ref().is_none_or(|index| expr_independent(index, params, independent))
+        }
+    }
+}
+
+fn expr_independent(expr: &Expr, params: &[Sym], independent: &[bool]) -> bool {
+    let prop_independent = |prop: &Prop| match *prop {
+        Prop::Computed(ref expr) => expr_independent(expr, params, independent),
+        Prop::Static(..) | Prop::Invalid => true,
+    };
+    match *expr {
+        Expr::Const(..) | Expr::Missing(..) => true,
+        Expr::Val(ref value) => value_independent(value, params, independent),
+        Expr::Var(sym) => params.contains(&sym),
+        Expr::Ref(i) => independent[i],
+        Expr::Cond(ref cond, ref consequent, ref alt) => {
+            expr_independent(cond, params, independent) &&
+                expr_independent(consequent, params, independent) &&
+                expr_independent(alt, params, independent)
+        }
+        Expr::Bin(ref left, _, ref right) => {
+            expr_independent(left, params, independent) &&
+                expr_independent(right, params, independent)
+        }
+        Expr::Un(_, ref expr) => expr_independent(expr, params, independent),
+        Expr::Prop(ref parent, ref prop) | Expr::Attr(ref parent, ref prop) => {
+            expr_independent(parent, params, independent) && prop_independent(prop)
+        }
+        Expr::Call(ref callee, ref args) => {
+            expr_independent(callee, params, independent) &&
+                args.iter().all(|arg| expr_independent(arg, params, independent))
+        }
+        Expr::Unsupported => false,
+    }
+}
+
+fn binop(op: parser::BinOp, left: &data::Data, right: &data::Data) -> Result<data::Data, ResolveError> {
+    Ok(match (op, left, right) {
+        // math ops
+        (parser::BiAdd, &data::Num(l), &data::Num(r)) => data::Num(l.checked_add(r).ok_or(Overflow)?),
+        (parser::BiSub, &data::Num(l), &data::Num(r)) => data::Num(l.checked_sub(r).ok_or(Overflow)?),
+        (parser::BiMul, &data::Num(l), &data::Num(r)) => data::Num(l.checked_mul(r).ok_or(Overflow)?),
+        (parser::BiDiv, &data::Num(_), &data::Num(0)) |
+        (parser::BiRem, &data::Num(_), &data::Num(0)) => return Err(DivideByZero),
+        // i64::MIN / -1 is the only other way to fail
+        (parser::BiDiv, &data::Num(l), &data::Num(r)) => data::Num(l.checked_div(r).ok_or(Overflow)?),
+        (parser::BiRem, &data::Num(l), &data::Num(r)) => data::Num(l.checked_rem(r).ok_or(Overflow)?),
+
+        (parser::BiLt, &data::Num(l), &data::Num(r)) => data::Bool(l < r),
+        (parser::BiLe, &data::Num(l), &data::Num(r)) => data::Bool(l <= r),
+        (parser::BiGt, &data::Num(l), &data::Num(r)) => data::Bool(l > r),
+        (parser::BiGe, &data::Num(l), &data::Num(r)) => data::Bool(l >= r),
+
+        // logical ops
+        (parser::BiAnd, &data::Bool(l), &data::Bool(r)) => data::Bool(l && r),
+        (parser::BiOr, &data::Bool(l), &data::Bool(r)) => data::Bool(l || r),
+
+        // equality ops. can be Num, Bool, or Str
+        (parser::BiEq, &data::Bool(l), &data::Bool(r)) => data::Bool(l == r),
+        (parser::BiEq, data::Str(l), data::Str(r)) => data::Bool(l == r),
+        (parser::BiEq, &data::Num(l), &data::Num(r)) => data::Bool(l == r),
+        (parser::BiNe, &data::Bool(l), &data::Bool(r)) => data::Bool(l != r),
+        (parser::BiNe, data::Str(l), data::Str(r)) => data::Bool(l != r),
+        (parser::BiNe, &data::Num(l), &data::Num(r)) => data::Bool(l != r),
+
+        (_, _, _) => return Err(WrongType)
+    })
+}
+
+fn unop(op: parser::UnOp, expr: &data::Data) -> Result<data::Data, ResolveError> {
+    match (op, expr) {
+        (parser::UnAdd, &data::Num(n)) => Ok(data::Num(n)),
+        (parser::UnSub, &data::Num(n)) => n.checked_neg().map(data::Num).ok_or(Overflow),
+        (parser::UnNot, &data::Bool(b)) => Ok(data::Bool(!b)),
+        _ => Err(WrongType)
+    }
+}
+
+/// A step of resolution. Entries and values are borrowed from the Program,
+/// and Data is only owned when it was computed rather than looked up.
+enum Target<'a> {
+    Entry(usize, &'a Entry),
+    Value(&'a Value),
+    Data(Cow<'a, data::Data>),
+}
+
+type ResolveResult<'a> = Result<Target<'a>, ResolveError>;
+
+fn owned(d: data::Data) -> ResolveResult<'static> {
+    Ok(Target::Data(Cow::Owned(d)))
+}
+
+struct Scope<'a, 'l> {
+    program: &'a Program,
+    data: &'a data::Data,
+    // the arguments of the macro being called
+    locals: &'l [(Sym, data::Data)],
+    // the property used to pick from a Hash
+    index: Option<&'l str>,
+}
+
+impl<'a, 'l> Scope<'a, 'l> {
+    fn with_locals<'m>(&self, locals: &'m [(Sym, data::Data)]) -> Scope<'a, 'm> {
+        Scope {
+            program: self.program,
+            data: self.data,
+            locals,
+            index: None,
+        }
+    }
+
+    fn with_index<'m>(&self, index: &'m str) -> Scope<'a, 'm> where 'l: 'm {
+        Scope {
+            program: self.program,
+            data: self.data,
+            locals: self.locals,
+            index: Some(index),
+        }
+    }
+
+    fn name(&self, sym: Sym) -> &'a str {
+        &self.program.names[sym]
+    }
+
+    /// Keeps resolving until a Data value is returned.
+    fn resolve_data(&self, mut target: Target<'a>) -> Result<Cow<'a, data::Data>, ResolveError> {
+        loop {
+            target = match target {
+                Target::Data(d) => return Ok(d),
+                Target::Entry(i, e) => self.entry(i, e)?,
+                Target::Value(v) => self.value(v)?,
+            };
+        }
+    }
+
+    fn expr_data(&self, expr: &'a Expr) -> Result<Cow<'a, data::Data>, ResolveError> {
+        let target = self.expr(expr)?;
+        self.resolve_data(target)
+    }
+
+    fn entry(&self, i: usize, entry: &'a Entry) -> ResolveResult<'a> {
+        if let Some(d) = self.program.memoized(i) {
+            return Ok(Target::Data(Cow::Borrowed(d?)));
+        }
+        match *entry {
+            Entry::Entity(_, ref value, _) => Ok(Target::Value(value)),
+            Entry::Macro(..) => owned(data::Null),
+        }
+    }
+
+    fn value(&self, value: &'a Value) -> ResolveResult<'a> {
+        match *value {
+            Value::Str(ref s) => owned(data::Str(s.clone())),
+            Value::ComplexStr(ref pieces) => {
+                let mut out = String::new();
+                for piece in pieces {
+                    match *self.expr_data(piece)? {
+                        data::Str(ref s) => out.push_str(s),
+                        data::Num(n) => out.push_str(&n.to_string()),
+                        _ => return Err(WrongType)
+                    }
+                }
+                owned(data::Str(out))
+            }
+            Value::Hash(ref map, def_key, ref def_index) => {
+                let symbols = &self.program.symbols;
+                if let Some(v) = self.index.and_then(|s| symbols.get(s)).and_then(|k| map.get(k)) {
+                    return Ok(Target::Value(v));
+                }
+                let default = def_key.and_then(|k| map.get(&k));
+                // the index picks the value, and the default is for when it
+                // cannot
+                if let Some(ref e) = *def_index {
+                    match self.expr_data(e) {
+                        Ok(key) => match *key {
+                            data::Str(ref s) => {
+                                if let Some(v) = symbols.get(&s[..]).and_then(|k| map.get(k)) {
+                                    return Ok(Target::Value(v));
+                                }
+                            }
+                            _ if default.is_none() => return Err(WrongType),
+                            _ => {}
+                        },
+                        Err(e) if default.is_none() => return Err(e),
+                        Err(_) => {}
+                    }
+                }
+                default.map(Target::Value).ok_or(MissingIndex)
+            }
+        }
+    }
+
+    /// Resolves the name of a property or attribute.
+    fn prop(&self, prop: &'a Prop) -> Result<Cow<'a, str>, ResolveError> {
+        match *prop {
+            Prop::Static(sym) => Ok(Cow::Borrowed(self.name(sym))),
+            Prop::Computed(ref expr) => match self.expr_data(expr)? {
+                Cow::Borrowed(data::Str(s)) => Ok(Cow::Borrowed(s)),
+                Cow::Owned(data::Str(s)) => Ok(Cow::Owned(s)),
+                _ => Err(WrongType)
+            },
+            Prop::Invalid => Err(WrongType),
+        }
+    }
+
+    fn expr(&self, expr: &'a Expr) -> ResolveResult<'a> {
+        match *expr {
+            Expr::Const(ref d) => Ok(Target::Data(Cow::Borrowed(d))),
+            Expr::Val(ref value) => Ok(Target::Value(value)),
+            Expr::Var(sym) => {
+                // locals only live for the macro call, so they are copied out
+                if let Some((_, val)) = self.locals.iter().find(|local| local.0 == sym) {
+                    return owned(val.clone());
+                }
+                match self.data.get(self.name(sym)) {
+                    Some(d) => Ok(Target::Data(Cow::Borrowed(d))),
+                    None => Err(MissingVar(self.name(sym).to_string()))
+                }
+            }
+            Expr::Ref(i) => Ok(Target::Entry(i, &self.program.entries[i])),
+            Expr::Missing(sym) => Err(MissingIdent(self.name(sym).to_string())),
+            Expr::Cond(ref cond, ref consequent, ref alt) => {
+                match *self.expr_data(cond)? {
+                    data::Bool(true) => self.expr(consequent),
+                    data::Bool(false) => self.expr(alt),
+                    _ => Err(WrongType)
+                }
+            }
+            Expr::Bin(ref left, op, ref right) => {
+                let left = self.expr_data(left)?;
+                let right = self.expr_data(right)?;
+                owned(binop(op, &left, &right)?)
+            }
+            Expr::Un(op, ref expr) => owned(unop(op, &*self.expr_data(expr)?)?),
+            Expr::Call(ref callee, ref args) => {
+                match self.expr(callee)? {
+                    Target::Entry(_, Entry::Macro(params, body)) => {
+                        if args.len() != params.len() {
+                            return Err(WrongNumberOfArgs);
+                        }
+                        let mut locals = Vec::with_capacity(args.len());
+                        for (&param, arg) in params.iter().zip(args) {
+                            locals.push((param, self.expr_data(arg)?.into_owned()));
+                        }
+                        let scope = self.with_locals(&locals);
+                        owned(scope.expr_data(body)?.into_owned())
+                    }
+                    _ => Err(WrongType)
+                }
+            }
+            Expr::Prop(ref parent, ref prop) => {
+                let prop = self.prop(prop)?;
+                match self.expr(parent)? {
+                    Target::Data(Cow::Borrowed(data::Map(m))) => {
+                        match m.get(&*prop) {
+                            Some(d) => Ok(Target::Data(Cow::Borrowed(d))),
+                            None => Err(MissingIndex)
+                        }
+                    }
+                    Target::Data(Cow::Owned(data::Map(mut m))) => {
+                        match m.remove(&*prop) {
+                            Some(d) => owned(d),
+                            None => Err(MissingIndex)
+                        }
+                    }
+                    Target::Entry(_, Entry::Entity(_, value, _)) => {
+                        self.with_index(&prop).value(value)
+                    }
+                    Target::Value(v) => self.with_index(&prop).value(v),
+                    _ => Err(WrongType)
+                }
+            }
+            Expr::Attr(ref parent, ref prop) => {
+                let prop = self.prop(prop)?;
+                match self.expr(parent)? {
+                    Target::Entry(_, Entry::Entity(_, _, attrs)) => {
+                        for &(name, ref value) in attrs {
+                            if self.name(name) == prop {
+                                return self.value(value);
+                            }
+                        }
+                        Err(MissingAttr)
+                    }
+                    _ => Err(WrongType)
+                }
+            }
+            Expr::Unsupported => Err(Unsupported),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{Program, Entry, Value, Expr};
+    use compiler::compile;
+    use compiler::ResolveError::{DivideByZero, Overflow, Unsupported};
+    use data::{Data, Str, Num, Map, Null};
+
+    fn program(source: &str) -> Program {
+        let compiled = compile(source).unwrap();
+        let definitions: Vec<_> = compiled.definitions.iter().map(|def| (&def.id[..], &def.entry)).collect();
+        Program::new(&definitions)
+    }
+
+    fn resolve(program: &Program, id: &str, data: &Data) -> Data {
+        program.resolve(program.lookup(id).unwrap(), data).unwrap()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let p = program(r#"
+        <brand { *short: 'Rust', long: 'Rust Lang' }>
+        <hi 'Hello, {{ brand.long }}!'>
+        <count($n) { 'Count: {{ $n }}' }>
+        <counted 'Counted {{ count($num) }}'>
+        <ne '{{ $num != 3 ? "yes" : "no" }}'>
+        "#);
+        let mut vars = HashMap::new();
+        vars.insert(String::from("num"), Num(3));
+        let data = Map(vars);
+
+        assert_eq!(resolve(&p, "brand", &Null), Str(String::from("Rust")));
+        assert_eq!(resolve(&p, "hi", &Null), Str(String::from("Hello, Rust Lang!")));
+        assert_eq!(resolve(&p, "counted", &data), Str(String::from("Counted Count: 3")));
+        assert_eq!(resolve(&p, "ne", &data), Str(String::from("no")));
+    }
+
+    #[test]
+    fn test_ne() {
+        let p = program(r#"
+        <str '{{ $name != "Rust" ? "other" : "same" }}'>
+        <bool '{{ ($num == 3) != ($num > 2) ? "other" : "same" }}'>
+        <folded '{{ "a" != "b" ? "other" : "same" }}'>
+        "#);
+        let mut vars = HashMap::new();
+        vars.insert(String::from("name"), Str(String::from("Rust")));
+        vars.insert(String::from("num"), Num(3));
+        let data = Map(vars);
+
+        assert_eq!(resolve(&p, "str", &data), Str(String::from("same")));
+        assert_eq!(resolve(&p, "bool", &data), Str(String::from("same")));
+        assert_eq!(resolve(&p, "folded", &Null), Str(String::from("other")));
+    }
+
+    #[test]
+    fn test_hash_index() {
+        let p = program(r#"
+        <plural($n) { $n == 1 ? 'one' : 'other' }>
+        <emails[plural($num)] { one: 'One email', *other: '{{ $num }} emails' }>
+        <picked[$key] { *a: 'A', b: 'B' }>
+        "#);
+        let data = |key: &str, n: i64| {
+            let mut vars = HashMap::new();
+            vars.insert(String::from("key"), Str(String::from(key)));
+            vars.insert(String::from("num"), Num(n));
+            Map(vars)
+        };
+
+        // the index picks over the default, which is for keys that are
+        // missing and indices that cannot be resolved
+        assert_eq!(resolve(&p, "emails", &data("a", 1)), Str(String::from("One email")));
+        assert_eq!(resolve(&p, "emails", &data("a", 3)), Str(String::from("3 emails")));
+        assert_eq!(resolve(&p, "picked", &data("b", 0)), Str(String::from("B")));
+        assert_eq!(resolve(&p, "picked", &data("c", 0)), Str(String::from("A")));
+        assert_eq!(resolve(&p, "picked", &Null), Str(String::from("A")));
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        let p = program(r#"
+        <math 'Total: {{ (1 + 2) * 3 }} items'>
+        <cond '{{ 2 > 1 ? "more" : "less" }}'>
+        <brand 'Rust'>
+        <hi 'Hello, {{ brand }}!'>
+        "#);
+
+        // static pieces and arithmetic are folded into a single string
+        match p.entries[p.lookup("math").unwrap()] {
+            Entry::Entity(_, Value::Str(ref s), _) => assert_eq!(s, "Total: 9 items"),
+            _ => panic!("math was not folded"),
+        }
+        match p.entries[p.lookup("cond").unwrap()] {
+            Entry::Entity(_, Value::Str(ref s), _) => assert_eq!(s, "more"),
+            _ => panic!("cond was not folded"),
+        }
+        // references become indices
+        match p.entries[p.lookup("hi").unwrap()] {
+            Entry::Entity(_, Value::ComplexStr(ref pieces), _) => {
+                assert!(pieces.iter().any(|piece| match *piece {
+                    Expr::Ref(i) => i == p.lookup("brand").unwrap(),
+                    _ => false,
+                }));
+            }
+            _ => panic!("hi should still be a ComplexStr"),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_errors() {
+        let p = program(r#"
+        <div '{{ 1 / 0 }}'>
+        <rem '{{ 1 % 0 }}'>
+        <add '{{ 9223372036854775807 + 1 }}'>
+        <mul '{{ 9223372036854775807 * 2 }}'>
+        <neg '{{ -(-9223372036854775807 - 1) }}'>
+        <min '{{ (-9223372036854775807 - 1) / (0 - 1) }}'>
+        <var '{{ 10 / $n }}'>
+        "#);
+        let err = |id: &str, data: &Data| p.resolve(p.lookup(id).unwrap(), data).unwrap_err();
+
+        // these are not folded, but fail when they are resolved
+        assert!(matches!(err("div", &Null), DivideByZero));
+        assert!(matches!(err("rem", &Null), DivideByZero));
+        assert!(matches!(err("add", &Null), Overflow));
+        assert!(matches!(err("mul", &Null), Overflow));
+        assert!(matches!(err("neg", &Null), Overflow));
+        assert!(matches!(err("min", &Null), Overflow));
+        let mut vars = HashMap::new();
+        vars.insert(String::from("n"), Num(0));
+        assert!(matches!(err("var", &Map(vars)), DivideByZero));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let p = program(r#"<os "{{ @os }}">"#);
+        assert!(matches!(p.resolve(p.lookup("os").unwrap(), &Null), Err(Unsupported)));
+    }
+
+    #[test]
+    fn test_memoize() {
+        let p = program(r#"
+        <brand 'Rust' long: 'Rust Lang'>
+        <hi 'Hello, {{ brand::long }}!'>
+        <name 'Hello, {{ $name }}'>
+        <greet 'Oh, {{ name }}'>
+        <double($n) { $n * 2 }>
+        <four '{{ double(2) }}'>
+        <ping '{{ pong }}{{ $x }}'>
+        <pong '{{ ping }}'>
+        "#);
+        let memoized = |id: &str| p.memo[p.lookup(id).unwrap()].is_some();
+        assert!(memoized("brand"));
+        assert!(memoized("hi"));
+        assert!(memoized("four"));
+        assert!(!memoized("double"));
+        assert!(!memoized("name"));
+        assert!(!memoized("greet"));
+        assert!(!memoized("ping"));
+        assert!(!memoized("pong"));
+
+        let hi = p.lookup("hi").unwrap();
+        assert!(p.memo[hi].as_ref().unwrap().get().is_none());
+        assert_eq!(resolve(&p, "hi", &Null), Str(String::from("Hello, Rust Lang!")));
+        assert_eq!(p.memo[hi].as_ref().unwrap().get(), Some(&Str(String::from("Hello, Rust Lang!"))));
+        // brand was resolved through hi, but only for its attribute
+        assert!(p.memo[p.lookup("brand").unwrap()].as_ref().unwrap().get().is_none());
+        assert_eq!(resolve(&p, "four", &Null), Str(String::from("4")));
+    }
+}
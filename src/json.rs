@@ -0,0 +1,269 @@
+//! Reading and writing JSON, for what the crate reads and writes as JSON.
+
+/// A JSON value. Objects keep their members in order, and numbers are kept
+/// as written, so that integers are read without going through `f64`.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+// deep enough for any real document, shallow enough not to overflow the stack
+const MAX_DEPTH: usize = 256;
+
+impl Json {
+    /// The name of its type, as JSON calls it.
+    pub(crate) fn kind(&self) -> &'static str {
+        match *self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Number(_) => "number",
+            Json::Str(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// The member of an object by its key.
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a JSON document, or returns the line, counted from 0, where it
+/// stops being one.
+pub(crate) fn parse(source: &str) -> Result<Json, usize> {
+    let mut parser = Parser { chars: source.chars().collect(), pos: 0, depth: 0 };
+    parser.document().map_err(|()| parser.line())
+}
+
+/// Writes a string as a JSON string, quotes included.
+pub(crate) fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn line(&self) -> usize {
+        self.chars[..self.pos.min(self.chars.len())].iter().filter(|&&c| c == '\n').count()
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t' || c == '\n' || c == '\r') {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ()> {
+        if self.eat(c) { Ok(()) } else { Err(()) }
+    }
+
+    fn document(&mut self) -> Result<Json, ()> {
+        // a byte order mark may come first
+        self.eat('\u{feff}');
+        let value = self.value()?;
+        self.skip_space();
+        match self.peek() {
+            None => Ok(value),
+            Some(_) => Err(()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, ()> {
+        self.skip_space();
+        match self.peek().ok_or(())? {
+            '{' => self.nested(Parser::object),
+            '[' => self.nested(Parser::array),
+            '"' => self.string().map(Json::Str),
+            '-' | '0'..='9' => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(c)) {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number.parse::<f64>().map_err(|_| ())?;
+                Ok(Json::Number(number))
+            }
+            _ => {
+                for &(word, ref value) in &[("true", Json::Bool(true)), ("false", Json::Bool(false)),
+                                            ("null", Json::Null)] {
+                    let end = self.pos + word.len();
+                    if end <= self.chars.len() && self.chars[self.pos..end].iter().cloned().eq(word.chars()) {
+                        self.pos = end;
+                        return Ok(value.clone());
+                    }
+                }
+                Err(())
+            }
+        }
+    }
+
+    fn nested<F: FnOnce(&mut Parser) -> Result<Json, ()>>(&mut self, read: F) -> Result<Json, ()> {
+        if self.depth == MAX_DEPTH {
+            return Err(());
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn array(&mut self) -> Result<Json, ()> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_space();
+        if self.eat(']') {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_space();
+            if self.eat(']') {
+                return Ok(Json::Array(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, ()> {
+        self.expect('{')?;
+        let mut members = vec![];
+        self.skip_space();
+        if self.eat('}') {
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            self.skip_space();
+            self.expect(':')?;
+            let value = self.value()?;
+            members.push((key, value));
+            self.skip_space();
+            if self.eat('}') {
+                return Ok(Json::Object(members));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ()> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.peek().ok_or(())? {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(text);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    let c = self.peek().ok_or(())?;
+                    self.pos += 1;
+                    text.push(match c {
+                        '"' | '\\' | '/' => c,
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let unit = self.hex()?;
+                            // a surrogate pair is written as two escapes
+                            let code = if (0xd800..0xdc00).contains(&unit) {
+                                self.expect('\\')?;
+                                self.expect('u')?;
+                                let low = self.hex()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(());
+                                }
+                                0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+                            } else {
+                                unit
+                            };
+                            ::std::char::from_u32(code).ok_or(())?
+                        }
+                        _ => return Err(()),
+                    });
+                }
+                c if c < ' ' => return Err(()),
+                c => {
+                    self.pos += 1;
+                    text.push(c);
+                }
+            }
+        }
+    }
+
+    fn hex(&mut self) -> Result<u32, ()> {
+        let end = self.pos + 4;
+        if end > self.chars.len() {
+            return Err(());
+        }
+        let hex: String = self.chars[self.pos..end].iter().collect();
+        self.pos = end;
+        u32::from_str_radix(&hex, 16).map_err(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, write_str, Json};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(" {\"a\": [1, -2.5e3, true, null], \"b\": \"\\u00e9\\ud83d\\ude00\\n\"} "), Ok(Json::Object(vec![
+            ("a".into(), Json::Array(vec![
+                Json::Number("1".into()), Json::Number("-2.5e3".into()), Json::Bool(true), Json::Null,
+            ])),
+            ("b".into(), Json::Str("é😀\n".into())),
+        ])));
+        assert_eq!(parse("{\n  \"a\": \"b\",\n}"), Err(2));
+        assert_eq!(parse("[1] 2"), Err(0));
+        assert!(parse(&"[".repeat(300)).is_err());
+    }
+
+    #[test]
+    fn test_write_str() {
+        let mut out = String::new();
+        write_str(&mut out, "a\"\\\n\u{1}é");
+        assert_eq!(out, "\"a\\\"\\\\\\n\\u0001é\"");
+        assert_eq!(parse(&out), Ok(Json::Str("a\"\\\n\u{1}é".into())));
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A BCP 47 language tag, such as `en-US`, `zh-Hant-TW` or `sl-rozaj`.
+///
+/// Subtags are kept in their conventional case, so tags that differ only in
+/// case are equal. `_` is accepted as a separator as well as `-`, for tags
+/// like `en_US` that come from the environment.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// let tag: l20n::LanguageTag = "zh-hant-tw".parse().unwrap();
+/// assert_eq!(tag.language, "zh");
+/// assert_eq!(tag.script.as_ref().map(|s| &s[..]), Some("Hant"));
+/// assert_eq!(tag.to_string(), "zh-Hant-TW");
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct LanguageTag {
+    /// The language, in lower case: `en`.
+    pub language: String,
+    /// The script, in title case: `Latn`.
+    pub script: Option<String>,
+    /// The region, in upper case: `US` or `419`.
+    pub region: Option<String>,
+    /// Variants, in lower case: `rozaj`, `1996`.
+    pub variants: Vec<String>,
+    /// Extensions and private use subtags, each with its singleton, in lower
+    /// case: `u-ca-buddhist`, `x-private`. They are kept but do not take
+    /// part in negotiation.
+    pub extensions: Vec<String>,
+}
+
+/// An error parsing a LanguageTag.
+#[derive(Debug, PartialEq)]
+pub enum TagError {
+    /// The tag is empty.
+    Empty,
+    /// A subtag is not valid where it is, or not valid at all.
+    InvalidSubtag(String),
+}
+
+/// How `negotiate` picks from the available tags.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NegotiationStrategy {
+    /// Every available tag that matches any requested one, best matches of
+    /// the first requested tag first.
+    Filtering,
+    /// The best available tag for each requested one.
+    Matching,
+    /// The single best available tag.
+    Lookup,
+}
+
+impl LanguageTag {
+    /// Parses a tag. Same as `str::parse`.
+    pub fn parse(tag: &str) -> Result<LanguageTag, TagError> {
+        let mut subtags = tag.split(['-', '_']).peekable();
+        let language = match subtags.next() {
+            Some("") | None => return Err(TagError::Empty),
+            Some(s) if is_alpha(s) && (s.len() >= 2 && s.len() <= 3 || s.len() >= 5 && s.len() <= 8) => {
+                s.to_ascii_lowercase()
+            }
+            Some(s) => return Err(TagError::InvalidSubtag(s.to_string())),
+        };
+        let mut tag = LanguageTag {
+            language,
+            script: None,
+            region: None,
+            variants: vec![],
+            extensions: vec![],
+        };
+
+        if let Some(&s) = subtags.peek() {
+            if s.len() == 4 && is_alpha(s) {
+                let mut script = s.to_ascii_lowercase();
+                script[..1].make_ascii_uppercase();
+                tag.script = Some(script);
+                subtags.next();
+            }
+        }
+        if let Some(&s) = subtags.peek() {
+            if s.len() == 2 && is_alpha(s) || s.len() == 3 && s.bytes().all(|b| b.is_ascii_digit()) {
+                tag.region = Some(s.to_ascii_uppercase());
+                subtags.next();
+            }
+        }
+        while let Some(&s) = subtags.peek() {
+            let variant = s.len() >= 5 && s.len() <= 8 && is_alphanumeric(s)
+                || s.len() == 4 && s.as_bytes()[0].is_ascii_digit() && is_alphanumeric(s);
+            if !variant {
+                break;
+            }
+            tag.variants.push(s.to_ascii_lowercase());
+            subtags.next();
+        }
+        while let Some(singleton) = subtags.next() {
+            if singleton.len() != 1 || !is_alphanumeric(singleton) {
+                return Err(TagError::InvalidSubtag(singleton.to_string()));
+            }
+            let private = singleton.eq_ignore_ascii_case("x");
+            let mut extension = singleton.to_ascii_lowercase();
+            // private use runs to the end, other extensions to the next
+            // singleton
+            while let Some(&s) = subtags.peek() {
+                if !private && s.len() == 1 {
+                    break;
+                }
+                let min = if private { 1 } else { 2 };
+                if s.len() < min || s.len() > 8 || !is_alphanumeric(s) {
+                    return Err(TagError::InvalidSubtag(s.to_string()));
+                }
+                extension.push('-');
+                extension.push_str(&s.to_ascii_lowercase());
+                subtags.next();
+            }
+            if extension.len() == 1 {
+                return Err(TagError::InvalidSubtag(extension));
+            }
+            tag.extensions.push(extension);
+        }
+        Ok(tag)
+    }
+
+    /// How well an available tag serves a requested one, lower being
+    /// better, or None if it does not at all.
+    pub(crate) fn distance(&self, available: &LanguageTag) -> Option<u8> {
+        fn covers(general: &Option<String>, specific: &Option<String>) -> bool {
+            general.is_none() || general == specific
+        }
+        if self.language != available.language {
+            return None;
+        }
+        let same_variants = self.variants == available.variants;
+        if self.script == available.script && self.region == available.region && same_variants {
+            // en-US for en-US
+            Some(0)
+        } else if covers(&available.script, &self.script) && covers(&available.region, &self.region)
+            && (available.variants.is_empty() || same_variants) {
+            // en for en-US
+            Some(1)
+        } else if covers(&self.script, &available.script) && covers(&self.region, &available.region)
+            && (self.variants.is_empty() || same_variants) {
+            // en-US for en
+            Some(2)
+        } else if covers(&self.script, &available.script) || covers(&available.script, &self.script) {
+            // en-GB for en-US
+            Some(3)
+        } else {
+            // sr-Latn for sr-Cyrl
+            Some(4)
+        }
+    }
+}
+
+impl FromStr for LanguageTag {
+    type Err = TagError;
+
+    fn from_str(s: &str) -> Result<LanguageTag, TagError> {
+        LanguageTag::parse(s)
+    }
+}
+
+impl fmt::Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.language)?;
+        for subtag in self.script.iter().chain(&self.region).chain(&self.variants).chain(&self.extensions) {
+            write!(f, "-{}", subtag)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_alpha(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphabetic())
+}
+
+fn is_alphanumeric(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Picks the available tags to use for the requested ones, in the order
+/// to try them, with `default` last if it is given.
+///
+/// An available tag matches a requested one if they have the same language.
+/// Better matches are, in order: the same tag, a more general tag (`en` for
+/// `en-US`), a more specific one (`en-US` for `en`), another region (`en-GB`
+/// for `en-US`), and another script.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use l20n::{negotiate, LanguageTag, NegotiationStrategy};
+///
+/// fn tags(tags: &[&str]) -> Vec<LanguageTag> {
+///     tags.iter().map(|t| t.parse().unwrap()).collect()
+/// }
+///
+/// let chain = negotiate(
+///     &tags(&["de-AT", "fr"]),
+///     &tags(&["en-US", "fr-CA", "de", "de-DE", "fr-FR"]),
+///     Some(&"en-US".parse().unwrap()),
+///     NegotiationStrategy::Matching,
+/// );
+/// assert_eq!(chain, tags(&["de", "fr-CA", "en-US"]));
+/// ```
+pub fn negotiate(requested: &[LanguageTag],
+                 available: &[LanguageTag],
+                 default: Option<&LanguageTag>,
+                 strategy: NegotiationStrategy) -> Vec<LanguageTag> {
+    let mut chosen: Vec<LanguageTag> = vec![];
+    'requested: for req in requested {
+        let mut matches: Vec<(u8, &LanguageTag)> = available.iter()
+            .filter_map(|avail| req.distance(avail).map(|d| (d, avail)))
+            .filter(|&(_, avail)| !chosen.contains(avail))
+            .collect();
+        // stable, so equally good tags stay in the order they are available
+        matches.sort_by_key(|&(d, _)| d);
+        for (_, avail) in matches {
+            chosen.push(avail.clone());
+            match strategy {
+                NegotiationStrategy::Filtering => {}
+                NegotiationStrategy::Matching => continue 'requested,
+                NegotiationStrategy::Lookup => break 'requested,
+            }
+        }
+    }
+    if let Some(default) = default {
+        if !chosen.contains(default) {
+            chosen.push(default.clone());
+        }
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{negotiate, LanguageTag, NegotiationStrategy, TagError};
+
+    fn tags(tags: &[&str]) -> Vec<LanguageTag> {
+        tags.iter().map(|t| t.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let tag = LanguageTag::parse("sl-latn-IT-rozaj-1994-u-ca-buddhist-x-Mine").unwrap();
+        assert_eq!(tag, LanguageTag {
+            language: String::from("sl"),
+            script: Some(String::from("Latn")),
+            region: Some(String::from("IT")),
+            variants: vec![String::from("rozaj"), String::from("1994")],
+            extensions: vec![String::from("u-ca-buddhist"), String::from("x-mine")],
+        });
+        assert_eq!(tag.to_string(), "sl-Latn-IT-rozaj-1994-u-ca-buddhist-x-mine");
+        assert_eq!(LanguageTag::parse("EN_us").unwrap().to_string(), "en-US");
+        assert_eq!(LanguageTag::parse("es-419").unwrap().region, Some(String::from("419")));
+
+        assert_eq!(LanguageTag::parse(""), Err(TagError::Empty));
+        assert_eq!(LanguageTag::parse("e"), Err(TagError::InvalidSubtag(String::from("e"))));
+        assert_eq!(LanguageTag::parse("en-US-"), Err(TagError::InvalidSubtag(String::from(""))));
+        assert_eq!(LanguageTag::parse("en-a"), Err(TagError::InvalidSubtag(String::from("a"))));
+        assert_eq!(LanguageTag::parse("en-US-Latn"), Err(TagError::InvalidSubtag(String::from("Latn"))));
+        assert_eq!(LanguageTag::parse("en-ü"), Err(TagError::InvalidSubtag(String::from("ü"))));
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let available = tags(&["en-US", "en", "en-GB", "fr-CA", "fr", "sr-Cyrl", "sr-Latn-RS"]);
+        let negotiated = |requested: &[&str], strategy| {
+            let chain = negotiate(&tags(requested), &available, None, strategy);
+            chain.iter().map(|t| t.to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(negotiated(&["en-AU", "fr-FR"], NegotiationStrategy::Filtering),
+                   vec!["en", "en-US", "en-GB", "fr", "fr-CA"]);
+        assert_eq!(negotiated(&["en-AU", "fr-FR"], NegotiationStrategy::Matching),
+                   vec!["en", "fr"]);
+        assert_eq!(negotiated(&["de", "en-AU", "fr-FR"], NegotiationStrategy::Lookup),
+                   vec!["en"]);
+        assert_eq!(negotiated(&["en-GB", "en-GB"], NegotiationStrategy::Matching),
+                   vec!["en-GB", "en"]);
+        assert_eq!(negotiated(&["fr"], NegotiationStrategy::Filtering), vec!["fr", "fr-CA"]);
+        assert_eq!(negotiated(&["sr-Latn"], NegotiationStrategy::Filtering),
+                   vec!["sr-Latn-RS", "sr-Cyrl"]);
+        assert!(negotiated(&["de"], NegotiationStrategy::Filtering).is_empty());
+
+        let chain = negotiate(&tags(&["de"]), &available, Some(&available[0]), NegotiationStrategy::Lookup);
+        assert_eq!(chain, tags(&["en-US"]));
+    }
+}
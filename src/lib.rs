@@ -29,12 +29,40 @@
 
 extern crate serde;
 
-pub use context::{Locale, LocalizeResult, LocalizeError};
-pub use data::{EncodeError};
+pub use accept::{locales_for_request, parse_accept_language, LanguageRange};
+pub use binary::{compile_binary, BinaryError};
+pub use context::{Locale, LocalizeResult, LocalizeError, DuplicatePolicy, Duplicate, ResourceError};
+pub use data::{EncodeError, Arg};
+pub use dump::{dump_json, load_json, JsonError, JSON_VERSION};
 pub use compiler::ResolveError;
-pub use parser::{ParseError, ParseErrorKind};
+pub use import::{FileLoader, ResourceLoader};
+pub use langtag::{negotiate, LanguageTag, NegotiationStrategy, TagError};
+pub use lint::{lint, Lint, LintKind};
+pub use loader::{Loader, LoadError};
+pub use parser::{parse, ParseError, ParseErrorKind, Span};
+pub use pseudo::{Pseudo, PseudoLocale};
+pub use reload::ReloadingLocale;
+pub use serializer::serialize;
+pub use shared::SharedLocale;
 
+mod accept;
+pub mod ast;
+mod binary;
+pub mod build;
 mod compiler;
+pub mod convert;
 mod data;
+mod dump;
+mod import;
+mod ir;
+mod json;
 mod context;
+mod langtag;
+mod lint;
+mod loader;
 mod parser;
+mod pseudo;
+mod reload;
+mod serializer;
+mod shared;
+pub mod visit;
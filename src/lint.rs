@@ -0,0 +1,270 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use compiler;
+use parser::ParseError;
+use parser;
+use visit::{self, Visit};
+
+use self::LintKind::*;
+
+/// A problem found in an L20n resource without having to localize it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct Lint {
+    /// The id of the entry where the problem was found.
+    pub id: String,
+    /// The kind of problem.
+    pub kind: LintKind,
+}
+
+/// The description of the Lint that was found.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub enum LintKind {
+    /// Referenced an entity or macro that does not exist.
+    UnknownIdent(String),
+    /// Called an entity as if it were a macro.
+    NotAMacro(String),
+    /// Called a macro with the wrong number of arguments. Holds the macro
+    /// name, the number of arguments it takes, and the number it was given.
+    WrongNumberOfArgs(String, usize, usize),
+    /// Accessed an attribute the entity does not have. Holds the entity and
+    /// the attribute names.
+    MissingAttr(String, String),
+    /// A Hash has neither a default key nor an index to pick a value.
+    MissingDefault,
+    /// A macro that is never called.
+    UnusedMacro,
+    /// The id is defined more than once, and only one definition is kept.
+    DuplicateId,
+    /// Used a $var that is not in the declared list of variables.
+    UndeclaredVar(String),
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.id)?;
+        match self.kind {
+            UnknownIdent(ref name) => write!(f, "unknown entity or macro `{}`", name),
+            NotAMacro(ref name) => write!(f, "`{}` is called but is not a macro", name),
+            WrongNumberOfArgs(ref name, expected, found) => {
+                write!(f, "macro `{}` takes {} arguments but was given {}", name, expected, found)
+            }
+            MissingAttr(ref entity, ref attr) => {
+                write!(f, "entity `{}` has no attribute `{}`", entity, attr)
+            }
+            MissingDefault => write!(f, "hash has no default key or index"),
+            UnusedMacro => write!(f, "macro is never used"),
+            DuplicateId => write!(f, "defined more than once"),
+            UndeclaredVar(ref name) => write!(f, "variable `${}` is not declared", name),
+        }
+    }
+}
+
+/// Checks L20n resources for problems that would otherwise only be found
+/// when localizing them.
+///
+/// All `sources` are checked together, as if added to the same `Locale`.
+/// If `vars` is given, any `$var` not in the list (or a macro argument) is
+/// reported as undeclared.
+pub fn lint(sources: &[&str], vars: Option<&[&str]>) -> Result<Vec<Lint>, ParseError> {
+    let mut lints = vec![];
+    let mut env = HashMap::new();
+    for source in sources {
+        for def in compiler::compile(source)?.definitions {
+            if env.insert(def.id.clone(), def.entry).is_some() {
+                lints.push(Lint { id: def.id, kind: DuplicateId });
+            }
+        }
+    }
+
+    lints.extend(lint_env(&env, vars));
+    lints.sort();
+    lints.dedup();
+    Ok(lints)
+}
+
+pub(crate) fn lint_env(env: &compiler::Env, vars: Option<&[&str]>) -> Vec<Lint> {
+    let mut linter = Linter {
+        env,
+        vars,
+        id: "",
+        params: &[],
+        used_macros: HashSet::new(),
+        lints: vec![],
+    };
+
+    for entry in env.values() {
+        linter.visit_entry(entry);
+    }
+
+    for (id, entry) in env {
+        if let parser::Macro(..) = *entry {
+            if !linter.used_macros.contains(id.as_str()) {
+                linter.id = id;
+                linter.lint(UnusedMacro);
+            }
+        }
+    }
+
+    linter.lints
+}
+
+struct Linter<'a> {
+    env: &'a compiler::Env<'a>,
+    vars: Option<&'a [&'a str]>,
+    // the entry being checked, and its macro arguments
+    id: &'a str,
+    params: &'a [parser::Expr<'a>],
+    used_macros: HashSet<&'a str>,
+    lints: Vec<Lint>,
+}
+
+impl<'a> Linter<'a> {
+    fn lint(&mut self, kind: LintKind) {
+        self.lints.push(Lint { id: self.id.to_string(), kind });
+    }
+}
+
+impl<'a> Visit<'a> for Linter<'a> {
+    fn visit_entry(&mut self, entry: &'a parser::Entry) {
+        match *entry {
+            parser::Entity(ref id, ..) => {
+                self.id = id;
+                self.params = &[];
+            }
+            parser::Macro(ref id, ref args, _) => {
+                self.id = id;
+                self.params = args;
+            }
+            parser::Comment(..) | parser::Import(..) => {}
+        }
+        visit::walk_entry(self, entry);
+    }
+
+    fn visit_value(&mut self, value: &'a parser::Value) {
+        if let parser::Hash(_, None, None) = *value {
+            self.lint(MissingDefault);
+        }
+        visit::walk_value(self, value);
+    }
+
+    fn visit_expr(&mut self, expr: &'a parser::Expr) {
+        match *expr {
+            parser::AttrExpr(ref parent, ref prop, parser::Static) => {
+                if let (parser::IdentExpr(entity), parser::IdentExpr(attr)) = (&**parent, &**prop) {
+                    if let Some(parser::Entity(_, _, _, attrs)) = self.env.get(&**entity) {
+                        if !attrs.iter().any(|a| a.0 == *attr) {
+                            self.lint(MissingAttr(entity.to_string(), attr.to_string()));
+                        }
+                    }
+                }
+            }
+            parser::CallExpr(ref callee, ref args) => {
+                if let parser::IdentExpr(ref name) = **callee {
+                    match self.env.get_key_value(&**name) {
+                        Some((name, parser::Macro(_, macro_args, _))) => {
+                            // a macro calling itself does not make it used
+                            if name != self.id {
+                                self.used_macros.insert(name);
+                            }
+                            if macro_args.len() != args.len() {
+                                self.lint(WrongNumberOfArgs(name.clone(), macro_args.len(), args.len()));
+                            }
+                        }
+                        Some(_) => self.lint(NotAMacro(name.to_string())),
+                        None => self.lint(UnknownIdent(name.to_string())),
+                    }
+                    for arg in args {
+                        self.visit_expr(arg);
+                    }
+                    return;
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expr(self, expr);
+    }
+
+    fn visit_var(&mut self, name: &'a str) {
+        let is_param = self.params.iter().any(|p| matches!(*p, parser::VarExpr(ref param) if param == name));
+        if let Some(vars) = self.vars {
+            if !is_param && !vars.contains(&name) {
+                self.lint(UndeclaredVar(name.to_string()));
+            }
+        }
+    }
+
+    fn visit_ident(&mut self, name: &'a str) {
+        if !self.env.contains_key(name) {
+            self.lint(UnknownIdent(name.to_string()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, Lint, LintKind};
+    use super::LintKind::*;
+
+    fn l(id: &str, kind: LintKind) -> Lint {
+        Lint { id: id.to_string(), kind }
+    }
+
+    #[test]
+    fn test_lint_clean() {
+        let src = r#"
+        <brand 'Rust' long: 'Rust Lang'>
+        <hi 'Hello, {{ brand::long }}!'>
+        <fac($n) { $n == 0 ? 1 : $n * fac($n - 1) }>
+        <factorial "Factorial of {{ $number }} is {{ fac($number) }}.">
+        "#;
+        assert_eq!(lint(&[src], Some(&["number"])).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_lint_references() {
+        let src = r#"
+        <brand 'Rust'>
+        <two($a, $b) { $a + $b }>
+        <unused($a) { $a }>
+        <recursive($n) { $n == 0 ? 0 : recursive($n - 1) }>
+        <hi 'Hello, {{ brnd }} {{ brand::long }} {{ two(1) }} {{ brand(1) }}'>
+        "#;
+        assert_eq!(lint(&[src], None).unwrap(), vec![
+            l("hi", UnknownIdent(String::from("brnd"))),
+            l("hi", NotAMacro(String::from("brand"))),
+            l("hi", WrongNumberOfArgs(String::from("two"), 2, 1)),
+            l("hi", MissingAttr(String::from("brand"), String::from("long"))),
+            l("recursive", UnusedMacro),
+            l("unused", UnusedMacro),
+        ]);
+    }
+
+    #[test]
+    fn test_lint_hash_default() {
+        let src = r#"
+        <pro { masculine: 'his', feminine: 'her' }>
+        <pro2 { *masculine: 'his', feminine: 'her' }>
+        <pro3['feminine'] { masculine: 'his', feminine: 'her' }>
+        "#;
+        assert_eq!(lint(&[src], None).unwrap(), vec![l("pro", MissingDefault)]);
+    }
+
+    #[test]
+    fn test_lint_duplicates_across_sources() {
+        let a = "<hi 'Hello'>";
+        let b = "<hi 'Hi'>";
+        assert_eq!(lint(&[a, b], None).unwrap(), vec![l("hi", DuplicateId)]);
+    }
+
+    #[test]
+    fn test_lint_undeclared_vars() {
+        let src = r#"
+        <greet($who) { $who }>
+        <hi 'Hello, {{ greet($name) }} and {{ $nmae }}'>
+        "#;
+        assert_eq!(lint(&[src], Some(&["name"])).unwrap(), vec![
+            l("hi", UndeclaredVar(String::from("nmae"))),
+        ]);
+    }
+}
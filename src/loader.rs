@@ -0,0 +1,287 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use context::{Duplicate, Locale};
+use import::FileLoader;
+use langtag::LanguageTag;
+use parser::ParseError;
+
+/// Finds and loads resources laid out on disk by locale.
+///
+/// The layout is a template of `/` separated path components relative to a
+/// root, where `{locale}` stands for a locale name and `{file}` for any file
+/// name, such as `locales/{locale}/{file}.l20n` or `l10n/app.{locale}.l20n`.
+/// A placeholder matches at least one character within a component.
+///
+/// ```rust,no_run
+/// # #![allow(deprecated)]
+/// let loader = l20n::Loader::new(".", "locales/{locale}/{file}.l20n").unwrap();
+/// for name in loader.locales().unwrap() {
+///     let locale = loader.load(&name).unwrap();
+///     println!("{}: {} comments", name, locale.comments().len());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Loader {
+    root: PathBuf,
+    components: Vec<Vec<Piece>>,
+}
+
+/// An error finding or loading resources.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The template has no `{locale}`, or an unknown `{placeholder}`.
+    Template(String),
+    /// No files match the template for the locale.
+    MissingLocale(String),
+    /// Reading a file or directory failed.
+    Io(PathBuf, io::Error),
+    /// A resource is not valid L20n.
+    Parse(PathBuf, ParseError),
+    /// A resource redefines an id under `DuplicatePolicy::Error`.
+    Duplicate(PathBuf, Box<Duplicate>),
+    /// Resources import each other, from the first one to import the
+    /// resource that imports it again.
+    ImportCycle(Vec<PathBuf>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Locale,
+    File,
+}
+
+impl Loader {
+    /// Creates a loader for the resources under `root` laid out as
+    /// `template`.
+    pub fn new<P: AsRef<Path>>(root: P, template: &str) -> Result<Loader, LoadError> {
+        let mut components = vec![];
+        let mut has_locale = false;
+        for component in template.split('/').filter(|c| !c.is_empty() && *c != ".") {
+            let pieces = pieces(component)
+                .ok_or_else(|| LoadError::Template(template.to_string()))?;
+            has_locale |= pieces.contains(&Piece::Locale);
+            components.push(pieces);
+        }
+        if !has_locale {
+            return Err(LoadError::Template(template.to_string()));
+        }
+        Ok(Loader {
+            root: root.as_ref().to_path_buf(),
+            components,
+        })
+    }
+
+    /// The locales that have at least one resource on disk, sorted by name.
+    pub fn locales(&self) -> Result<Vec<String>, LoadError> {
+        let locales: BTreeSet<String> = self.find(None)?.into_iter()
+            .map(|(locale, _)| locale)
+            .collect();
+        Ok(locales.into_iter().collect())
+    }
+
+    /// The resources of `locale`, sorted by path.
+    pub fn files(&self, locale: &str) -> Result<Vec<PathBuf>, LoadError> {
+        let files: Vec<PathBuf> = self.find(Some(locale))?.into_iter()
+            .map(|(_, path)| path)
+            .collect();
+        if files.is_empty() {
+            return Err(LoadError::MissingLocale(locale.to_string()));
+        }
+        Ok(files)
+    }
+
+    /// Reads the resources of `locale` into a new `Locale`, in the order of
+    /// their paths. The `Locale` has `locale` as its language if it is a
+    /// valid language tag.
+    pub fn load(&self, locale: &str) -> Result<Locale, LoadError> {
+        let mut l = Locale::new();
+        if let Ok(tag) = LanguageTag::parse(locale) {
+            l.set_language(tag);
+        }
+        self.load_into(locale, &mut l)?;
+        Ok(l)
+    }
+
+    /// Reads the resources of `locale` into an existing `Locale`, such as one
+    /// with a `DuplicatePolicy` set. Imports are read relative to the file
+    /// they are in, and a file is only added once, even if it is imported.
+    pub fn load_into(&self, locale: &str, into: &mut Locale) -> Result<(), LoadError> {
+        let names: Vec<String> = self.files(locale)?.iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        into.add_resources_from(&FileLoader, &names)
+    }
+
+    /// Walks the template one component at a time, keeping every path that
+    /// matches so far with the locale it matched.
+    fn find(&self, locale: Option<&str>) -> Result<Vec<(String, PathBuf)>, LoadError> {
+        let mut found = vec![(locale.map(String::from), self.root.clone())];
+        let last = self.components.len() - 1;
+        for (i, component) in self.components.iter().enumerate() {
+            let mut next = vec![];
+            for (locale, dir) in found {
+                let entries = match fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    // only a missing root is an error, other directories
+                    // may exist for some locales and not for others
+                    Err(ref e) if i > 0 && e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(LoadError::Io(dir, e)),
+                };
+                for entry in entries {
+                    let entry = entry.map_err(|e| LoadError::Io(dir.clone(), e))?;
+                    let name = match entry.file_name().into_string() {
+                        Ok(name) => name,
+                        Err(_) => continue,
+                    };
+                    let mut matched = locale.clone();
+                    if !matches(component, &name, &mut matched) {
+                        continue;
+                    }
+                    let path = entry.path();
+                    let is_dir = path.is_dir();
+                    if (i == last) != is_dir {
+                        next.push((matched, path));
+                    }
+                }
+            }
+            found = next;
+        }
+        let mut found: Vec<(String, PathBuf)> = found.into_iter()
+            .filter_map(|(locale, path)| locale.map(|locale| (locale, path)))
+            .collect();
+        found.sort_by(|a, b| a.1.cmp(&b.1));
+        Ok(found)
+    }
+}
+
+/// Splits a template component into text and placeholders.
+fn pieces(component: &str) -> Option<Vec<Piece>> {
+    let mut pieces = vec![];
+    let mut rest = component;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            pieces.push(Piece::Text(rest[..start].to_string()));
+        }
+        let end = rest[start..].find('}')? + start;
+        pieces.push(match &rest[start + 1..end] {
+            "locale" => Piece::Locale,
+            "file" => Piece::File,
+            _ => return None,
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest.to_string()));
+    }
+    Some(pieces)
+}
+
+/// Matches a file name against a template component. `{locale}` has to
+/// match the same text every time, and records it in `locale` the first.
+fn matches(pieces: &[Piece], name: &str, locale: &mut Option<String>) -> bool {
+    let (first, rest) = match pieces.split_first() {
+        Some(split) => split,
+        None => return name.is_empty(),
+    };
+    match *first {
+        Piece::Text(ref text) => {
+            name.starts_with(&text[..]) && matches(rest, &name[text.len()..], locale)
+        }
+        Piece::Locale if locale.is_some() => {
+            let known = locale.clone().unwrap();
+            name.starts_with(&known[..]) && matches(rest, &name[known.len()..], locale)
+        }
+        Piece::Locale | Piece::File => {
+            // try the shortest match first, so `{locale}.{file}` splits at
+            // the first dot
+            for (end, _) in name.char_indices().skip(1).chain(Some((name.len(), ' '))) {
+                let mut captured = locale.clone();
+                if *first == Piece::Locale {
+                    captured = Some(name[..end].to_string());
+                }
+                if matches(rest, &name[end..], &mut captured) {
+                    *locale = captured;
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use super::{LoadError, Loader};
+
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = env::temp_dir().join(format!("l20n-loader-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for &(path, source) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_load() {
+        let root = tree("load", &[
+            ("locales/en-US/main.l20n", "<hi 'Hi'>"),
+            ("locales/en-US/more.l20n", "import('../../shared/brand.l20n')\n<bye 'Bye from {{ brand }}'>"),
+            ("shared/brand.l20n", "<brand 'Acme'>"),
+            ("locales/en-US/notes.txt", "not a resource"),
+            ("locales/fr/main.l20n", "<hi 'Salut'>"),
+            ("locales/de/broken.l20n", "<hi"),
+            ("locales/empty/README", ""),
+        ]);
+        let loader = Loader::new(&root, "locales/{locale}/{file}.l20n").unwrap();
+        assert_eq!(loader.locales().unwrap(), vec!["de", "en-US", "fr"]);
+        assert_eq!(loader.files("en-US").unwrap(), vec![
+            root.join("locales/en-US/main.l20n"),
+            root.join("locales/en-US/more.l20n"),
+        ]);
+
+        let locale = loader.load("en-US").unwrap();
+        assert_eq!(locale.localize_entity("bye", ()).unwrap(), "Bye from Acme");
+        assert_eq!(locale.language().map(|l| l.to_string()), Some(String::from("en-US")));
+        match loader.load("de").err() {
+            Some(LoadError::Parse(path, _)) => assert_eq!(path, root.join("locales/de/broken.l20n")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match loader.load("empty").err() {
+            Some(LoadError::MissingLocale(ref locale)) => assert_eq!(locale, "empty"),
+            other => panic!("expected a missing locale, got {:?}", other),
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_flat_layout() {
+        let root = tree("flat", &[
+            ("app.en.l20n", "<hi 'Hi'>"),
+            ("app.pt-BR.l20n", "<hi 'Oi'>"),
+            ("other.en.l20n", "<hi 'Hey'>"),
+        ]);
+        let loader = Loader::new(&root, "app.{locale}.l20n").unwrap();
+        assert_eq!(loader.locales().unwrap(), vec!["en", "pt-BR"]);
+        let locale = loader.load("pt-BR").unwrap();
+        assert_eq!(locale.localize_entity("hi", ()).unwrap(), "Oi");
+        fs::remove_dir_all(root).unwrap();
+
+        assert!(Loader::new(".", "{file}.l20n").is_err());
+        assert!(Loader::new(".", "{locale}/{name}.l20n").is_err());
+        match Loader::new("/does/not/exist", "{locale}.l20n").unwrap().locales() {
+            Err(LoadError::Io(ref path, _)) => assert_eq!(path.to_str(), Some("/does/not/exist")),
+            other => panic!("expected an io error, got {:?}", other),
+        }
+    }
+}
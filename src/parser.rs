@@ -2,17 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 pub type Result<T> = ::std::result::Result<T, ParseError>;
 
 pub use self::ParseErrorKind::*;
-pub use self::Entry::*;
-pub use self::Value::*;
-pub use self::AccessType::*;
-pub use self::Expr::*;
-pub use self::BinOp::*;
-pub use self::UnOp::*;
+pub use ast::*;
 
 /// An error occurred trying to parse an L20n resource. The L20n file is
 /// invalid.
@@ -26,6 +22,19 @@ pub struct ParseError {
     pub col: usize,
 }
 
+/// Where an entry was found in an L20n resource.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    /// The line where the entry starts.
+    pub line: usize,
+    /// The column where the entry starts.
+    pub col: usize,
+    /// The line of the last character of the entry.
+    pub end_line: usize,
+    /// The column of the last character of the entry.
+    pub end_col: usize,
+}
+
 /// The description of the ParseError that occurred.
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ParseErrorKind {
@@ -55,125 +64,133 @@ pub enum ParseErrorKind {
     StrError,
     /// Illegal syntax for a Hash.
     HashError,
+    /// Illegal syntax for an import.
+    ImportError,
+    /// A resource imports another where there is nothing to load it with.
+    UnresolvedImportError,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Entry {
-    Entity(String, Value, Vec<Expr>, Vec<Attr>),
-    Macro(String, Vec<Expr>, Expr),
-    Comment(String),
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum Value {
-    Str(String),
-    ComplexStr(Vec<Expr>),
-    Hash(HashMap<String, Value>, Option<String>, Option<Box<Expr>>)
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum AccessType {
-    Computed,
-    Static,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
-    CondExpr(Box<Expr>, Box<Expr>, Box<Expr>),
-    BinExpr(Box<Expr>, BinOp, Box<Expr>),
-    UnExpr(UnOp, Box<Expr>),
-    VarExpr(String),
-    ValExpr(Value),
-    PropExpr(Box<Expr>, Box<Expr>, AccessType),
-    AttrExpr(Box<Expr>, Box<Expr>, AccessType),
-    CallExpr(Box<Expr>, Vec<Expr>),
-    IdentExpr(String),
-    NumExpr(i64),
-    ParenExpr(Box<Expr>),
-    GlobalExpr(String),
-    ThisExpr,
+/// Parses an L20n resource into its entries, without compiling it.
+///
+/// The entries borrow their names and text from `source`. Call `into_owned`
+/// on them to keep them after `source` is dropped.
+///
+/// ```rust
+/// use l20n::ast::{Entity, Str};
+///
+/// let entries = l20n::parse("<hi 'Hello'>").unwrap();
+/// assert_eq!(entries, vec![
+///     Entity("hi".into(), Str("Hello".into()), vec![], vec![]),
+/// ]);
+/// ```
+pub fn parse<'a>(source: &'a str) -> Result<Vec<Entry<'a>>> {
+    Parser::new(source).parse()
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Attr(pub String, pub Value, pub Vec<Expr>);
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum BinOp {
-    BiAdd,
-    BiSub,
-    BiMul,
-    BiDiv,
-    BiRem,
-    BiAnd,
-    BiOr,
-    BiEq,
-    BiNe,
-    BiLt,
-    BiLe,
-    BiGt,
-    BiGe
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum UnOp {
-    UnAdd,
-    UnSub,
-    UnNot
+/// Parses the contents of a string without its quotes, such as text with
+/// `{{ }}` placeables kept in another format. Quotes in it are text.
+pub fn parse_text(text: &str) -> Result<Value<'static>> {
+    // NUL stands in for the quotes, as it cannot be in text anyway
+    let source = format!("\u{0}{}\u{0}", text);
+    let mut parser = Parser::new(&source);
+    parser.bump();
+    let value = parser.parse_str()?;
+    if parser.ch.is_some() {
+        return Err(parser.error(StrError));
+    }
+    Ok(value.into_owned())
 }
 
-pub struct Parser<T> {
-    reader: T,
+pub struct Parser<'a> {
+    source: &'a str,
     ch: Option<char>,
-    lookahead: Option<char>,
+    // the byte offsets of `ch` and of the character after it
+    pos: usize,
+    next: usize,
     line: usize,
     col: usize,
+    prev: (usize, usize),
 }
 
-impl<T: Iterator<Item=char>> Parser<T> {
-    pub fn new(source: T) -> Parser<T> {
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Parser<'a> {
         Parser {
-            reader: source,
+            source,
             ch: None,
-            lookahead: None,
+            pos: 0,
+            next: 0,
             line: 0,
             col: 0,
+            prev: (0, 0),
         }
     }
 
     fn error(&self, kind: ParseErrorKind) -> ParseError {
         ParseError {
-            kind: kind,
+            kind,
             line: self.line,
             col: self.col,
         }
     }
 
     fn bump(&mut self) {
-        match self.lookahead.take() {
-            None => { self.ch = self.reader.next(); }
-            Some(ch) => { self.ch = Some(ch); }
+        self.prev = (self.line, self.col);
+        self.pos = self.next;
+        self.ch = self.char_at(self.pos);
+
+        if let Some(ch) = self.ch {
+            self.next = self.pos + ch.len_utf8();
+            if ch == '\n' {
+                self.line += 1;
+                self.col = 1;
+            } else {
+                self.col += 1;
+            }
         }
+    }
 
-        match self.ch {
-            Some(ch) => {
-                if ch == '\n' {
-                    self.line += 1;
-                    self.col = 1;
-                } else {
-                    self.col += 1;
-                }
-            }
-            None => { }
+    /// Moves ahead over the characters after the current one while they are
+    /// ASCII (other than a newline) and match `f`, stopping on the last of
+    /// them. This is the same as calling `bump` for each one, without
+    /// decoding them one at a time.
+    fn skip_while<F: Fn(u8) -> bool>(&mut self, f: F) {
+        let bytes = self.source.as_bytes();
+        let mut end = self.next;
+        while end < bytes.len() && bytes[end] < 0x80 && bytes[end] != b'\n' && f(bytes[end]) {
+            end += 1;
+        }
+        if end > self.next {
+            let skipped = end - self.next;
+            self.prev = (self.line, self.col + skipped - 1);
+            self.col += skipped;
+            self.pos = end - 1;
+            self.next = end;
+            self.ch = Some(bytes[self.pos] as char);
+        }
+    }
+
+    fn char_at(&self, pos: usize) -> Option<char> {
+        match self.source.as_bytes().get(pos) {
+            Some(&b) if b < 0x80 => Some(b as char),
+            Some(_) => self.source[pos..].chars().next(),
+            None => None,
         }
     }
 
-    fn peek(&mut self) -> Option<char> {
-        match self.lookahead {
-            None => {
-                self.lookahead = self.reader.next();
-                self.lookahead
+    fn peek(&self) -> Option<char> {
+        self.char_at(self.next)
+    }
+
+    /// The source from `start` up to the current character, appended to
+    /// `buf` if part of it was already copied.
+    fn slice(&self, start: usize, buf: Option<String>) -> Cow<'a, str> {
+        let slice = &self.source[start..self.pos];
+        match buf {
+            Some(mut buf) => {
+                buf.push_str(slice);
+                Cow::Owned(buf)
             }
-            Some(ch) => Some(ch),
+            None => Cow::Borrowed(slice),
         }
     }
 
@@ -181,40 +198,61 @@ impl<T: Iterator<Item=char>> Parser<T> {
         self.ch == Some(ch)
     }
 
-    pub fn parse(mut self) -> Result<Vec<Entry>> {
+    pub fn parse(mut self) -> Result<Vec<Entry<'a>>> {
         self.bump();
         let mut entries = vec!();
-        loop {
-            self.parse_whitespace();
-            let ch = match self.ch {
-                Some(ch) => ch,
-                None => { break; }
-            };
+        while let Some((entry, _)) = self.parse_next()? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
 
-            if ch == '<' {
-                entries.push(try!(self.parse_entry()));
-            } else if ch == '/' && self.peek() == Some('*') {
-                entries.push(try!(self.parse_comment()));
-            } else {
-                return Err(self.error(EntryError))
-            }
+    pub fn parse_spanned(mut self) -> Result<Vec<(Entry<'a>, Span)>> {
+        self.bump();
+        let mut entries = vec!();
+        while let Some(entry) = self.parse_next()? {
+            entries.push(entry);
         }
         Ok(entries)
     }
 
+    fn parse_next(&mut self) -> Result<Option<(Entry<'a>, Span)>> {
+        self.parse_whitespace();
+        let ch = match self.ch {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+
+        let (line, col) = (self.line, self.col);
+        let entry = if ch == '<' {
+            self.parse_entry()?
+        } else if ch == '/' && self.peek() == Some('*') {
+            self.parse_comment()?
+        } else if ch == 'i' {
+            self.parse_import()?
+        } else {
+            return Err(self.error(EntryError))
+        };
+        let (end_line, end_col) = self.prev;
+        Ok(Some((entry, Span { line, col, end_line, end_col })))
+    }
+
     fn parse_whitespace(&mut self) {
         while self.ch_is(' ') ||
                     self.ch_is('\n') ||
                     self.ch_is('\t') ||
-                    self.ch_is('\r') { self.bump(); }
+                    self.ch_is('\r') {
+            self.skip_while(|b| b == b' ' || b == b'\t' || b == b'\r');
+            self.bump();
+        }
     }
 
-    fn parse_entry(&mut self) -> Result<Entry> {
+    fn parse_entry(&mut self) -> Result<Entry<'a>> {
         self.bump();
-        let id = try!(self.parse_identifier());
+        let id = self.parse_identifier()?;
         let val = match self.ch {
-            Some('(') => try!(self.parse_macro(id)),
-            Some(_) => try!(self.parse_entity(id)),
+            Some('(') => self.parse_macro(id)?,
+            Some(_) => self.parse_entity(id)?,
             None => return Err(self.error(EntryError))
         };
 
@@ -227,7 +265,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
         }
     }
 
-    fn parse_macro(&mut self, id: String) -> Result<Entry> {
+    fn parse_macro(&mut self, id: Cow<'a, str>) -> Result<Entry<'a>> {
         if id.as_bytes()[0] == b'_' {
             return Err(self.error(MacroError));
         }
@@ -236,10 +274,10 @@ impl<T: Iterator<Item=char>> Parser<T> {
 
         let mut args = vec![];
 
-        try!(self.parse_list(')', MacroError, |this| {
-            args.push(try!(this.parse_variable()));
+        self.parse_list(')', MacroError, |this| {
+            args.push(this.parse_variable()?);
             Ok(())
-        }));
+        })?;
 
         self.bump();
         self.parse_whitespace();
@@ -250,7 +288,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
         self.bump();
         self.parse_whitespace();
         
-        let body = try!(self.parse_expression());
+        let body = self.parse_expression()?;
 
         self.parse_whitespace();
         
@@ -262,14 +300,14 @@ impl<T: Iterator<Item=char>> Parser<T> {
         Ok(Macro(id, args, body))
     }
 
-    fn parse_entity(&mut self, id: String) -> Result<Entry> {
+    fn parse_entity(&mut self, id: Cow<'a, str>) -> Result<Entry<'a>> {
         let mut index = vec![];
         if self.ch_is('[') {
             self.bump();
-            try!(self.parse_list(']', EntityError, |this| {
-                index.push(try!(this.parse_expression()));
+            self.parse_list(']', EntityError, |this| {
+                index.push(this.parse_expression()?);
                 Ok(())
-            }));
+            })?;
             self.bump();
         }
 
@@ -279,30 +317,30 @@ impl<T: Iterator<Item=char>> Parser<T> {
         };
         self.parse_whitespace();
 
-        let value = try!(self.parse_value());
+        let value = self.parse_value()?;
         self.parse_whitespace();
-        let attrs = try!(self.parse_attrs());
+        let attrs = self.parse_attrs()?;
 
         Ok(Entity(id, value, index, attrs))
     }
 
-    fn parse_attrs(&mut self) -> Result<Vec<Attr>> {
+    fn parse_attrs(&mut self) -> Result<Vec<Attr<'a>>> {
         let mut attrs = vec![];
         loop {
-            match self.ch {
-                Some('>') => break,
-                _ => {}
+            if self.ch_is('>') {
+                break;
             }
 
-            let id = try!(self.parse_identifier());
+            let id = self.parse_identifier()?;
 
             let mut indices = vec![];
             if self.ch_is('[') {
                 self.bump();
-                try!(self.parse_list(']', AttrError, |this| {
-                    indices.push(try!(this.parse_expression()));
+                self.parse_list(']', AttrError, |this| {
+                    indices.push(this.parse_expression()?);
                     Ok(())
-                }));
+                })?;
+                self.bump();
             }
 
             self.parse_whitespace();
@@ -312,37 +350,73 @@ impl<T: Iterator<Item=char>> Parser<T> {
             self.bump();
             self.parse_whitespace();
 
-            let value = try!(self.parse_value());
+            let value = self.parse_value()?;
+            self.parse_whitespace();
 
             attrs.push(Attr(id, value, indices));
         }
         Ok(attrs)
     }
 
-    fn parse_comment(&mut self) -> Result<Entry> {
+    fn parse_comment(&mut self) -> Result<Entry<'a>> {
         self.bump();
         self.bump();
-        let mut s = String::new();
+        let start = self.pos;
         loop {
             match self.ch {
-                Some(c@'*') => {
-                    if self.peek() == Some('/') {
-                        self.bump();
-                        self.bump();
-                        break;
-                    } else {
-                        s.push(c);
-                    }
-                },
-                Some(c) => s.push(c),
+                Some('*') if self.peek() == Some('/') => break,
+                Some(_) => self.skip_while(|b| b != b'*'),
                 None => return Err(self.error(EntryError)),
             }
             self.bump();
         }
+        let s = self.slice(start, None);
+        self.bump();
+        self.bump();
         Ok(Comment(s))
     }
 
-    fn parse_value(&mut self) -> Result<Value> {
+    fn parse_import(&mut self) -> Result<Entry<'a>> {
+        for expected in "import".chars() {
+            if !self.ch_is(expected) {
+                return Err(self.error(ImportError));
+            }
+            self.bump();
+        }
+        self.parse_whitespace();
+        if !self.ch_is('(') {
+            return Err(self.error(ImportError));
+        }
+        self.bump();
+        self.parse_whitespace();
+
+        let quote = match self.ch {
+            Some(quote @ '\'') | Some(quote @ '"') => quote,
+            _ => return Err(self.error(ImportError)),
+        };
+        self.bump();
+        let start = self.pos;
+        while !self.ch_is(quote) {
+            match self.ch {
+                None | Some('\n') => return Err(self.error(ImportError)),
+                Some(_) => self.bump(),
+            }
+        }
+        let path = self.slice(start, None);
+        if path.is_empty() {
+            return Err(self.error(ImportError));
+        }
+        self.bump();
+
+        self.parse_whitespace();
+        if !self.ch_is(')') {
+            return Err(self.error(ImportError));
+        }
+        self.bump();
+        Ok(Import(path))
+    }
+
+    fn parse_value(&mut self) -> Result<Value<'a>> {
         match self.ch {
             Some('"') | Some('\'') => self.parse_str(),
             Some('{') => self.parse_hash(),
@@ -350,43 +424,55 @@ impl<T: Iterator<Item=char>> Parser<T> {
         }
     }
 
-    fn parse_str(&mut self) -> Result<Value> {
-        let mut s = String::new();
+    fn parse_str(&mut self) -> Result<Value<'a>> {
+        // text is borrowed from the source, unless a `\` has to be dropped
+        let mut buf: Option<String> = None;
+        let mut start = self.next;
 
         let quote = self.ch.unwrap();
         let mut exprs = vec![];
 
-        loop {
+        let s = loop {
             self.bump();
             match self.ch {
-                Some(c@'{') => {
-                    if self.peek() == Some('{') {
-                        self.bump();
+                Some('{') if self.peek() == Some('{') => {
+                    let s = self.slice(start, buf.take());
+                    self.bump();
+                    self.bump();
+                    self.parse_whitespace();
+                    let expr = self.parse_expression()?;
+                    self.parse_whitespace();
+                    if self.ch_is('}') && self.peek() == Some('}') {
                         self.bump();
-                        self.parse_whitespace();
-                        let expr = try!(self.parse_expression());
-                        self.parse_whitespace();
-                        if self.ch_is('}') && self.peek() == Some('}') {
-                            self.bump();
-                            exprs.push(ValExpr(Str(s)));
-                            exprs.push(expr);
-                            s = String::new();
-                        } else {
-                            return Err(self.error(ValueError));
-                        }
+                        exprs.push(ValExpr(Str(s)));
+                        exprs.push(expr);
+                        start = self.next;
                     } else {
-                        s.push(c);
+                        return Err(self.error(ValueError));
                     }
                 },
-                Some('\\') => {},
-                Some(c) if c == quote => { self.bump(); break },
-                Some(c) => s.push(c),
+                Some('\\') => {
+                    // drop the `\` and keep the next character as it is,
+                    // even if it is a quote or a `{`
+                    buf = Some(self.slice(start, buf.take()).into_owned());
+                    start = self.next;
+                    self.bump();
+                },
+                Some(c) if c == quote => {
+                    let s = self.slice(start, buf.take());
+                    self.bump();
+                    break s;
+                },
+                Some(_) => {
+                    let quote = quote as u8;
+                    self.skip_while(|b| b != quote && b != b'{' && b != b'\\');
+                },
                 None => return Err(self.error(StrError))
             }
-        }
+        };
 
-        if exprs.len() > 0 {
-            if s.len() > 0 {
+        if !exprs.is_empty() {
+            if !s.is_empty() {
                 exprs.push(ValExpr(Str(s)));
             }
             Ok(ComplexStr(exprs))
@@ -395,7 +481,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
         }
     }
 
-    fn parse_hash(&mut self) -> Result<Value> {
+    fn parse_hash(&mut self) -> Result<Value<'a>> {
         self.bump();
         self.parse_whitespace();
 
@@ -403,7 +489,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
 
         let mut default = None;
 
-        try!(self.parse_list('}', HashError, |this| {
+        self.parse_list('}', HashError, |this| {
             let mut is_default = false;
 
 
@@ -420,7 +506,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
                 Some(_) => {},
                 None => return Err(this.error(HashError)),
             }
-            let key = try!(this.parse_identifier());
+            let key = this.parse_identifier()?;
             if is_default {
                 default = Some(key.clone());
             }
@@ -433,12 +519,12 @@ impl<T: Iterator<Item=char>> Parser<T> {
             }
 
             this.parse_whitespace();
-            let value = try!(this.parse_value());
+            let value = this.parse_value()?;
             this.parse_whitespace();
 
             map.insert(key, value);
             Ok(())
-        }));
+        })?;
 
         self.bump();
         self.parse_whitespace();
@@ -447,9 +533,9 @@ impl<T: Iterator<Item=char>> Parser<T> {
     }
 
     fn parse_list<F>(&mut self, end: char, err: ParseErrorKind, mut handle: F) -> Result<()>
-    where F: FnMut(&mut Parser<T>) -> Result<()> {
+    where F: FnMut(&mut Parser<'a>) -> Result<()> {
         loop {
-            try!(handle(self));
+            handle(self)?;
 
             match self.ch {
                 Some(',') => {
@@ -463,12 +549,12 @@ impl<T: Iterator<Item=char>> Parser<T> {
         Ok(())
     }
 
-    fn parse_expression(&mut self) -> Result<Expr> {
+    fn parse_expression(&mut self) -> Result<Expr<'a>> {
         self.parse_cond_expression()
     }
 
-    fn parse_cond_expression(&mut self) -> Result<Expr> {
-        let cond = try!(self.parse_or_expression());
+    fn parse_cond_expression(&mut self) -> Result<Expr<'a>> {
+        let cond = self.parse_or_expression()?;
         self.parse_whitespace();
 
         match self.ch {
@@ -477,49 +563,42 @@ impl<T: Iterator<Item=char>> Parser<T> {
         }
 
         self.parse_whitespace();
-        let consequent = try!(self.parse_expression());
+        let consequent = self.parse_expression()?;
         self.parse_whitespace();
         if !self.ch_is(':') {
             return Err(self.error(ExprError))
         }
         self.bump();
         self.parse_whitespace();
-        let alternate = try!(self.parse_expression());
+        let alternate = self.parse_expression()?;
 
         Ok(CondExpr(Box::new(cond), Box::new(consequent), Box::new(alternate)))
     }
 
-    fn parse_prefix_expression<F>(&mut self, ops: &[BinOp], mut next: F) -> Result<Expr>
-    where F: FnMut(&mut Parser<T>) -> Result<Expr> {
-        let mut exp = try!(next(self));
+    /// Parses a chain of binary operators that bind at least as tightly as
+    /// `min`, grouping operators of the same precedence from the left.
+    fn parse_binary_expression(&mut self, min: u8) -> Result<Expr<'a>> {
+        let mut exp = self.parse_unary_expression()?;
         loop {
             self.parse_whitespace();
-            let mut binop = None;
-            for op in ops.iter() {
-                let mut chars = self.peek_bin_op(op);
-                if chars > 0 {
-                    binop = Some(*op);
-                    while chars > 0 {
-                        self.bump();
-                        chars -= 1;
-                    }
-                    break;
-                }
-            }
-            let binop = match binop {
+            let (binop, chars) = match self.peek_bin_op() {
+                Some((op, _)) if precedence(op) < min => break,
+                Some(op) => op,
                 None => break,
-                Some(op) => op
             };
+            for _ in 0..chars {
+                self.bump();
+            }
 
             self.parse_whitespace();
-            let right = try!(next(self));
+            let right = self.parse_binary_expression(precedence(binop) + 1)?;
             exp = BinExpr(Box::new(exp), binop, Box::new(right));
         }
         Ok(exp)
     }
 
-    fn parse_postfix_expression<F>(&mut self, ops: &[UnOp], mut next: F) -> Result<Expr>
-    where F: FnMut(&mut Parser<T>) -> Result<Expr> {
+    fn parse_postfix_expression<F>(&mut self, ops: &[UnOp], mut next: F) -> Result<Expr<'a>>
+    where F: FnMut(&mut Parser<'a>) -> Result<Expr<'a>> {
         let mut unop = None;
         for op in ops.iter() {
             if self.peek_un_op(op) {
@@ -535,93 +614,69 @@ impl<T: Iterator<Item=char>> Parser<T> {
         self.bump();
         self.parse_whitespace();
 
-        Ok(UnExpr(unop, Box::new(try!(self.parse_postfix_expression(ops, next)))))
+        Ok(UnExpr(unop, Box::new(self.parse_postfix_expression(ops, next)?)))
     }
 
-    fn peek_bin_op(&mut self, op: &BinOp) -> usize {
+    /// The binary operator at the current character, and how many
+    /// characters it takes up.
+    fn peek_bin_op(&self) -> Option<(BinOp, usize)> {
         let (c1, c2) = match (self.ch, self.peek()) {
             (Some(c1), Some(c2)) => (c1, c2),
-            _ => return 0
+            _ => return None
         };
 
-        match (*op, c1, c2) {
-            (BiAnd, '&', '&') |
-            (BiOr, '|', '|')    |
-            (BiEq, '=', '=')    |
-            (BiNe, '!', '=')    |
-            (BiGe, '>', '=')    |
-            (BiLe, '<', '=') => 2,
-            (BiGt, '>', _)        |
-            (BiLt, '<', _)        |
-            (BiAdd, '+', _)     |
-            (BiSub, '-', _)     |
-            (BiMul, '*', _)     |
-            (BiDiv, '/', _)     |
-            (BiRem, '%', _)    => 1,
-            _ => 0
-        }
+        Some(match (c1, c2) {
+            ('&', '&') => (BiAnd, 2),
+            ('|', '|') => (BiOr, 2),
+            ('=', '=') => (BiEq, 2),
+            ('!', '=') => (BiNe, 2),
+            ('>', '=') => (BiGe, 2),
+            ('<', '=') => (BiLe, 2),
+            ('>', _) => (BiGt, 1),
+            ('<', _) => (BiLt, 1),
+            ('+', _) => (BiAdd, 1),
+            ('-', _) => (BiSub, 1),
+            ('*', _) => (BiMul, 1),
+            ('/', _) => (BiDiv, 1),
+            ('%', _) => (BiRem, 1),
+            _ => return None
+        })
     }
 
     fn peek_un_op(&self, op: &UnOp) -> bool {
-        match (self.ch, *op) {
+        matches!((self.ch, *op),
             (Some('+'), UnAdd) |
             (Some('-'), UnSub) |
-            (Some('!'), UnNot) => true,
-            _ => false
-        }
-    }
-
-
-    fn parse_or_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiOr], |this| this.parse_and_expression())
-    }
-
-    fn parse_and_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiAnd], |this| this.parse_eq_expression())
+            (Some('!'), UnNot))
     }
 
-    fn parse_eq_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiEq, BiNe], |this| this.parse_rel_expression())
-    }
-
-    fn parse_rel_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiGe, BiGt, BiLe, BiLt], |this| this.parse_add_expression())
-    }
 
-    fn parse_add_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiAdd, BiSub], |this| this.parse_rem_expression())
+    fn parse_or_expression(&mut self) -> Result<Expr<'a>> {
+        self.parse_binary_expression(0)
     }
 
-    fn parse_rem_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiRem], |this| this.parse_mul_expression())
-    }
-
-    fn parse_mul_expression(&mut self) -> Result<Expr> {
-        self.parse_prefix_expression(&[BiMul, BiDiv], |this| this.parse_unary_expression())
-    }
-
-    fn parse_unary_expression(&mut self) -> Result<Expr> {
+    fn parse_unary_expression(&mut self) -> Result<Expr<'a>> {
         self.parse_postfix_expression(&[UnAdd, UnSub, UnNot], |this| this.parse_member_expression())
     }
 
-    fn parse_member_expression(&mut self) -> Result<Expr> {
-        let mut exp = try!(self.parse_paren_expression());
+    fn parse_member_expression(&mut self) -> Result<Expr<'a>> {
+        let mut exp = self.parse_paren_expression()?;
 
         loop {
             match self.ch {
                 Some('.') | Some('[') => {
-                    exp = try!(self.parse_property_expression(exp));
+                    exp = self.parse_property_expression(exp)?;
                 },
                 Some(':') => {
                     if self.peek() == Some(':') {
                         self.bump();
                         self.bump();
-                        exp = try!(self.parse_attr_expression(exp));
+                        exp = self.parse_attr_expression(exp)?;
                     } else {
                         break;
                     }
                 },
-                Some('(') => exp = try!(self.parse_call_expression(exp)),
+                Some('(') => exp = self.parse_call_expression(exp)?,
                 _ => break
             }
         }
@@ -629,12 +684,12 @@ impl<T: Iterator<Item=char>> Parser<T> {
         Ok(exp)
     }
 
-    fn parse_property_expression(&mut self, accessed: Expr) -> Result<Expr> {
+    fn parse_property_expression(&mut self, accessed: Expr<'a>) -> Result<Expr<'a>> {
         let computed = self.ch_is('[');
         self.bump();
         if computed {
             self.parse_whitespace();
-            let exp = try!(self.parse_expression());
+            let exp = self.parse_expression()?;
             self.parse_whitespace();
             if !self.ch_is(']') {
                 return Err(self.error(ExprError));
@@ -642,12 +697,12 @@ impl<T: Iterator<Item=char>> Parser<T> {
             self.bump();
             Ok(PropExpr(Box::new(accessed), Box::new(exp), Computed))
         } else {
-            let exp = try!(self.parse_identifier());
+            let exp = self.parse_identifier()?;
             Ok(PropExpr(Box::new(accessed), Box::new(IdentExpr(exp)), Static))
         }
     }
 
-    fn parse_attr_expression(&mut self, accessed: Expr) -> Result<Expr> {
+    fn parse_attr_expression(&mut self, accessed: Expr<'a>) -> Result<Expr<'a>> {
         match accessed {
             ParenExpr(..) | IdentExpr(..) | ThisExpr => {},
             _ => return Err(self.error(AttrError))
@@ -657,7 +712,7 @@ impl<T: Iterator<Item=char>> Parser<T> {
         if computed {
             self.bump();
             self.parse_whitespace();
-            let exp = try!(self.parse_expression());
+            let exp = self.parse_expression()?;
             self.parse_whitespace();
             if !self.ch_is(']') {
                 return Err(self.error(ExprError))
@@ -666,31 +721,31 @@ impl<T: Iterator<Item=char>> Parser<T> {
 
             Ok(AttrExpr(Box::new(accessed), Box::new(exp), Computed))
         } else {
-            Ok(AttrExpr(Box::new(accessed), Box::new(try!(self.parse_expression())), Static))
+            Ok(AttrExpr(Box::new(accessed), Box::new(self.parse_expression()?), Static))
         }
     }
 
-    fn parse_call_expression(&mut self, callee: Expr) -> Result<Expr> {
+    fn parse_call_expression(&mut self, callee: Expr<'a>) -> Result<Expr<'a>> {
         self.bump(); // (
         let mut args = vec![];
 
-        try!(self.parse_list(')', CallError, |this| {
-            args.push(try!(this.parse_expression()));
+        self.parse_list(')', CallError, |this| {
+            args.push(this.parse_expression()?);
             Ok(())
-        }));
+        })?;
         self.bump(); // )
 
         Ok(CallExpr(Box::new(callee), args))
     }
 
-    fn parse_paren_expression(&mut self) -> Result<Expr> {
+    fn parse_paren_expression(&mut self) -> Result<Expr<'a>> {
         match self.ch {
             Some('(') => self.bump(),
             _ => return self.parse_primary_expression()
         }
 
         self.parse_whitespace();
-        let exp = try!(self.parse_expression());
+        let exp = self.parse_expression()?;
         self.parse_whitespace();
 
         match self.ch {
@@ -701,98 +756,97 @@ impl<T: Iterator<Item=char>> Parser<T> {
         Ok(ParenExpr(Box::new(exp)))
     }
 
-    fn parse_primary_expression(&mut self) -> Result<Expr> {
+    fn parse_primary_expression(&mut self) -> Result<Expr<'a>> {
         match self.ch {
             Some(c) => {
                 match c {
-                    '0'...'9' => self.parse_number(),
-                    '\'' | '"' | '{' | '[' => Ok(ValExpr(try!(self.parse_value()))),
+                    '0'..='9' => self.parse_number(),
+                    '\'' | '"' | '{' | '[' => Ok(ValExpr(self.parse_value()?)),
                     '$' => self.parse_variable(),
                     '@' => {
                         self.bump();
-                        Ok(GlobalExpr(try!(self.parse_identifier())))
+                        Ok(GlobalExpr(self.parse_identifier()?))
                     },
                     '~' => {
                         self.bump();
                         Ok(ThisExpr)
                     },
-                    _ => Ok(IdentExpr(try!(self.parse_identifier())))
+                    _ => Ok(IdentExpr(self.parse_identifier()?))
                 }
             },
             None => Err(self.error(ExprError))
         }
     }
 
-    fn parse_number(&mut self) -> Result<Expr> {
-        let mut num = String::new();
-        loop {
-            match self.ch {
-                Some(ch) => match ch {
-                    '0'...'9' => {
-                        num.push(ch);
-                        self.bump();
-                    },
-                    _ => break
-                },
-                _ => break,
-            }
+    fn parse_number(&mut self) -> Result<Expr<'a>> {
+        let start = self.pos;
+        let (line, col) = (self.line, self.col);
+        while let Some('0'..='9') = self.ch {
+            self.bump();
         }
+        let num = &self.source[start..self.pos];
 
-        if num.len() > 0 {
-            Ok(NumExpr(num.parse().unwrap()))
-        } else {
-            Err(self.error(ExprError))
+        // fails if there are no digits, or too many for an i64, which is
+        // reported where the number starts
+        match num.parse() {
+            Ok(n) => Ok(NumExpr(n)),
+            Err(_) => Err(ParseError { kind: ExprError, line, col }),
         }
     }
 
-    fn parse_variable(&mut self) -> Result<Expr> {
+    fn parse_variable(&mut self) -> Result<Expr<'a>> {
         if !self.ch_is('$') {
             return Err(self.error(VarError));
         }
         self.bump();
-        Ok(VarExpr(try!(self.parse_identifier())))
+        Ok(VarExpr(self.parse_identifier()?))
     }
 
-    fn parse_identifier(&mut self) -> Result<String> {
-        let mut id = String::new();
+    fn parse_identifier(&mut self) -> Result<Cow<'a, str>> {
+        let start = self.pos;
         // identifiers must start with a-zA-Z_
         match self.ch {
-            Some(c) => match c {
-                'a'...'z' | 'A'...'Z' | '_' => id.push(c),
-                _ => return Err(self.error(IdentifierError))
-            },
-            None => return Err(self.error(IdentifierError))
+            Some('a'..='z') | Some('A'..='Z') | Some('_') => {},
+            _ => return Err(self.error(IdentifierError))
         }
 
-        loop {
-            self.bump();
-            let ch = match self.ch {
-                Some(c) => c,
-                None => return Err(self.error(IdentifierError))
-            };
-
-            match ch {
-                'a'...'z' | 'A'...'Z' | '0'...'9' | '_' => id.push(ch),
-                _ => break,
-            }
+        self.skip_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+        self.bump();
+        if self.ch.is_none() {
+            return Err(self.error(IdentifierError));
         }
-        Ok(id)
+        Ok(self.slice(start, None))
+    }
+}
+
+/// How tightly a binary operator binds, from `||` (loosest) to `*` and `/`.
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BiOr => 0,
+        BiAnd => 1,
+        BiEq | BiNe => 2,
+        BiGe | BiGt | BiLe | BiLt => 3,
+        BiAdd | BiSub => 4,
+        BiRem => 5,
+        BiMul | BiDiv => 6,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Parser, Entity, Str, Hash, Attr, VarExpr, Macro, CondExpr,
-                            BinExpr, ValExpr, ComplexStr, NumExpr, BiGt, BiGe, Comment};
+    use super::{Parser, Entry, Entity, Str, Hash, Attr, VarExpr, Macro, CondExpr,
+                            BinExpr, ValExpr, ComplexStr, NumExpr, BiGt, BiGe, BiSub, BiMul,
+                            BiRem, Comment, Import, ImportError, ExprError, Span};
+    use std::borrow::Cow;
     use std::collections::HashMap;
 
-    fn s(v: &'static str) -> String {
-        String::from(v)
+    fn s(v: &'static str) -> Cow<'static, str> {
+        Cow::Borrowed(v)
     }
 
     #[test]
     fn test_basic_entity() {
-        let p = Parser::new("<hello \"Hello, World\" >".chars());
+        let p = Parser::new("<hello \"Hello, World\" >");
         assert_eq!(p.parse().unwrap(), vec![
                              Entity(s("hello"), Str(s("Hello, World")), vec![], vec![])
         ]);
@@ -800,7 +854,7 @@ mod tests {
 
     #[test]
     fn test_multiple_entities() {
-        let p = Parser::new("<hell0 \"Hello, World\">\n<bye 'Bye!'>".chars());
+        let p = Parser::new("<hell0 \"Hello, World\">\n<bye 'Bye!'>");
         assert_eq!(p.parse().unwrap(), vec![
                              Entity(s("hell0"), Str(s("Hello, World")), vec![], vec![]),
                              Entity(s("bye"), Str(s("Bye!")), vec![], vec![])
@@ -809,7 +863,7 @@ mod tests {
 
     #[test]
     fn test_macro() {
-        let p = Parser::new("<foo($n) { $n > 1 ? 'foo' : 'bar' }>".chars());
+        let p = Parser::new("<foo($n) { $n > 1 ? 'foo' : 'bar' }>");
         assert_eq!(p.parse().unwrap(), vec![
                              Macro(s("foo"),
                                          vec![VarExpr(s("n"))],
@@ -823,7 +877,7 @@ mod tests {
 
     #[test]
     fn test_ge() {
-        let p = Parser::new("<foo($n) { $n >= 1 ? 'foo' : 'bar' }>".chars());
+        let p = Parser::new("<foo($n) { $n >= 1 ? 'foo' : 'bar' }>");
         assert_eq!(p.parse().unwrap(), vec![
                              Macro(s("foo"),
                                          vec![VarExpr(s("n"))],
@@ -835,9 +889,24 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn test_number() {
+        let p = Parser::new("<max '{{ 9223372036854775807 }}'>");
+        assert_eq!(p.parse().unwrap(), vec![
+                             Entity(s("max"), ComplexStr(vec![
+                                 ValExpr(Str(s(""))),
+                                 NumExpr(i64::MAX),
+                             ]), vec![], vec![])
+        ]);
+
+        // too big for an i64
+        let err = Parser::new("<big '{{ 99999999999999999999 }}'>").parse().unwrap_err();
+        assert_eq!((err.kind, err.line, err.col), (ExprError, 0, 10));
+    }
+
     #[test]
     fn test_hash() {
-        let p = Parser::new("<pro { masculine: 'his', feminine: 'her'}>".chars());
+        let p = Parser::new("<pro { masculine: 'his', feminine: 'her'}>");
         let mut map = HashMap::new();
         map.insert(s("masculine"), Str(s("his")));
         map.insert(s("feminine"), Str(s("her")));
@@ -848,7 +917,7 @@ mod tests {
 
     #[test]
     fn test_hash_default() {
-        let p = Parser::new("<pro { *masculine: 'his', feminine: 'her'}>".chars());
+        let p = Parser::new("<pro { *masculine: 'his', feminine: 'her'}>");
         let mut map = HashMap::new();
         map.insert(s("masculine"), Str(s("his")));
         map.insert(s("feminine"), Str(s("her")));
@@ -859,7 +928,7 @@ mod tests {
 
     #[test]
     fn test_hash_index() {
-        let p = Parser::new("<pro['feminine'] { masculine: 'his', feminine: 'her'}>".chars());
+        let p = Parser::new("<pro['feminine'] { masculine: 'his', feminine: 'her'}>");
         let mut map = HashMap::new();
         map.insert(s("masculine"), Str(s("his")));
         map.insert(s("feminine"), Str(s("her")));
@@ -870,15 +939,39 @@ mod tests {
 
     #[test]
     fn test_attr() {
-        let p = Parser::new("<pro 'her' neuter: 'their'>".chars());
+        let p = Parser::new("<pro 'her' neuter: 'their'>");
         assert_eq!(p.parse().unwrap(), vec![
                              Entity(s("pro"), Str(s("her")), vec![], vec![Attr(s("neuter"), Str(s("their")), vec![])])
         ]);
     }
 
+    #[test]
+    fn test_attrs() {
+        let p = Parser::new("<pro 'her' neuter: 'their' plural[$n]: {*one: 'x'}\n  other: 'y' >");
+        let mut map = HashMap::new();
+        map.insert(s("one"), Str(s("x")));
+        assert_eq!(p.parse().unwrap(), vec![
+                             Entity(s("pro"), Str(s("her")), vec![], vec![
+                                 Attr(s("neuter"), Str(s("their")), vec![]),
+                                 Attr(s("plural"), Hash(map, Some(s("one")), None), vec![VarExpr(s("n"))]),
+                                 Attr(s("other"), Str(s("y")), vec![]),
+                             ])
+        ]);
+    }
+
+    #[test]
+    fn test_escapes() {
+        let p = Parser::new(r#"<hi "Say \"hi\" \{{ $x }} \\" title: '\'\\'>"#);
+        assert_eq!(p.parse().unwrap(), vec![
+                             Entity(s("hi"), Str(s("Say \"hi\" {{ $x }} \\")), vec![], vec![
+                                 Attr(s("title"), Str(s("'\\")), vec![])
+                             ])
+        ]);
+    }
+
     #[test]
     fn test_complex_str() {
-        let p = Parser::new("<hi 'Hello, {{ $name }}!'>".chars());
+        let p = Parser::new("<hi 'Hello, {{ $name }}!'>");
         assert_eq!(p.parse().unwrap(), vec![
                              Entity(s("hi"), ComplexStr(vec![
                                  ValExpr(Str(s("Hello, "))),
@@ -890,9 +983,64 @@ mod tests {
 
     #[test]
     fn test_comment() {
-        let p = Parser::new("/* foo bar */".chars());
+        let p = Parser::new("/* foo bar */");
         assert_eq!(p.parse().unwrap(), vec![Comment(s(" foo bar "))])
     }
 
-}
+    #[test]
+    fn test_import() {
+        let p = Parser::new("import('../brand.l20n')\nimport ( \"a b\" )<hi 'Hi'>");
+        let entries = p.parse().unwrap();
+        assert_eq!(entries[..2], [Import(s("../brand.l20n")), Import(s("a b"))]);
+
+        for bad in &["import", "imports('a')", "import('')", "import('a\")", "import('a'", "import(a)"] {
+            let err = Parser::new(bad).parse().unwrap_err();
+            assert_eq!(err.kind, ImportError, "{}", bad);
+        }
+    }
 
+    #[test]
+    fn test_spans() {
+        let p = Parser::new("<hi 'Hello'>\n  /* note */\n<bye 'Bye'>");
+        let spans: Vec<Span> = p.parse_spanned().unwrap().into_iter().map(|(_, span)| span).collect();
+        assert_eq!(spans, vec![
+            Span { line: 0, col: 1, end_line: 0, end_col: 12 },
+            Span { line: 1, col: 4, end_line: 1, end_col: 13 },
+            Span { line: 2, col: 2, end_line: 2, end_col: 12 },
+        ]);
+
+        // columns count characters, not bytes
+        let p = Parser::new("<hi 'Grüße'> <bye 'Tschüss'>");
+        let spans: Vec<Span> = p.parse_spanned().unwrap().into_iter().map(|(_, span)| span).collect();
+        assert_eq!(spans[1], Span { line: 0, col: 14, end_line: 0, end_col: 28 });
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let source = String::from("<hi 'Hello, {{ $name }}' path: 'C:\\dir'>\n/* über */");
+        let entries = Parser::new(&source).parse().unwrap();
+        match entries[0] {
+            Entity(Cow::Borrowed("hi"), ComplexStr(ref exprs), _, ref attrs) => {
+                assert!(matches!(exprs[0], ValExpr(Str(Cow::Borrowed("Hello, ")))));
+                assert!(matches!(exprs[1], VarExpr(Cow::Borrowed("name"))));
+                // a dropped `\` means the text had to be copied
+                assert!(matches!(attrs[0].1, Str(Cow::Owned(ref s)) if s == "C:dir"));
+            }
+            _ => panic!("hi should be borrowed from the source"),
+        }
+
+        let entries: Vec<Entry<'static>> = entries.into_iter().map(Entry::into_owned).collect();
+        drop(source);
+        assert_eq!(entries[1], Comment(s(" über ")));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let p = Parser::new("<m($a) { 1 - 2 - 3 * 4 % 5 }>");
+        let num = |n| Box::new(NumExpr(n));
+        let mul = BinExpr(num(3), BiMul, num(4));
+        let rem = BinExpr(Box::new(mul), BiRem, num(5));
+        let sub = BinExpr(Box::new(BinExpr(num(1), BiSub, num(2))), BiSub, Box::new(rem));
+        assert_eq!(p.parse().unwrap(), vec![Macro(s("m"), vec![VarExpr(s("a"))], sub)]);
+    }
+}
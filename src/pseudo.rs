@@ -0,0 +1,397 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use ast::*;
+use context::{Locale, LocalizeResult, ResourceError};
+use parser::ParseError;
+use visit::{walk_expr, walk_value_mut, Visit, VisitMut};
+
+const ACCENTED_UPPER: &str = "ȦƁƇḒḖƑƓĦĪĴĶĿḾȠǾƤɊŘŞŦŬṼẆẊẎẐ";
+const ACCENTED_LOWER: &str = "ȧƀƈḓḗƒɠħīĵķŀḿƞǿƥɋřşŧŭṽẇẋẏẑ";
+const FLIPPED_UPPER: &str = "∀ԐↃᗡƎℲ⅁HIſӼ⅂WNOԀÒᴚS⊥∩ɅMX⅄Z";
+const FLIPPED_LOWER: &str = "ɐqɔpǝɟƃɥıɾʞʅɯuodbɹsʇnʌʍxʎz";
+
+// right-to-left override, and the pop that ends it
+const RLO: char = '\u{202e}';
+const PDF: char = '\u{202c}';
+
+/// How to pseudo-localize: to make text that is still readable, but looks
+/// translated, so that strings left hard-coded stand out, and that is longer
+/// than the source, as translations often are, so that truncation shows.
+///
+/// Only the literal text of values is changed. Placeables (`{{ }}`), Hash
+/// keys and indices, and macros are left as they are, so a pseudo-localized
+/// resource localizes with the same data as the source. So are the values
+/// that indices refer to, such as `brand::gender` in
+/// `<his[brand::gender] {...}>`, so that they still match the keys.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// let entries = l20n::parse("<hi \"Hello, {{ $name }}\">").unwrap();
+/// let pseudo = l20n::Pseudo::default().transform(&entries);
+/// assert_eq!(l20n::serialize(&pseudo), "<hi \"[Ħḗŀŀǿ, {{ $name }}~~~]\">\n");
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Pseudo {
+    /// Replaces ASCII letters with accented look-alikes: `Ħḗŀŀǿ`.
+    pub accents: bool,
+    /// Pads each string with `~` by this much of its length: `0.3` makes a
+    /// string of 10 letters 3 longer. Placeables do not count.
+    pub expansion: f64,
+    /// Marks the start and end of each string with `[` and `]`, so that one
+    /// that was cut short, or put together from several, can be told apart.
+    pub brackets: bool,
+    /// Makes text right-to-left: letters are replaced with upside-down
+    /// look-alikes instead of accented ones, and each run of text is wrapped
+    /// in a right-to-left override, to test a layout for languages like
+    /// Arabic.
+    pub rtl: bool,
+}
+
+impl Default for Pseudo {
+    /// Accents, 30% expansion and brackets, left to right.
+    fn default() -> Pseudo {
+        Pseudo { accents: true, expansion: 0.3, brackets: true, rtl: false }
+    }
+}
+
+impl Pseudo {
+    /// Pseudo-localizes entries into a new resource.
+    pub fn transform(&self, entries: &[Entry]) -> Vec<Entry<'static>> {
+        let indices = Indices::of(entries);
+        entries.iter().map(|entry| {
+            let mut entry = entry.clone().into_owned();
+            self.apply(&mut entry, &indices);
+            entry
+        }).collect()
+    }
+
+    /// Pseudo-localizes an entry in place, but for the values in `indices`.
+    fn apply(&self, entry: &mut Entry, indices: &HashSet<(String, Option<String>)>) {
+        Transform { pseudo: self, indices }.visit_entry_mut(entry);
+    }
+
+    fn letters(&self, text: &str) -> String {
+        let (upper, lower) = match (self.rtl, self.accents) {
+            (true, _) => (FLIPPED_UPPER, FLIPPED_LOWER),
+            (false, true) => (ACCENTED_UPPER, ACCENTED_LOWER),
+            (false, false) => return text.to_string(),
+        };
+        text.chars().map(|c| match c {
+            'A'..='Z' => upper.chars().nth(c as usize - 'A' as usize).unwrap(),
+            'a'..='z' => lower.chars().nth(c as usize - 'a' as usize).unwrap(),
+            c => c,
+        }).collect()
+    }
+
+    /// A run of text between placeables.
+    fn run(&self, text: &str) -> String {
+        let letters = self.letters(text);
+        if self.rtl && !text.is_empty() {
+            format!("{}{}{}", RLO, letters, PDF)
+        } else {
+            letters
+        }
+    }
+
+    /// The marks that go around a string of `len` characters.
+    fn ends(&self, len: usize) -> (&'static str, String) {
+        let padding = "~".repeat((len as f64 * self.expansion).ceil() as usize);
+        if self.brackets {
+            ("[", padding + "]")
+        } else {
+            ("", padding)
+        }
+    }
+}
+
+/// The entity values, by id, and attribute values, by id and name, that an
+/// index picks a Hash value with, directly or through the entities and
+/// macros it refers to.
+struct Indices<'e> {
+    entries: HashMap<&'e str, &'e Entry<'e>>,
+    found: HashSet<(String, Option<String>)>,
+    pending: Vec<(String, Option<String>)>,
+}
+
+impl<'e> Indices<'e> {
+    fn of<I: IntoIterator<Item = &'e Entry<'e>>>(entries: I) -> HashSet<(String, Option<String>)> {
+        let entries: Vec<_> = entries.into_iter().collect();
+        let mut indices = Indices { entries: HashMap::new(), found: HashSet::new(), pending: vec![] };
+        for &entry in &entries {
+            if let Entity(ref id, ..) | Macro(ref id, ..) = *entry {
+                indices.entries.insert(id, entry);
+            }
+        }
+        for entry in entries {
+            if let Entity(_, ref value, ref exprs, ref attrs) = *entry {
+                for expr in exprs {
+                    indices.visit_expr(expr);
+                }
+                indices.hash_indices(value);
+                for Attr(_, value, exprs) in attrs {
+                    for expr in exprs {
+                        indices.visit_expr(expr);
+                    }
+                    indices.hash_indices(value);
+                }
+            }
+        }
+
+        // what a value used as an index is made of is used as one too
+        while let Some((id, attr)) = indices.pending.pop() {
+            match (indices.entries.get(&id[..]).cloned(), attr) {
+                (Some(Entity(_, value, ..)), None) => indices.visit_value(value),
+                (Some(Entity(.., attrs)), Some(name)) => {
+                    for attr in attrs.iter().filter(|attr| attr.0 == name) {
+                        indices.visit_value(&attr.1);
+                    }
+                }
+                (Some(Macro(_, _, body)), _) => indices.visit_expr(body),
+                _ => {}
+            }
+        }
+        indices.found
+    }
+
+    /// Visits the default indices of the Hashes in a value.
+    fn hash_indices(&mut self, value: &Value) {
+        if let Hash(ref map, _, ref index) = *value {
+            if let Some(ref index) = *index {
+                self.visit_expr(index);
+            }
+            for value in map.values() {
+                self.hash_indices(value);
+            }
+        }
+    }
+
+    fn insert(&mut self, id: &str, attr: Option<&str>) {
+        let key = (id.to_string(), attr.map(str::to_string));
+        if self.found.insert(key.clone()) {
+            self.pending.push(key);
+        }
+    }
+}
+
+impl<'e, 'ast> Visit<'ast> for Indices<'e> {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        match *expr {
+            AttrExpr(ref parent, ref name, access) => {
+                match (&**parent, access, &**name) {
+                    (IdentExpr(id), Static, IdentExpr(name)) => self.insert(id, Some(name)),
+                    (IdentExpr(id), Computed, _) => {
+                        // any of them could be the one
+                        let names: Vec<String> = match self.entries.get(&id[..]) {
+                            Some(Entity(.., attrs)) => attrs.iter().map(|attr| attr.0.to_string()).collect(),
+                            _ => vec![],
+                        };
+                        for attr in names {
+                            self.insert(id, Some(&attr));
+                        }
+                        self.visit_expr(name);
+                    }
+                    _ => walk_expr(self, expr),
+                }
+            }
+            IdentExpr(ref id) => self.insert(id, None),
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+/// Rewrites the strings of entity and attribute values, leaving everything
+/// that is an expression alone, as well as the values in `indices`.
+struct Transform<'p> {
+    pseudo: &'p Pseudo,
+    indices: &'p HashSet<(String, Option<String>)>,
+}
+
+impl<'p> VisitMut for Transform<'p> {
+    fn visit_entry_mut(&mut self, entry: &mut Entry) {
+        if let Entity(ref id, ref mut value, _, ref mut attrs) = *entry {
+            if !self.indices.contains(&(id.to_string(), None)) {
+                self.visit_value_mut(value);
+            }
+            for Attr(name, value, _) in attrs {
+                if !self.indices.contains(&(id.to_string(), Some(name.to_string()))) {
+                    self.visit_value_mut(value);
+                }
+            }
+        }
+    }
+
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        let pseudo = self.pseudo;
+        match *value {
+            Str(ref mut s) => {
+                let (start, end) = pseudo.ends(s.chars().count());
+                *s = Cow::Owned(format!("{}{}{}", start, pseudo.run(s), end));
+            }
+            ComplexStr(ref mut pieces) => {
+                // text and placeables take turns, starting with text
+                let mut len = 0;
+                for piece in pieces.iter_mut().step_by(2) {
+                    if let ValExpr(Str(ref mut s)) = *piece {
+                        len += s.chars().count();
+                        *s = Cow::Owned(pseudo.run(s));
+                    }
+                }
+                if pieces.len() % 2 == 0 {
+                    pieces.push(ValExpr(Str(Cow::Borrowed(""))));
+                }
+                let (start, end) = pseudo.ends(len);
+                if let Some(ValExpr(Str(ref mut s))) = pieces.first_mut() {
+                    s.to_mut().insert_str(0, start);
+                }
+                if let Some(ValExpr(Str(ref mut s))) = pieces.last_mut() {
+                    s.to_mut().push_str(&end);
+                }
+            }
+            Hash(..) => walk_value_mut(self, value),
+        }
+    }
+
+    fn visit_expr_mut(&mut self, _expr: &mut Expr) {}
+}
+
+/// A `Locale` that localizes into pseudo-localized text, for trying an
+/// application out before there are translations.
+///
+/// Resources are added as they are, and pseudo-localized along with the
+/// rest, so the same resources as the real locale can be used.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// let mut locale = l20n::PseudoLocale::new(l20n::Locale::new(), l20n::Pseudo {
+///     expansion: 0.0,
+///     ..Default::default()
+/// });
+/// locale.add_resource("<hi \"Hi, {{ $name }}\">").unwrap();
+///
+/// let mut data = std::collections::HashMap::new();
+/// data.insert("name", "Rust");
+/// assert_eq!(locale.localize_entity("hi", &data).unwrap(), "[Ħī, Rust]");
+/// ```
+#[derive(Clone)]
+pub struct PseudoLocale {
+    source: Locale,
+    pseudo: Locale,
+    options: Pseudo,
+}
+
+impl PseudoLocale {
+    /// Pseudo-localizes the resources of a Locale, and any added later.
+    pub fn new(source: Locale, options: Pseudo) -> PseudoLocale {
+        let mut locale = PseudoLocale { pseudo: source.clone(), source, options };
+        locale.update();
+        locale
+    }
+
+    /// Adds an L20n string resource, as `Locale::add_resource` does.
+    pub fn add_resource(&mut self, res: &str) -> Result<(), ParseError> {
+        self.source.add_resource(res)?;
+        self.update();
+        Ok(())
+    }
+
+    /// Adds an L20n string resource, as `Locale::try_add_resource` does.
+    pub fn try_add_resource(&mut self, res: &str) -> Result<(), ResourceError> {
+        self.source.try_add_resource(res)?;
+        self.update();
+        Ok(())
+    }
+
+    fn update(&mut self) {
+        let indices = self.source.with_definitions(|definitions| {
+            Indices::of(definitions.iter().map(|&(_, entry)| entry))
+        });
+        let mut pseudo = self.source.clone();
+        pseudo.rewrite(|entry| self.options.apply(entry, &indices));
+        self.pseudo = pseudo;
+    }
+
+    /// The pseudo-localized Locale, to localize with.
+    pub fn locale(&self) -> &Locale {
+        &self.pseudo
+    }
+
+    /// Resolves a single entity into pseudo-localized text, as
+    /// `Locale::localize_entity` does.
+    pub fn localize_entity<D: ::serde::Serialize>(&self, id: &str, data: D) -> LocalizeResult<String> {
+        self.pseudo.localize_entity(id, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use context::Locale;
+    use parser::parse;
+    use serializer::serialize;
+    use super::{Pseudo, PseudoLocale};
+
+    #[test]
+    fn test_transform() {
+        let entries = parse("\
+<plural($n) { $n == 1 ? \"one\" : \"other\" }>
+<brand \"Mail\">
+<hi \"Hi {{ $name }} from {{ brand }}\" title: '{{ \"x\" }}'>
+<emails[plural($n)] { one: \"One\", *other: \"{{ $n }} emails\" }>
+").unwrap();
+        let pseudo = Pseudo::default().transform(&entries);
+        assert_eq!(serialize(&pseudo), "\
+<plural($n) { $n == 1 ? \"one\" : \"other\" }>
+<brand \"[Ḿȧīŀ~~]\">
+<hi \"[Ħī {{ $name }} ƒřǿḿ {{ brand }}~~~]\" title: \"[{{ \"x\" }}]\">
+<emails[plural($n)] { one: \"[Ǿƞḗ~]\", *other: \"[{{ $n }} ḗḿȧīŀş~~~]\" }>
+");
+
+        let plain = Pseudo { accents: false, expansion: 1.0, brackets: false, rtl: false };
+        assert_eq!(serialize(&plain.transform(&parse("<a \"ab\">").unwrap())), "<a \"ab~~\">\n");
+
+        let rtl = Pseudo { rtl: true, expansion: 0.0, ..Default::default() };
+        assert_eq!(serialize(&rtl.transform(&parse("<a \"Ab {{ $x }}\">").unwrap())),
+                   "<a \"[\u{202e}∀q \u{202c}{{ $x }}]\">\n");
+    }
+
+    #[test]
+    fn test_pseudo_locale() {
+        let mut source = Locale::new();
+        source.add_resource("<plural($n) { $n == 1 ? \"one\" : \"other\" }>").unwrap();
+        let mut locale = PseudoLocale::new(source, Pseudo::default());
+        locale.add_resource("<emails[plural($n)] { one: \"One\", *other: \"{{ $n }} emails\" }>").unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("n", 1);
+        assert_eq!(locale.localize_entity("emails", &data).unwrap(), "[Ǿƞḗ~]");
+        data.insert("n", 12);
+        assert_eq!(locale.localize_entity("emails", &data).unwrap(), "[12 ḗḿȧīŀş~~~]");
+        // resources added later are not pseudo-localized twice
+        locale.add_resource("<a \"a\">").unwrap();
+        assert_eq!(locale.localize_entity("emails", &data).unwrap(), "[12 ḗḿȧīŀş~~~]");
+        assert_eq!(locale.locale().localize_entity("a", ()).unwrap(), "[ȧ~]");
+    }
+
+    #[test]
+    fn test_indices() {
+        // values used to pick a Hash value still match its keys
+        let source = "\
+<brand \"Firefox\" gender: \"masculine\">
+<hi[brand::gender] { masculine: \"his\", feminine: \"her\" }>
+<kind \"{{ tone }}\">
+<tone \"warm\">
+<greeting[kind] { warm: \"Hi\", *cold: \"Hello\" }>
+";
+        let pseudo = Pseudo::default().transform(&parse(source).unwrap());
+        assert_eq!(serialize(&pseudo[..1]), "<brand \"[Ƒīřḗƒǿẋ~~~]\" gender: \"masculine\">\n");
+        assert_eq!(serialize(&pseudo[2..4]), "<kind \"{{ tone }}\">\n<tone \"warm\">\n");
+
+        let mut locale = PseudoLocale::new(Locale::new(), Pseudo::default());
+        locale.add_resource(source).unwrap();
+        assert_eq!(locale.localize_entity("hi", ()).unwrap(), "[ħīş~]");
+        assert_eq!(locale.localize_entity("greeting", ()).unwrap(), "[Ħī~]");
+        assert_eq!(locale.localize_entity("brand", ()).unwrap(), "[Ƒīřḗƒǿẋ~~~]");
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use compiler;
+use context::Locale;
+use import::{self, FileLoader};
+use langtag::LanguageTag;
+use loader::{LoadError, Loader};
+use shared::SharedLocale;
+
+/// A `Locale` loaded from disk that picks up changes to its resources,
+/// for previewing translations without restarting.
+///
+/// Each `poll` checks every file for changes, recompiles the ones that
+/// changed, and swaps in a new `Locale` with them. A file that no longer
+/// compiles keeps its previous version until it is fixed. Readers get the
+/// `Locale` as it was when they asked, even if a reload happens meanwhile.
+///
+/// ```rust,no_run
+/// # #![allow(deprecated)]
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let loader = l20n::Loader::new("locales", "{locale}/{file}.l20n").unwrap();
+/// let locale = Arc::new(l20n::ReloadingLocale::new(loader, "en-US").unwrap());
+/// l20n::ReloadingLocale::watch(&locale, Duration::from_secs(1), |e| {
+///     eprintln!("keeping the previous version: {:?}", e);
+/// });
+///
+/// let hi = locale.locale().localize_entity("hi", ()).unwrap();
+/// ```
+pub struct ReloadingLocale {
+    loader: Loader,
+    name: String,
+    /// What each version starts from, for its language and
+    /// `DuplicatePolicy`.
+    base: Locale,
+    current: SharedLocale,
+    files: Mutex<Vec<File>>,
+}
+
+struct File {
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    compiled: Option<compiler::Resource<'static>>,
+}
+
+impl ReloadingLocale {
+    /// Loads the resources of `locale`, all of which have to compile. The
+    /// `Locale` has the language of `locale`, as with `Loader::load`.
+    pub fn new(loader: Loader, locale: &str) -> Result<ReloadingLocale, LoadError> {
+        let mut base = Locale::new();
+        if let Ok(tag) = LanguageTag::parse(locale) {
+            base.set_language(tag);
+        }
+        ReloadingLocale::with_base(loader, locale, base)
+    }
+
+    /// Loads the resources of `locale` into copies of `base`, such as one
+    /// with a `DuplicatePolicy` set, as with `Loader::load_into`. Each
+    /// version starts again from `base`.
+    ///
+    /// Under `DuplicatePolicy::Error`, a change that defines an id twice is
+    /// reported, and the previous version is kept until the next change.
+    pub fn with_base(loader: Loader, locale: &str, base: Locale) -> Result<ReloadingLocale, LoadError> {
+        let mut files = vec![];
+        for path in loader.files(locale)? {
+            let stamp = stamp(&path).map_err(|e| LoadError::Io(path.clone(), e))?;
+            let compiled = compile(&path)?;
+            files.push(File {
+                path,
+                stamp: Some(stamp),
+                compiled: Some(compiled),
+            });
+        }
+        Ok(ReloadingLocale {
+            current: SharedLocale::new(build(&base, &files)?),
+            loader,
+            name: locale.to_string(),
+            base,
+            files: Mutex::new(files),
+        })
+    }
+
+    /// The current version of the resources.
+    pub fn locale(&self) -> Arc<Locale> {
+        self.current.snapshot()
+    }
+
+    /// Checks for files that were changed, added or removed, and swaps in a
+    /// new `Locale` if any were. The errors are for files that could not be
+    /// read or compiled, whose previous version is kept. They are reported
+    /// once per change.
+    pub fn poll(&self) -> Vec<LoadError> {
+        let mut files = self.files.lock().unwrap();
+        let paths = match self.loader.files(&self.name) {
+            Ok(paths) => paths,
+            // keep everything if the whole locale is gone, which is more
+            // likely a checkout in progress than a translation to drop
+            Err(e) => return vec![e],
+        };
+
+        let mut old: HashMap<PathBuf, File> = files.drain(..).map(|f| (f.path.clone(), f)).collect();
+        let mut changed = false;
+        let mut errors = vec![];
+        for path in paths {
+            let mut file = old.remove(&path).unwrap_or_else(|| File {
+                path: path.clone(),
+                stamp: None,
+                compiled: None,
+            });
+            match stamp(&path) {
+                Ok(stamp) if file.stamp != Some(stamp) => {
+                    file.stamp = Some(stamp);
+                    match compile(&path) {
+                        Ok(compiled) => {
+                            file.compiled = Some(compiled);
+                            changed = true;
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => errors.push(LoadError::Io(path, e)),
+            }
+            files.push(file);
+        }
+        changed |= old.values().any(|f| f.compiled.is_some());
+
+        if changed {
+            match build(&self.base, &files) {
+                Ok(locale) => self.current.publish(locale),
+                Err(e) => errors.push(e),
+            }
+        }
+        errors
+    }
+
+    /// Polls every `interval` on a background thread, passing any errors to
+    /// `on_error`. The thread stops once the `ReloadingLocale` is dropped.
+    pub fn watch<F>(this: &Arc<ReloadingLocale>, interval: Duration, mut on_error: F) -> thread::JoinHandle<()>
+        where F: FnMut(LoadError) + Send + 'static
+    {
+        let weak = Arc::downgrade(this);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let this = match weak.upgrade() {
+                Some(this) => this,
+                None => return,
+            };
+            for e in this.poll() {
+                on_error(e);
+            }
+        })
+    }
+}
+
+fn stamp(path: &Path) -> io::Result<(SystemTime, u64)> {
+    let meta = fs::metadata(path)?;
+    Ok((meta.modified()?, meta.len()))
+}
+
+/// Compiles a file along with what it imports. Imported files are only read
+/// again when the file that imports them changes, unless they are resources
+/// of the locale themselves.
+fn compile(path: &Path) -> Result<compiler::Resource<'static>, LoadError> {
+    let mut merged = compiler::Resource { definitions: vec![], comments: vec![], imports: vec![] };
+    for (name, source) in import::resolve(&FileLoader, &[path.to_string_lossy().into_owned()])? {
+        let compiled = compiler::compile(&source).map_err(|e| LoadError::Parse(PathBuf::from(name), e))?.into_owned();
+        merged.definitions.extend(compiled.definitions);
+        merged.comments.extend(compiled.comments);
+    }
+    Ok(merged)
+}
+
+fn build(base: &Locale, files: &[File]) -> Result<Locale, LoadError> {
+    let mut locale = base.clone();
+    for file in files {
+        if let Some(ref compiled) = file.compiled {
+            locale.add_compiled(compiled.clone())
+                .map_err(|dup| LoadError::Duplicate(file.path.clone(), Box::new(dup)))?;
+        }
+    }
+    Ok(locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use context::{DuplicatePolicy, Locale};
+    use langtag::LanguageTag;
+    use loader::{LoadError, Loader};
+    use super::ReloadingLocale;
+
+    fn dir(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("l20n-reload-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("en")).unwrap();
+        root
+    }
+
+    fn hi(locale: &ReloadingLocale) -> String {
+        locale.locale().localize_entity("hi", ()).unwrap()
+    }
+
+    #[test]
+    fn test_poll() {
+        let root = dir("poll");
+        fs::write(root.join("en/main.l20n"), "<hi 'Hi'>").unwrap();
+        let loader = Loader::new(&root, "{locale}/{file}.l20n").unwrap();
+        let locale = ReloadingLocale::new(loader, "en").unwrap();
+        assert_eq!(hi(&locale), "Hi");
+        assert!(locale.poll().is_empty());
+
+        let before = locale.locale();
+        fs::write(root.join("en/main.l20n"), "<hi 'Hello'>").unwrap();
+        assert!(locale.poll().is_empty());
+        assert_eq!(hi(&locale), "Hello");
+        assert_eq!(before.localize_entity("hi", ()).unwrap(), "Hi");
+
+        fs::write(root.join("en/main.l20n"), "<hi 'Hello>").unwrap();
+        match &locale.poll()[..] {
+            [LoadError::Parse(ref path, _)] => assert_eq!(*path, root.join("en/main.l20n")),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert_eq!(hi(&locale), "Hello");
+        assert!(locale.poll().is_empty());
+
+        fs::write(root.join("en/other.l20n"), "<bye 'Bye'>").unwrap();
+        assert!(locale.poll().is_empty());
+        assert_eq!(locale.locale().localize_entity("bye", ()).unwrap(), "Bye");
+        fs::remove_file(root.join("en/other.l20n")).unwrap();
+        assert!(locale.poll().is_empty());
+        assert!(locale.locale().localize_entity("bye", ()).is_err());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_base() {
+        let root = dir("base");
+        fs::write(root.join("en/main.l20n"), "<hi 'Hi'>").unwrap();
+        let loader = Loader::new(&root, "{locale}/{file}.l20n").unwrap();
+        let locale = ReloadingLocale::new(loader, "en").unwrap();
+        assert_eq!(locale.locale().language(), Some(&LanguageTag::parse("en").unwrap()));
+
+        let loader = Loader::new(&root, "{locale}/{file}.l20n").unwrap();
+        let mut base = Locale::new();
+        base.set_duplicate_policy(DuplicatePolicy::Error);
+        let locale = ReloadingLocale::with_base(loader, "en", base).unwrap();
+
+        // a duplicate is rejected, keeping the version before it
+        fs::write(root.join("en/other.l20n"), "<hi 'Hello'>\n<bye 'Bye'>").unwrap();
+        match &locale.poll()[..] {
+            [LoadError::Duplicate(ref path, ref dup)] => {
+                assert_eq!(*path, root.join("en/other.l20n"));
+                assert_eq!(dup.id, "hi");
+            }
+            other => panic!("expected a duplicate, got {:?}", other),
+        }
+        assert_eq!(hi(&locale), "Hi");
+        assert!(locale.locale().localize_entity("bye", ()).is_err());
+
+        fs::write(root.join("en/other.l20n"), "<bye 'Bye'>").unwrap();
+        assert!(locale.poll().is_empty());
+        assert_eq!(locale.locale().localize_entity("bye", ()).unwrap(), "Bye");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_watch() {
+        let root = dir("watch");
+        fs::write(root.join("en/main.l20n"), "<hi 'Hi'>").unwrap();
+        let loader = Loader::new(&root, "{locale}/{file}.l20n").unwrap();
+        let locale = Arc::new(ReloadingLocale::new(loader, "en").unwrap());
+        let (tx, rx) = mpsc::channel();
+        let handle = ReloadingLocale::watch(&locale, Duration::from_millis(5), move |e| {
+            let _ = tx.send(e);
+        });
+
+        fs::write(root.join("en/main.l20n"), "<hi>").unwrap();
+        match rx.recv_timeout(Duration::from_secs(10)) {
+            Ok(LoadError::Parse(..)) => {}
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        fs::write(root.join("en/main.l20n"), "<hi 'Hello'>").unwrap();
+        // the watcher may see the file half written, so wait for the end
+        for _ in 0..1000 {
+            if locale.locale().localize_entity("hi", ()).ok().as_ref().map(|s| &s[..]) == Some("Hello") {
+                break;
+            }
+            ::std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(hi(&locale), "Hello");
+
+        drop(locale);
+        handle.join().unwrap();
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,354 @@
+use std::fmt::Write;
+
+use ast::*;
+
+/// Writes entries out as L20n source, which parses back into the same
+/// entries.
+///
+/// Strings are written with `"`, keys of Hashes in sorted order, and
+/// expressions with parentheses only where they are needed. Comments are
+/// kept as they are, except that a `*/` in them is split into `* /`.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// let entries = l20n::parse("<hi   'Hello, {{$name}}'  title:'Hi'>").unwrap();
+/// let source = l20n::serialize(&entries);
+/// assert_eq!(source, "<hi \"Hello, {{ $name }}\" title: \"Hi\">\n");
+/// assert_eq!(l20n::parse(&source).unwrap(), entries);
+/// ```
+pub fn serialize(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    for entry in entries {
+        write_entry(&mut out, entry);
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes out a single expression, as it would be in a placeable.
+pub(crate) fn serialize_expr(expr: &Expr) -> String {
+    let mut out = String::new();
+    write_expr(&mut out, expr, 0);
+    out
+}
+
+fn write_entry(out: &mut String, entry: &Entry) {
+    match *entry {
+        Entity(ref id, ref value, ref indices, ref attrs) => {
+            out.push('<');
+            out.push_str(id);
+            write_indices(out, indices);
+            out.push(' ');
+            write_value(out, value);
+            for Attr(id, value, indices) in attrs {
+                out.push(' ');
+                out.push_str(id);
+                write_indices(out, indices);
+                out.push_str(": ");
+                write_value(out, value);
+            }
+            out.push('>');
+        }
+        Macro(ref id, ref args, ref body) => {
+            out.push('<');
+            out.push_str(id);
+            out.push('(');
+            write_list(out, args);
+            out.push_str(") { ");
+            write_expr(out, body, 0);
+            out.push_str(" }>");
+        }
+        Comment(ref text) => {
+            out.push_str("/*");
+            out.push_str(&text.replace("*/", "* /"));
+            out.push_str("*/");
+        }
+        Import(ref path) => {
+            let quote = if path.contains('"') { '\'' } else { '"' };
+            let _ = write!(out, "import({}{}{})", quote, path, quote);
+        }
+    }
+}
+
+fn write_indices(out: &mut String, indices: &[Expr]) {
+    if !indices.is_empty() {
+        out.push('[');
+        write_list(out, indices);
+        out.push(']');
+    }
+}
+
+fn write_list(out: &mut String, exprs: &[Expr]) {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_expr(out, expr, 0);
+    }
+}
+
+/// Writes out the contents of a string value without its quotes, which
+/// are left as they are in the text. `parser::parse_text` reads it back.
+pub(crate) fn serialize_text(value: &Value) -> String {
+    let mut out = String::new();
+    write_contents(&mut out, value, false);
+    out
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match *value {
+        Str(..) | ComplexStr(..) => {
+            out.push('"');
+            write_contents(out, value, true);
+            out.push('"');
+        }
+        Hash(ref map, ref default, _) => {
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                out.push_str(if i > 0 { ", " } else { " " });
+                if default.as_ref() == Some(key) {
+                    out.push('*');
+                }
+                out.push_str(key);
+                out.push_str(": ");
+                write_value(out, &map[key]);
+            }
+            out.push_str(" }");
+        }
+    }
+}
+
+fn write_contents(out: &mut String, value: &Value, quoted: bool) {
+    match *value {
+        Str(ref s) => write_text(out, s, false, quoted),
+        ComplexStr(ref pieces) => {
+            // text and placeables take turns, as the parser leaves them, so
+            // a literal string between two pieces of text is a placeable
+            let mut text_next = true;
+            for (i, piece) in pieces.iter().enumerate() {
+                match *piece {
+                    ValExpr(Str(ref s)) if text_next => {
+                        write_text(out, s, i + 1 < pieces.len(), quoted);
+                        text_next = false;
+                    }
+                    ref expr => {
+                        out.push_str("{{ ");
+                        write_expr(out, expr, 0);
+                        out.push_str(" }}");
+                        text_next = true;
+                    }
+                }
+            }
+        }
+        Hash(..) => write_value(out, value),
+    }
+}
+
+/// Escapes `\`, any `{` that would start a placeable, and `"` if the text
+/// is `quoted`.
+fn write_text(out: &mut String, text: &str, before_placeable: bool, quoted: bool) {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let opens = c == '{' && match chars.peek() {
+            Some(&next) => next == '{',
+            None => before_placeable,
+        };
+        if c == '"' && quoted || c == '\\' || opens {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
+
+/// How tightly an expression holds together: it is written in parentheses
+/// where something that binds tighter is needed.
+fn level(expr: &Expr) -> u8 {
+    match *expr {
+        // a static attribute takes a whole expression after the `::`, so
+        // anything written after it would be taken in as well
+        CondExpr(..) | AttrExpr(_, _, Static) => 0,
+        BinExpr(_, op, _) => precedence(op) + 1,
+        UnExpr(..) => 8,
+        PropExpr(..) | AttrExpr(..) | CallExpr(..) => 9,
+        NumExpr(n) if n < 0 => 8,
+        _ => 10,
+    }
+}
+
+fn precedence(op: BinOp) -> u8 {
+    match op {
+        BiOr => 0,
+        BiAnd => 1,
+        BiEq | BiNe => 2,
+        BiGe | BiGt | BiLe | BiLt => 3,
+        BiAdd | BiSub => 4,
+        BiRem => 5,
+        BiMul | BiDiv => 6,
+    }
+}
+
+fn write_expr(out: &mut String, expr: &Expr, min: u8) {
+    if level(expr) < min {
+        out.push('(');
+        write_expr(out, expr, 0);
+        out.push(')');
+        return;
+    }
+    match *expr {
+        CondExpr(ref cond, ref consequent, ref alternate) => {
+            write_expr(out, cond, 1);
+            out.push_str(" ? ");
+            write_expr(out, consequent, 0);
+            out.push_str(" : ");
+            write_expr(out, alternate, 0);
+        }
+        BinExpr(ref left, op, ref right) => {
+            let level = precedence(op) + 1;
+            write_expr(out, left, level);
+            let _ = write!(out, " {} ", bin_op(op));
+            write_expr(out, right, level + 1);
+        }
+        UnExpr(op, ref expr) => {
+            out.push(match op {
+                UnAdd => '+',
+                UnSub => '-',
+                UnNot => '!',
+            });
+            write_expr(out, expr, 8);
+        }
+        VarExpr(ref name) => {
+            out.push('$');
+            out.push_str(name);
+        }
+        ValExpr(ref value) => write_value(out, value),
+        PropExpr(ref parent, ref prop, access) => {
+            write_expr(out, parent, 9);
+            match (access, &**prop) {
+                (Static, IdentExpr(name)) => {
+                    out.push('.');
+                    out.push_str(name);
+                }
+                _ => {
+                    out.push('[');
+                    write_expr(out, prop, 0);
+                    out.push(']');
+                }
+            }
+        }
+        AttrExpr(ref parent, ref attr, access) => {
+            match **parent {
+                ParenExpr(..) | IdentExpr(..) | ThisExpr => write_expr(out, parent, 0),
+                ref parent => {
+                    out.push('(');
+                    write_expr(out, parent, 0);
+                    out.push(')');
+                }
+            }
+            out.push_str("::");
+            match access {
+                Static => write_expr(out, attr, 0),
+                Computed => {
+                    out.push('[');
+                    write_expr(out, attr, 0);
+                    out.push(']');
+                }
+            }
+        }
+        CallExpr(ref callee, ref args) => {
+            write_expr(out, callee, 9);
+            out.push('(');
+            write_list(out, args);
+            out.push(')');
+        }
+        IdentExpr(ref name) => out.push_str(name),
+        NumExpr(n) => {
+            let _ = write!(out, "{}", n);
+        }
+        ParenExpr(ref expr) => {
+            out.push('(');
+            write_expr(out, expr, 0);
+            out.push(')');
+        }
+        GlobalExpr(ref name) => {
+            out.push('@');
+            out.push_str(name);
+        }
+        ThisExpr => out.push('~'),
+    }
+}
+
+fn bin_op(op: BinOp) -> &'static str {
+    match op {
+        BiAdd => "+",
+        BiSub => "-",
+        BiMul => "*",
+        BiDiv => "/",
+        BiRem => "%",
+        BiAnd => "&&",
+        BiOr => "||",
+        BiEq => "==",
+        BiNe => "!=",
+        BiLt => "<",
+        BiLe => "<=",
+        BiGt => ">",
+        BiGe => ">=",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use ast::*;
+    use parser::{parse, parse_text};
+    use super::{serialize, serialize_text};
+
+    fn round_trip(source: &str) -> String {
+        let entries = parse(source).unwrap();
+        let serialized = serialize(&entries);
+        assert_eq!(parse(&serialized).unwrap(), entries, "{}", serialized);
+        serialized
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(round_trip("<hi 'Hi'>"), "<hi \"Hi\">\n");
+        assert_eq!(round_trip(r#"<q 'say "{{ $what }}"' path : 'C:\\dir\\'>"#),
+                   "<q \"say \\\"{{ $what }}\\\"\" path: \"C:\\\\dir\\\\\">\n");
+        assert_eq!(round_trip("<n[$n, @os] {*one: {a: 'x', *b: 'y'}, many: 'z'} title[$n]: {*x: 'X'}>"),
+                   "<n[$n, @os] { many: \"z\", *one: { a: \"x\", *b: \"y\" } } title[$n]: { *x: \"X\" }>\n");
+        assert_eq!(round_trip("/* a */ import('../brand.l20n')"),
+                   "/* a */\nimport(\"../brand.l20n\")\n");
+        assert_eq!(round_trip("<m($a, $b) { $a > 1 ? ($b || !$a) : (plural($a))::[$b] }>"),
+                   "<m($a, $b) { $a > 1 ? ($b || !$a) : (plural($a))::[$b] }>\n");
+
+        round_trip("<e '{{ (1 - (2 - 3)) * -(4 % 5) }}{{ (a.b[c])::d }}{{ (x::y) == 1 }}'>");
+        round_trip("<e '{{ ~::title }}{{ f(1, 'a{{ \"b\" }}', {k: 'v'}) }}{{ (a ? b : c) ? d : e }}'>");
+    }
+
+    #[test]
+    fn test_escapes() {
+        let s = |v: &'static str| Cow::Borrowed(v);
+        let text = |v: &'static str| ValExpr(Str(s(v)));
+        let entries = vec![
+            Entity(s("a"), Str(s("{{ not a placeable }} {x}")), vec![], vec![]),
+            Entity(s("b"), ComplexStr(vec![text("{"), VarExpr(s("x")), text("}")]), vec![], vec![]),
+            Comment(s("ends */ early")),
+        ];
+        let serialized = serialize(&entries);
+        assert_eq!(serialized, "<a \"\\{{ not a placeable }} {x}\">\n<b \"\\{{{ $x }}}\">\n/*ends * / early*/\n");
+        let mut parsed = parse(&serialized).unwrap();
+        assert_eq!(parsed.pop(), Some(Comment(s("ends * / early"))));
+        assert_eq!(parsed, &entries[..2]);
+    }
+
+    #[test]
+    fn test_text() {
+        let source = r#"say "{{ $what }}" \{{ not }}"#;
+        let value = parse_text(source).unwrap();
+        assert_eq!(serialize_text(&value), source);
+        assert!(parse_text("{{ $x").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use context::{Locale, ResourceError};
+use parser::ParseError;
+
+/// A `Locale` shared between threads, that can be updated while it is being
+/// read.
+///
+/// Readers take a `snapshot`, which stays as it was for as long as they hold
+/// it. Updates are made to a copy of the current version, without holding up
+/// readers, and then published all at once, so no reader ever sees an update
+/// half done.
+///
+/// ```rust
+/// # #![allow(deprecated)]
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// let shared = Arc::new(l20n::SharedLocale::default());
+/// shared.add_resource("<hi 'Hello'>").unwrap();
+///
+/// let reader = shared.clone();
+/// let t = thread::spawn(move || {
+///     let locale = reader.snapshot();
+///     let hi = locale.localize_entity("hi", ()).unwrap();
+///     assert!(hi == "Hello" || hi == "Hi");
+/// });
+///
+/// shared.update(|locale| {
+///     locale.add_resource("<hi 'Hi'>")?;
+///     locale.add_resource("<bye 'Bye'>")
+/// }).unwrap();
+/// t.join().unwrap();
+/// ```
+pub struct SharedLocale {
+    current: RwLock<Arc<Locale>>,
+    // updates wait for each other, so that none of them are lost
+    writer: Mutex<()>,
+}
+
+impl SharedLocale {
+    /// Shares a Locale.
+    pub fn new(locale: Locale) -> SharedLocale {
+        SharedLocale {
+            current: RwLock::new(Arc::new(locale)),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// The current version.
+    pub fn snapshot(&self) -> Arc<Locale> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Makes a new version by calling `f` on a copy of the current one, and
+    /// publishes it if `f` succeeds. Nothing is published if it fails.
+    pub fn update<F, E>(&self, f: F) -> Result<(), E>
+        where F: FnOnce(&mut Locale) -> Result<(), E>
+    {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let mut next = (*self.snapshot()).clone();
+        f(&mut next)?;
+        *self.current.write().unwrap() = Arc::new(next);
+        Ok(())
+    }
+
+    /// Adds a resource and publishes the result, as `Locale::add_resource`
+    /// does.
+    pub fn add_resource(&self, res: &str) -> Result<(), ParseError> {
+        self.update(|locale| locale.add_resource(res))
+    }
+
+    /// Adds a resource and publishes the result, as
+    /// `Locale::try_add_resource` does.
+    pub fn try_add_resource(&self, res: &str) -> Result<(), ResourceError> {
+        self.update(|locale| locale.try_add_resource(res))
+    }
+
+    /// Replaces the current version with a Locale made elsewhere.
+    pub fn publish(&self, locale: Locale) {
+        let _writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        *self.current.write().unwrap() = Arc::new(locale);
+    }
+}
+
+impl Default for SharedLocale {
+    fn default() -> SharedLocale {
+        SharedLocale::new(Locale::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use context::Locale;
+    use super::SharedLocale;
+
+    fn send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        send_sync::<Locale>();
+        send_sync::<SharedLocale>();
+    }
+
+    #[test]
+    fn test_update() {
+        let shared = SharedLocale::default();
+        shared.add_resource("<hi 'Hello'>").unwrap();
+        let before = shared.snapshot();
+
+        shared.update(|locale| {
+            locale.add_resource("<hi 'Hi'>")?;
+            locale.add_resource("<bye 'Bye'>")
+        }).unwrap();
+        assert_eq!(before.localize_entity("hi", ()).unwrap(), "Hello");
+        assert!(before.localize_entity("bye", ()).is_err());
+        assert_eq!(shared.snapshot().localize_entity("hi", ()).unwrap(), "Hi");
+
+        // a failed update is not published, not even in part
+        assert!(shared.update(|locale| {
+            locale.add_resource("<later 'Later'>")?;
+            locale.add_resource("<broken")
+        }).is_err());
+        assert!(shared.snapshot().localize_entity("later", ()).is_err());
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let shared = Arc::new(SharedLocale::default());
+        shared.add_resource("<a '0'>\n<b '0'>").unwrap();
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || for _ in 0..200 {
+                let locale = shared.snapshot();
+                let a = locale.localize_entity("a", ()).unwrap();
+                let b = locale.localize_entity("b", ()).unwrap();
+                assert_eq!(a, b);
+            })
+        }).collect();
+        let writers: Vec<_> = (0..4).map(|i| {
+            let shared = shared.clone();
+            thread::spawn(move || for j in 0..25 {
+                let n = i * 100 + j;
+                shared.add_resource(&format!("<a '{}'>\n<b '{}'>\n<w{}_{} ''>", n, n, i, j)).unwrap();
+            })
+        }).collect();
+        for t in readers.into_iter().chain(writers) {
+            t.join().unwrap();
+        }
+
+        // every update was kept
+        let locale = shared.snapshot();
+        for i in 0..4 {
+            for j in 0..25 {
+                assert!(locale.localize_entity(&format!("w{}_{}", i, j), ()).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Traversal of the syntax tree in `ast`.
+//!
+//! `Visit` walks a tree by reference and `VisitMut` walks it mutably. Each
+//! method has a default that continues into the node's children through the
+//! matching `walk_*` function, so an implementation only overrides the
+//! nodes it cares about, and calls the `walk_*` function itself if it still
+//! wants the children visited.
+//!
+//! The names of static properties and attributes (`foo.bar`, `foo::bar`) are
+//! part of their `PropExpr` or `AttrExpr`, and are not visited as
+//! `IdentExpr`s, since they do not refer to other entries.
+//!
+//! # Example
+//!
+//! ```rust
+//! use l20n::visit::{self, Visit};
+//!
+//! struct Vars(Vec<String>);
+//!
+//! impl<'ast> Visit<'ast> for Vars {
+//!     fn visit_var(&mut self, name: &'ast str) {
+//!         self.0.push(name.to_string());
+//!     }
+//! }
+//!
+//! let entries = l20n::parse("<hi 'Hello, {{ $first }} {{ $last }}'>").unwrap();
+//! let mut vars = Vars(vec![]);
+//! for entry in &entries {
+//!     vars.visit_entry(entry);
+//! }
+//! assert_eq!(vars.0, vec!["first", "last"]);
+//! ```
+
+use std::borrow::Cow;
+
+use ast::{Entry, Value, Expr, Attr};
+use ast;
+
+/// Walks the syntax tree by reference.
+pub trait Visit<'ast> {
+    /// Visits an entry, and then its value, indices and attributes, or its
+    /// arguments and body.
+    fn visit_entry(&mut self, entry: &'ast Entry) {
+        walk_entry(self, entry)
+    }
+
+    /// Visits an attribute, and then its indices and value.
+    fn visit_attr(&mut self, attr: &'ast Attr) {
+        walk_attr(self, attr)
+    }
+
+    /// Visits a value, and then the pieces of a string or the values and
+    /// index of a Hash.
+    fn visit_value(&mut self, value: &'ast Value) {
+        walk_value(self, value)
+    }
+
+    /// Visits an expression, and then its sub-expressions.
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr)
+    }
+
+    /// Visits the literal text of a `Str`.
+    fn visit_str(&mut self, _s: &'ast str) {}
+
+    /// Visits the name of a `VarExpr`.
+    fn visit_var(&mut self, _name: &'ast str) {}
+
+    /// Visits the name of an `IdentExpr`.
+    fn visit_ident(&mut self, _name: &'ast str) {}
+
+    /// Visits the name of a `GlobalExpr`.
+    fn visit_global(&mut self, _name: &'ast str) {}
+}
+
+/// Visits the children of an entry.
+pub fn walk_entry<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, entry: &'ast Entry) {
+    match *entry {
+        ast::Entity(_, ref value, ref indices, ref attrs) => {
+            for index in indices {
+                v.visit_expr(index);
+            }
+            v.visit_value(value);
+            for attr in attrs {
+                v.visit_attr(attr);
+            }
+        }
+        ast::Macro(_, ref args, ref body) => {
+            for arg in args {
+                v.visit_expr(arg);
+            }
+            v.visit_expr(body);
+        }
+        ast::Comment(..) | ast::Import(..) => {}
+    }
+}
+
+/// Visits the children of an attribute.
+pub fn walk_attr<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, attr: &'ast Attr) {
+    for index in &attr.2 {
+        v.visit_expr(index);
+    }
+    v.visit_value(&attr.1);
+}
+
+/// Visits the children of a value.
+pub fn walk_value<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, value: &'ast Value) {
+    match *value {
+        ast::Str(ref s) => v.visit_str(s),
+        ast::ComplexStr(ref exprs) => {
+            for expr in exprs {
+                v.visit_expr(expr);
+            }
+        }
+        ast::Hash(ref map, _, ref index) => {
+            for value in map.values() {
+                v.visit_value(value);
+            }
+            if let Some(ref index) = *index {
+                v.visit_expr(index);
+            }
+        }
+    }
+}
+
+/// Visits the children of an expression.
+pub fn walk_expr<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, expr: &'ast Expr) {
+    match *expr {
+        ast::CondExpr(ref cond, ref consequent, ref alt) => {
+            v.visit_expr(cond);
+            v.visit_expr(consequent);
+            v.visit_expr(alt);
+        }
+        ast::BinExpr(ref left, _, ref right) => {
+            v.visit_expr(left);
+            v.visit_expr(right);
+        }
+        ast::UnExpr(_, ref expr) | ast::ParenExpr(ref expr) => v.visit_expr(expr),
+        ast::VarExpr(ref name) => v.visit_var(name),
+        ast::ValExpr(ref value) => v.visit_value(value),
+        ast::PropExpr(ref parent, ref prop, access) |
+        ast::AttrExpr(ref parent, ref prop, access) => {
+            v.visit_expr(parent);
+            if access == ast::Computed {
+                v.visit_expr(prop);
+            }
+        }
+        ast::CallExpr(ref callee, ref args) => {
+            v.visit_expr(callee);
+            for arg in args {
+                v.visit_expr(arg);
+            }
+        }
+        ast::IdentExpr(ref name) => v.visit_ident(name),
+        ast::GlobalExpr(ref name) => v.visit_global(name),
+        ast::NumExpr(..) | ast::ThisExpr => {}
+    }
+}
+
+/// Walks the syntax tree mutably, to rewrite it in place.
+pub trait VisitMut {
+    /// Visits an entry, and then its value, indices and attributes, or its
+    /// arguments and body.
+    fn visit_entry_mut(&mut self, entry: &mut Entry) {
+        walk_entry_mut(self, entry)
+    }
+
+    /// Visits an attribute, and then its indices and value.
+    fn visit_attr_mut(&mut self, attr: &mut Attr) {
+        walk_attr_mut(self, attr)
+    }
+
+    /// Visits a value, and then the pieces of a string or the values and
+    /// index of a Hash.
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        walk_value_mut(self, value)
+    }
+
+    /// Visits an expression, and then its sub-expressions.
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+
+    /// Visits the literal text of a `Str`.
+    fn visit_str_mut(&mut self, _s: &mut Cow<str>) {}
+
+    /// Visits the name of a `VarExpr`.
+    fn visit_var_mut(&mut self, _name: &mut Cow<str>) {}
+
+    /// Visits the name of an `IdentExpr`.
+    fn visit_ident_mut(&mut self, _name: &mut Cow<str>) {}
+
+    /// Visits the name of a `GlobalExpr`.
+    fn visit_global_mut(&mut self, _name: &mut Cow<str>) {}
+}
+
+/// Visits the children of an entry mutably.
+pub fn walk_entry_mut<V: VisitMut + ?Sized>(v: &mut V, entry: &mut Entry) {
+    match *entry {
+        ast::Entity(_, ref mut value, ref mut indices, ref mut attrs) => {
+            for index in indices {
+                v.visit_expr_mut(index);
+            }
+            v.visit_value_mut(value);
+            for attr in attrs {
+                v.visit_attr_mut(attr);
+            }
+        }
+        ast::Macro(_, ref mut args, ref mut body) => {
+            for arg in args {
+                v.visit_expr_mut(arg);
+            }
+            v.visit_expr_mut(body);
+        }
+        ast::Comment(..) | ast::Import(..) => {}
+    }
+}
+
+/// Visits the children of an attribute mutably.
+pub fn walk_attr_mut<V: VisitMut + ?Sized>(v: &mut V, attr: &mut Attr) {
+    for index in &mut attr.2 {
+        v.visit_expr_mut(index);
+    }
+    v.visit_value_mut(&mut attr.1);
+}
+
+/// Visits the children of a value mutably.
+pub fn walk_value_mut<V: VisitMut + ?Sized>(v: &mut V, value: &mut Value) {
+    match *value {
+        ast::Str(ref mut s) => v.visit_str_mut(s),
+        ast::ComplexStr(ref mut exprs) => {
+            for expr in exprs {
+                v.visit_expr_mut(expr);
+            }
+        }
+        ast::Hash(ref mut map, _, ref mut index) => {
+            for value in map.values_mut() {
+                v.visit_value_mut(value);
+            }
+            if let Some(ref mut index) = *index {
+                v.visit_expr_mut(index);
+            }
+        }
+    }
+}
+
+/// Visits the children of an expression mutably.
+pub fn walk_expr_mut<V: VisitMut + ?Sized>(v: &mut V, expr: &mut Expr) {
+    match *expr {
+        ast::CondExpr(ref mut cond, ref mut consequent, ref mut alt) => {
+            v.visit_expr_mut(cond);
+            v.visit_expr_mut(consequent);
+            v.visit_expr_mut(alt);
+        }
+        ast::BinExpr(ref mut left, _, ref mut right) => {
+            v.visit_expr_mut(left);
+            v.visit_expr_mut(right);
+        }
+        ast::UnExpr(_, ref mut expr) | ast::ParenExpr(ref mut expr) => v.visit_expr_mut(expr),
+        ast::VarExpr(ref mut name) => v.visit_var_mut(name),
+        ast::ValExpr(ref mut value) => v.visit_value_mut(value),
+        ast::PropExpr(ref mut parent, ref mut prop, access) |
+        ast::AttrExpr(ref mut parent, ref mut prop, access) => {
+            v.visit_expr_mut(parent);
+            if access == ast::Computed {
+                v.visit_expr_mut(prop);
+            }
+        }
+        ast::CallExpr(ref mut callee, ref mut args) => {
+            v.visit_expr_mut(callee);
+            for arg in args {
+                v.visit_expr_mut(arg);
+            }
+        }
+        ast::IdentExpr(ref mut name) => v.visit_ident_mut(name),
+        ast::GlobalExpr(ref mut name) => v.visit_global_mut(name),
+        ast::NumExpr(..) | ast::ThisExpr => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{Visit, VisitMut, walk_entry_mut};
+    use ast::{Entry, Entity};
+    use parser::parse;
+
+    struct Refs(Vec<String>);
+
+    impl<'ast> Visit<'ast> for Refs {
+        fn visit_ident(&mut self, name: &'ast str) {
+            self.0.push(name.to_string());
+        }
+    }
+
+    struct Rename(&'static str, &'static str);
+
+    impl VisitMut for Rename {
+        fn visit_entry_mut(&mut self, entry: &mut Entry) {
+            if let Entity(ref mut id, ..) = *entry {
+                if *id == self.0 {
+                    *id = self.1.into();
+                }
+            }
+            walk_entry_mut(self, entry);
+        }
+
+        fn visit_ident_mut(&mut self, name: &mut Cow<str>) {
+            if *name == self.0 {
+                *name = self.1.into();
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit_refs() {
+        let entries = parse(r#"
+        <brand 'Rust' long: 'Rust Lang'>
+        <hi['a'] { a: '{{ brand::long }}', b: '{{ fac(brand.x) }}' }>
+        "#).unwrap();
+        let mut refs = Refs(vec![]);
+        for entry in &entries {
+            refs.visit_entry(entry);
+        }
+        refs.0.sort();
+        assert_eq!(refs.0, vec!["brand", "brand", "fac"]);
+    }
+
+    #[test]
+    fn test_visit_mut_rename() {
+        let mut entries = parse("<brand 'Rust'>\n<hi 'Hello, {{ brand }}!'>").unwrap();
+        for entry in &mut entries {
+            Rename("brand", "product").visit_entry_mut(entry);
+        }
+        assert_eq!(entries, parse("<product 'Rust'>\n<hi 'Hello, {{ product }}!'>").unwrap());
+    }
+}